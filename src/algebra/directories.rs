@@ -1,6 +1,15 @@
 use crate::models::HopEffect;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub trait Directories {
     fn dir_exists(&self, dir_path: &Path) -> HopEffect<bool>;
+
+    /// The root of the git working tree that holds `dir_path`, if any. The root of a worktree or submodule is the
+    /// directory with the `.git` file, rather than the repository it belongs to.
+    fn git_root(&self, dir_path: &Path) -> HopEffect<Option<PathBuf>>;
+
+    /// The roots of git working trees, and directories holding any of `project_files`, in `dir_path` and up to
+    /// `depth` levels of directories below it. Hidden directories and directories inside a found directory are not
+    /// searched.
+    fn find_projects(&self, dir_path: &Path, depth: usize, project_files: &[&str]) -> HopEffect<Vec<PathBuf>>;
 }
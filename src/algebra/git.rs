@@ -0,0 +1,22 @@
+use crate::models::{GitConflict, HopEffect, SyncPreference};
+use std::path::Path;
+
+/// Keeps the history of a hop home in a git repository.
+pub trait Git {
+    /// Makes `dir` a git repository if it isn't one already.
+    fn init_repo(&self, dir: &Path) -> HopEffect<()>;
+
+    /// Commits every change in `dir`. Does nothing when nothing has changed.
+    fn commit_all(&self, dir: &Path, message: &str) -> HopEffect<()>;
+
+    /// Points the `origin` remote of `dir` at `url`, adding it if needed.
+    fn set_remote(&self, dir: &Path, url: &str) -> HopEffect<()>;
+
+    fn has_remote(&self, dir: &Path) -> HopEffect<bool>;
+
+    /// Merges `origin` into `dir`. Links that were changed on both sides are resolved with `prefer`. Without a
+    /// preference the merge is undone and the conflicting links are returned.
+    fn pull(&self, dir: &Path, prefer: Option<SyncPreference>) -> HopEffect<Vec<GitConflict>>;
+
+    fn push(&self, dir: &Path) -> HopEffect<()>;
+}
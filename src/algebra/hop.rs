@@ -1,85 +1,287 @@
-use crate::models::{HopEffect, Link, LinkPair, HomeType};
-use crate::program::io_error;
+use crate::config::{Config, MatchMode, SortOrder, TargetPaths};
+use crate::formats::{self, FrecencyTool};
+use crate::models::{
+    BatchOp, BatchOutcome, ConflictPolicy, DirVisit, GitSyncStatus, HomeLayer, HomeMigration, HomeType, HopEffect,
+    ImportAction, ImportOptions, JournalEntry, LayeredLinkPair, Link, LinkChange, LinkDiff, LinkPair, LinkTarget,
+    PromptMode, ScanOptions, SyncChange, SyncPlan, SyncPreference, Snapshot, TrashedLink,
+};
+use std::cmp::Ordering;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use crate::program::{io_error, io_error_ex, io_error_ex_nested, pluralise};
 
 use super::{
-    directories::Directories, std_io::StdIO, symlinks::SymLink, symlinks::SymLinks,
-    user_dirs::UserDirs,
+    directories::Directories, git::Git, journal::Journal, locks::Locks, snapshots::Snapshots, std_io::StdIO,
+    symlinks::SymLink, symlinks::SymLinks, trash::Trash, user_dirs::UserDirs,
 };
 
+/// The files that mark a directory as a project for `hop scan`, when it isn't a git repository.
+const PROJECT_FILES: [&str; 2] = ["Cargo.toml", "package.json"];
+
 /// The data required to run hop
 pub struct HopProgram<T> {
     pub value: T,
-    pub hop_home_dir: HomeType,
+    /// The hop homes to read links from, from highest to lowest precedence. A link hides any links with the same
+    /// name in later homes. Links are only written to the first writable home.
+    pub hop_homes: Vec<HomeLayer>,
+    pub config: Config,
+    /// Whether questions are asked or answered without reading stdin.
+    pub prompts: PromptMode,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum DeleteStatus {
     DeleteAborted,
-    DeleteSucceeded(LinkPair),
+    DeleteCompleted(Vec<DeleteOutcome>),
+}
+
+/// Whether a link matched for deletion was deleted. Each link is deleted on its own, so one failing doesn't stop the
+/// rest.
+#[derive(Debug, PartialEq)]
+pub enum DeleteOutcome {
+    Deleted(LinkPair),
+    Failed(LinkPair, String),
 }
 
 impl<T> HopProgram<T>
 where
-    T: UserDirs + StdIO + SymLinks + Directories,
+    T: UserDirs + StdIO + SymLinks + Directories + Git + Journal + Trash + Snapshots + Locks,
 {
 
+    /// The hop home that links are written to.
+    pub fn hop_home(&self) -> HopEffect<PathBuf> {
+        let writable_layer =
+            self.hop_homes
+            .iter()
+            .find(|layer| layer.writable)
+            .ok_or_else(|| io_error("There is no writable hop home."))?;
+
+        self.value.get_hop_home(&writable_layer.home_type)
+    }
+
     pub fn list_links(&self) -> HopEffect<Vec<LinkPair>> {
-        self.get_link_pairs()
+        let entries = self.get_sorted_link_pairs()?;
+        Ok(entries.into_iter().map(|lp| lp.link_pair).collect())
+    }
+
+    pub fn tabulate_links(&self) -> HopEffect<Vec<LayeredLinkPair>> {
+        self.get_sorted_link_pairs()
     }
 
-    pub fn tabulate_links(&self) -> HopEffect<Vec<LinkPair>> {
-        self.get_link_pairs()
+    /// The target written to the link, which is relative when the hop home is shared between machines. Relative
+    /// targets are resolved again when links are read.
+    fn stored_target(&self, hop_home: &Path, target: &Path) -> HopEffect<PathBuf> {
+        match self.config.targets {
+            TargetPaths::Absolute => Ok(target.to_path_buf()),
+            TargetPaths::Home => {
+                let home = self.value.home_dir()?;
+                match target.strip_prefix(&home) {
+                    Ok(home_relative) => Ok(Path::new("~").join(home_relative)),
+                    Err(_) => Ok(target.to_path_buf()),
+                }
+            },
+            TargetPaths::HopHome => Ok(relative_path(hop_home, target)),
+        }
     }
 
-    pub fn jump_target(&self, link: Link) -> HopEffect<String> {
+    /// Whether links are read from more than one hop home.
+    pub fn is_layered(&self) -> bool {
+        self.hop_homes.len() > 1
+    }
+
+    pub fn jump_target(&self, link: Link) -> HopEffect<LinkTarget> {
         let entries = self.get_link_pairs()?;
-        match entries.iter().find(|&lp| lp.link == link) {
-            Some(found_lp) => Ok(format!("{}", found_lp.target)),
-            None => Err(io_error(&format!("Could not find link: {}", link))),
+
+        if let Some(found_lp) = entries.iter().find(|lp| lp.link == link) {
+            return Ok(found_lp.target.clone());
+        }
+
+        match self.config.match_mode {
+            MatchMode::Exact => Err(io_error(&format!("Could not find link: {}", link))),
+            MatchMode::Prefix => {
+                let prefix = link.0.as_bytes();
+                let mut matches: Vec<LinkPair> =
+                    entries
+                    .into_iter()
+                    .filter(|lp| lp.link.0.as_bytes().starts_with(prefix))
+                    .collect();
+
+                match matches.len() {
+                    0 => Err(io_error(&format!("Could not find link: {}", link))),
+                    1 => Ok(matches.remove(0).target),
+                    _ => {
+                        let names: Vec<String> = matches.iter().map(|lp| lp.link.to_string()).collect();
+                        Err(io_error(&format!("`{}` matches more than one link: {}", link, names.join(", "))))
+                    }
+                }
+            }
         }
     }
 
+    fn get_sorted_link_pairs(&self) -> HopEffect<Vec<LayeredLinkPair>> {
+        let mut entries = self.get_layered_link_pairs()?;
+
+        match self.config.sort {
+            SortOrder::Name => entries.sort_by(|a, b| a.link_pair.link.0.cmp(&b.link_pair.link.0)),
+            SortOrder::Target => entries.sort_by(|a, b| a.link_pair.target.0.cmp(&b.link_pair.target.0)),
+            SortOrder::None => (),
+        }
+
+        Ok(entries)
+    }
+
     fn get_link_pairs(&self) -> HopEffect<Vec<LinkPair>> {
-        let hop_home_dir = self.value.get_hop_home(&self.hop_home_dir)?;
-        let entries = self.value.read_dir_links(&hop_home_dir)?;
+        let entries = self.get_layered_link_pairs()?;
+        Ok(entries.into_iter().map(|lp| lp.link_pair).collect())
+    }
+
+    /// Reads the links in every hop home. A link hides any links with the same name in lower precedence homes.
+    fn get_layered_link_pairs(&self) -> HopEffect<Vec<LayeredLinkPair>> {
+        let mut entries: Vec<LayeredLinkPair> = vec![];
+
+        for layer in &self.hop_homes {
+            if let Some(hop_home_dir) = self.layer_home(layer)? {
+                for link_pair in self.value.read_dir_links(&hop_home_dir)? {
+                    if !entries.iter().any(|lp| lp.link_pair.link == link_pair.link) {
+                        let base_dir = layer.relative_to.as_deref().unwrap_or(&hop_home_dir);
+                        entries.push(LayeredLinkPair::new(resolve_target(base_dir, link_pair), &layer.name))
+                    }
+                }
+            }
+        }
 
-        Ok(entries.to_vec())
+        Ok(entries)
     }
 
-    pub fn mark_dir(&self, pair: &LinkPair) -> HopEffect<String> {
-        let hop_home = self.value.get_hop_home(&self.hop_home_dir)?;
-        let symlink_path = (hop_home).join(&pair.link);
+    /// The links in a single hop home with their targets resolved.
+    pub fn hop_home_links(&self, hop_home: &Path) -> HopEffect<Vec<LinkPair>> {
+        let links =
+            self.value
+            .read_dir_links(hop_home)?
+            .into_iter()
+            .map(|lp| resolve_target(hop_home, lp))
+            .collect();
 
-        let target_path = pair.target.to_path_buf();
+        Ok(links)
+    }
 
-        let resolved_target_path =
-          // Check if the path is relative and add the current directory to it
-          if target_path.is_relative() {
-            let current_dir = std::env::current_dir()?;
-            if pair.target.as_ref() == std::path::Path::new(".") {
-              // If the user entered ".", then just use the current directory
-              current_dir
-            } else {
-              // Otherwise append the current directory to the relative path
-              current_dir.join(&target_path)
+    /// The directory of a hop home. Writable hop homes are created if they don't exist. Read-only hop homes that
+    /// don't exist, such as a network share that is not mounted, are skipped.
+    fn layer_home(&self, layer: &HomeLayer) -> HopEffect<Option<PathBuf>> {
+        match &layer.home_type {
+            HomeType::Absolute(path) if !layer.writable => {
+                if self.value.dir_exists(path)? {
+                    Ok(Some(path.clone()))
+                } else {
+                    Ok(None)
+                }
+            },
+            home_type => self.value.get_hop_home(home_type).map(Some),
+        }
+    }
+
+    pub fn mark_dir(&self, pair: &LinkPair) -> HopEffect<PathBuf> {
+        let _lock = self.lock_home()?;
+        self.mark_and_record(pair)
+    }
+
+    /// Marks `pair` unless a link with its exact name is already in the hop home that links are written to. Links
+    /// in other hop homes, or that only start with the name, don't count. Returns `None` if nothing was marked.
+    pub fn mark_dir_if_missing(&self, pair: &LinkPair) -> HopEffect<Option<PathBuf>> {
+        let _lock = self.lock_home()?;
+        let hop_home = self.hop_home()?;
+
+        if self.value.read_dir_links(&hop_home)?.iter().any(|lp| lp.link == pair.link) {
+            Ok(None)
+        } else {
+            self.mark_and_record(pair).map(Some)
+        }
+    }
+
+    fn mark_and_record(&self, pair: &LinkPair) -> HopEffect<PathBuf> {
+        let target = self.mark(pair, false)?;
+        let marked_pair = LinkPair { link: pair.link.clone(), target: LinkTarget(target.clone()) };
+        self.record_change(&format!("Mark {}", marked_pair), vec![LinkChange::added(&marked_pair)])?;
+
+        Ok(target)
+    }
+
+    /// Marks all of `pairs` under one lock and records them as a single change. If any of them can't be marked, the
+    /// ones marked before it are removed again. Returns the pairs with their targets resolved.
+    pub fn mark_links(&self, pairs: &[LinkPair]) -> HopEffect<Vec<LinkPair>> {
+        let _lock = self.lock_home()?;
+        let hop_home = self.hop_home()?;
+        let resolved_pairs = pairs.iter().map(resolved_pair).collect::<HopEffect<Vec<LinkPair>>>()?;
+
+        for pair in &resolved_pairs {
+            if !self.value.dir_exists(&pair.target.0)? {
+                return Err(io_error(&format!(
+                    "A directory named `{}` does not exist or you do not have permission to it.",
+                    &pair.target
+                )))
             }
-          } else {
-            target_path
-          };
+        }
+
+        let changes: Vec<LinkChange> = resolved_pairs.iter().map(LinkChange::added).collect();
+        self.apply_all_changes(&hop_home, &changes, "scan")?;
+        self.record_change(&format!("Mark {}", pluralise(changes.len(), "link")), changes)?;
+
+        Ok(resolved_pairs)
+    }
+
+    /// The link to mark when the name or target may be left out. The target defaults to the working directory and the
+    /// name to the last part of the target, made to follow the naming rules and given a numbered suffix if a link
+    /// already has it. With `git_root`, the root of the git repository holding the target is marked instead.
+    pub fn infer_link_pair(&self, link: Option<Link>, target: Option<LinkTarget>, git_root: bool) -> HopEffect<LinkPair> {
+        let mut target = target.unwrap_or_else(|| LinkTarget::new("."));
+
+        if git_root {
+            let target_path = normalise(&resolve_mark_target(&target)?);
+
+            let root =
+                self.value
+                .git_root(&target_path)?
+                .ok_or_else(|| io_error(&format!("`{}` is not in a git repository.", target_path.to_string_lossy())))?;
+
+            target = LinkTarget(root)
+        }
+
+        match link {
+            Some(link) => Ok(LinkPair { link, target }),
+            None => {
+                let target_path = normalise(&resolve_mark_target(&target)?);
+
+                let name =
+                    target_path
+                    .file_name()
+                    .and_then(|name| formats::sanitise_name(&name.to_string_lossy()))
+                    .ok_or_else(|| io_error(&format!(
+                        "A link can't be named after `{}`. Please give a name.",
+                        target_path.to_string_lossy()
+                    )))?;
+
+                let link_pairs = self.get_link_pairs()?;
+                Ok(LinkPair::new(formats::unused_name(&name, &link_pairs)?, target_path))
+            },
+        }
+    }
+
+    /// Writes a link to the hop home. The target must be an existing directory unless `allow_missing` is set.
+    fn mark(&self, pair: &LinkPair, allow_missing: bool) -> HopEffect<PathBuf> {
+        let hop_home = self.hop_home()?;
+        let symlink_path = hop_home.join(&pair.link);
+
+        let resolved_target_path = resolve_mark_target(&pair.target)?;
 
         //TODO: Send in a LinkTarget
-        if self.value.dir_exists(&resolved_target_path)? {
+        if allow_missing || self.value.dir_exists(&resolved_target_path)? {
             //TODO: Send in a SymLink
-            if self.value.link_exists(&symlink_path)? {
-                Err(io_error(&format!(
-                    "A link named `{}` already exists. Aborting mark creation.",
-                    pair.link
-                )))
-            } else {
-                self.value
-                    .write_link(&SymLink(symlink_path), &resolved_target_path)
-                    .map(|_| resolved_target_path.to_string_lossy().to_string())
-            }
+            let stored_target_path = self.stored_target(&hop_home, &resolved_target_path)?;
+
+            self.write_new_link(&pair.link, SymLink(symlink_path), &stored_target_path, "mark creation")
+                .map(|_| resolved_target_path)
         } else {
             Err(io_error(&format!(
                 "A directory named `{}` does not exist or you do not have permission to it.",
@@ -88,52 +290,1236 @@ where
         }
     }
 
-    pub fn delete_link(&self, link: &Link) -> HopEffect<DeleteStatus> {
-        let link_pairs = self.get_link_pairs()?;
+    /// Writing the link is the check for whether it exists, so that a link written by something else since the hop
+    /// home was read is never replaced.
+    fn write_new_link(&self, link: &Link, symlink: SymLink, target: &Path, action: &str) -> HopEffect<()> {
+        self.value
+            .write_link(&symlink, target)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => io_error(&format!("A link named `{}` already exists. Aborting {}.", link, action)),
+                _ => e,
+            })
+    }
+
+    /// Waits for other hops to finish changing the hop home. The lock is released when the returned value is dropped.
+    fn lock_home(&self) -> HopEffect<T::Lock> {
+        let hop_home = self.hop_home()?;
+        self.value.lock_home(&hop_home, Duration::from_secs(self.config.lock_timeout))
+    }
+
+    /// Compares the links in the hop home with `other`. A link with a different name but the same target as a link
+    /// on the other side is reported as a different name rather than as a link on only one side.
+    pub fn diff_links(&self, other: Vec<LinkPair>) -> HopEffect<LinkDiff> {
+        let hop_home = self.hop_home()?;
+        let here = self.hop_home_links(&hop_home)?;
+
+        let mut diff = LinkDiff {
+            only_here: vec![],
+            only_there: vec![],
+            different_targets: vec![],
+            different_names: vec![],
+        };
+
+        let mut unmatched_here: Vec<LinkPair> = vec![];
+
+        for here_lp in &here {
+            match other.iter().find(|lp| lp.link == here_lp.link) {
+                Some(there_lp) if there_lp.target == here_lp.target => (),
+                Some(there_lp) => diff.different_targets.push((here_lp.clone(), there_lp.clone())),
+                None => unmatched_here.push(here_lp.clone()),
+            }
+        }
+
+        let mut unmatched_there: Vec<LinkPair> =
+            other
+            .into_iter()
+            .filter(|there_lp| !here.iter().any(|lp| lp.link == there_lp.link))
+            .collect();
+
+        for here_lp in unmatched_here {
+            match unmatched_there.iter().position(|lp| lp.target == here_lp.target) {
+                Some(index) => diff.different_names.push((here_lp, unmatched_there.remove(index))),
+                None => diff.only_here.push(here_lp),
+            }
+        }
+
+        diff.only_there = unmatched_there;
+
+        Ok(diff)
+    }
+
+    /// Brings the links from `other` into the hop home. Links only in `other` are added and the user chooses which
+    /// side wins for each link that differs. Links only in the hop home are kept.
+    pub fn merge_links(&self, other: Vec<LinkPair>, other_name: &str) -> HopEffect<Vec<ImportAction>> {
+        let diff = self.diff_links(other)?;
+
+        let mut actions: Vec<ImportAction> = vec![];
+        let mut changes: Vec<LinkChange> = vec![];
+
+        for pair in diff.only_there {
+            changes.push(LinkChange::added(&resolved_pair(&pair)?));
+            actions.push(ImportAction::Create(pair))
+        }
+
+        for (here_lp, there_lp) in diff.different_targets {
+            let message = format!(
+                "`{}` links to {} here and to {} in {}. Use the target from {}?",
+                here_lp.link, here_lp.target, there_lp.target, other_name, other_name
+            );
+
+            let action = self.prompt_user(
+                &message,
+                || {
+                    changes.push(LinkChange::retargeted(&here_lp, &resolved_pair(&there_lp)?.target));
+                    Ok(ImportAction::Update(there_lp.clone()))
+                },
+                || Ok(ImportAction::Skip(here_lp.clone()))
+            )?;
+
+            actions.push(action)
+        }
 
-        match link_pairs.iter().find(|lp| &lp.link == link) {
-            Some(pair) => {
-                let prompt_message = format!(
-                    "Are you sure you want to delete {} which links to {} ?",
-                    pair.link, pair.target
-                );
+        for (here_lp, there_lp) in diff.different_names {
+            let message = format!(
+                "{} is marked as `{}` here and as `{}` in {}. Use the name from {}?",
+                here_lp.target, here_lp.link, there_lp.link, other_name, other_name
+            );
 
-                let no_action = || Ok(DeleteStatus::DeleteAborted);
+            let action = self.prompt_user(
+                &message,
+                || {
+                    let renamed_pair = LinkPair { link: there_lp.link.clone(), target: here_lp.target.clone() };
+
+                    changes.push(LinkChange::added(&renamed_pair));
+                    changes.push(LinkChange::removed(&here_lp));
+                    Ok(ImportAction::Rename(here_lp.link.clone(), renamed_pair))
+                },
+                || Ok(ImportAction::Skip(here_lp.clone()))
+            )?;
+
+            actions.push(action)
+        }
 
-                let yes_action = || {
-                    let hop_home = &self.value.get_hop_home(&self.hop_home_dir)?;
-                    self.value.delete_link(hop_home, pair)?;
+        let merged = actions.iter().filter(|action| planned_pair(action).is_some()).count();
+
+        if merged > 0 {
+            //The prompts are answered before the lock is taken, so each change is checked again before it is applied
+            let _lock = self.lock_home()?;
+            let hop_home = self.hop_home()?;
+            let current = self.hop_home_links(&hop_home)?;
+
+            for change in &changes {
+                let current_target = current.iter().find(|lp| lp.link == change.link).map(|lp| normalise(&lp.target.0));
+
+                if current_target != change.before.as_ref().map(|before| normalise(&before.0)) {
+                    return Err(io_error(&format!("`{}` was changed while the merge was being planned. Aborting merge.", change.link)))
+                }
+            }
+
+            self.snapshot_before("merge")?;
+            self.apply_all_changes(&hop_home, &changes, "merge")?;
+            self.record_change(&format!("Merge {} from {}", pluralise(merged, "link"), other_name), changes)?;
+        }
+
+        Ok(actions)
+    }
+
+    /// Imports links into the hop home. Every link is checked before any are written, so an import that fails
+    /// because of a missing target or a conflict doesn't leave the hop home half imported. If writing a link fails,
+    /// the links written before it are reverted.
+    pub fn import_links(&self, pairs: Vec<LinkPair>, options: &ImportOptions) -> HopEffect<Vec<ImportAction>> {
+        let _lock = self.lock_home()?;
+        let hop_home = self.hop_home()?;
+        let actions = self.plan_import(&hop_home, pairs, options)?;
+
+        if !options.dry_run {
+            let existing = self.hop_home_links(&hop_home)?;
+            let changes: Vec<LinkChange> =
+                actions
+                .iter()
+                .filter_map(|action| match action {
+                    ImportAction::Create(pair) | ImportAction::Rename(_, pair) => Some(LinkChange::added(pair)),
+                    ImportAction::Update(pair) => match existing.iter().find(|lp| lp.link == pair.link) {
+                        Some(existing_pair) => Some(LinkChange::retargeted(existing_pair, &pair.target)),
+                        None => Some(LinkChange::added(pair)),
+                    },
+                    ImportAction::Skip(_) => None,
+                })
+                .collect();
+
+            if !changes.is_empty() {
+                self.snapshot_before("import")?;
+                self.apply_all_changes(&hop_home, &changes, "import")?;
+                self.record_change(&format!("Import {}", pluralise(changes.len(), "link")), changes)?;
+            }
+        }
 
-                    Ok(DeleteStatus::DeleteSucceeded(pair.clone()))
+        Ok(actions)
+    }
+
+    fn plan_import(&self, hop_home: &Path, pairs: Vec<LinkPair>, options: &ImportOptions) -> HopEffect<Vec<ImportAction>> {
+        let existing = self.hop_home_links(hop_home)?;
+
+        let mut actions: Vec<ImportAction> = vec![];
+        let mut missing_targets: Vec<String> = vec![];
+
+        for pair in pairs {
+            validate_link_name(&pair.link, "import")?;
+
+            let target_path = resolve_mark_target(&pair.target)?;
+
+            if !options.allow_missing && !self.value.dir_exists(&target_path)? {
+                missing_targets.push(format!("{}", pair));
+                continue
+            }
+
+            let pair = LinkPair { target: LinkTarget(target_path), ..pair };
+            let is_planned = |link: &Link| actions.iter().filter_map(planned_pair).any(|planned| &planned.link == link);
+            let existing_pair = existing.iter().find(|lp| lp.link == pair.link);
+
+            let action = match (existing_pair, is_planned(&pair.link)) {
+                (None, false) => ImportAction::Create(pair),
+                (Some(existing_pair), false) if existing_pair.target == pair.target => ImportAction::Skip(pair),
+                (_, in_file) => match options.on_conflict {
+                    ConflictPolicy::Skip => ImportAction::Skip(pair),
+                    ConflictPolicy::Overwrite if in_file => ImportAction::Skip(pair),
+                    ConflictPolicy::Overwrite => ImportAction::Update(pair),
+                    ConflictPolicy::Rename => {
+                        let taken: Vec<LinkPair> = existing.iter().chain(actions.iter().filter_map(planned_pair)).cloned().collect();
+                        let renamed_link = Link::new(formats::unused_name(&pair.link.to_string(), &taken)?);
+
+                        ImportAction::Rename(pair.link.clone(), LinkPair { link: renamed_link, ..pair })
+                    },
+                    ConflictPolicy::Fail => return Err(io_error(&format!(
+                        "A link named `{}` already exists. Aborting import.",
+                        pair.link
+                    ))),
+                },
+            };
+
+            actions.push(action)
+        }
+
+        if missing_targets.is_empty() {
+            Ok(actions)
+        } else {
+            Err(io_error(&format!(
+                "These targets do not exist or you do not have permission to them. Aborting import.\n{}",
+                missing_targets.join("\n")
+            )))
+        }
+    }
+
+    /// Deletes the links with the given names, where a name may be a glob pattern using `*` and `?`. Every matched
+    /// link is confirmed at once.
+    pub fn delete_links(&self, names: &[Link]) -> HopEffect<DeleteStatus> {
+        let link_pairs = self.match_links(names)?;
+
+        let prompt_message = match link_pairs.as_slice() {
+            [pair] => format!("Are you sure you want to delete {} which links to {}?", pair.link, pair.target),
+            _ => {
+                if self.prompts == PromptMode::Ask && self.config.confirm {
+                    link_pairs.iter().for_each(|pair| self.value.println(&format!("  {}", pair)));
+                }
+
+                format!("Are you sure you want to delete these {} links?", link_pairs.len())
+            },
+        };
+
+        let no_action = || Ok(DeleteStatus::DeleteAborted);
+
+        let yes_action = || {
+            let _lock = self.lock_home()?;
+            let hop_home = &self.hop_home()?;
+
+            //The links were matched before the lock was taken, so each is checked again before it is deleted
+            let current = self.hop_home_links(hop_home)?;
+            let unchanged = |pair: &LinkPair| {
+                current.iter().any(|lp| lp.link == pair.link && normalise(&lp.target.0) == normalise(&pair.target.0))
+            };
+
+            let outcomes: Vec<DeleteOutcome> =
+                link_pairs
+                .iter()
+                .map(|pair| {
+                    if !unchanged(pair) {
+                        return DeleteOutcome::Failed(pair.clone(), format!("`{}` was changed before it could be deleted.", pair.link))
+                    }
+
+                    match self.value.trash_link(hop_home, pair) {
+                        Ok(()) => DeleteOutcome::Deleted(pair.clone()),
+                        Err(e) => DeleteOutcome::Failed(pair.clone(), e.to_string()),
+                    }
+                })
+                .collect();
+
+            let deleted: Vec<&LinkPair> =
+                outcomes
+                .iter()
+                .filter_map(|outcome| match outcome {
+                    DeleteOutcome::Deleted(pair) => Some(pair),
+                    DeleteOutcome::Failed(..) => None,
+                })
+                .collect();
+
+            let description = match deleted.as_slice() {
+                [] => None,
+                [pair] => Some(format!("Delete {}", pair)),
+                _ => Some(format!("Delete {} links", deleted.len())),
+            };
+
+            if let Some(description) = description {
+                self.record_change(&description, deleted.iter().map(|pair| LinkChange::removed(pair)).collect())?;
+            }
+
+            self.expire_trash(hop_home)?;
+
+            Ok(DeleteStatus::DeleteCompleted(outcomes))
+        };
+
+        if self.config.confirm {
+            self.prompt_user(&prompt_message, yes_action, no_action)
+        } else {
+            yes_action()
+        }
+    }
+
+    /// The links named by `names` in the order they are named. Patterns only match links in the writable hop home,
+    /// while a link named exactly in a read-only hop home is an error. Nothing is matched if any name matches nothing.
+    fn match_links(&self, names: &[Link]) -> HopEffect<Vec<LinkPair>> {
+        let layered_pairs = self.get_layered_link_pairs()?;
+        let mut matched: Vec<LinkPair> = vec![];
+
+        for name in names {
+            let pattern = name.0.as_bytes();
+
+            let name_matches: Vec<&LayeredLinkPair> = if is_glob(pattern) {
+                let pattern_matches: Vec<&LayeredLinkPair> =
+                    layered_pairs
+                    .iter()
+                    .filter(|lp| self.is_writable_layer(&lp.layer) && glob_matches(pattern, lp.link_pair.link.0.as_bytes()))
+                    .collect();
+
+                if pattern_matches.is_empty() {
+                    return Err(io_error(&format!("No links match `{}` for deletion", name)))
+                }
+
+                pattern_matches
+            } else {
+                match layered_pairs.iter().find(|lp| &lp.link_pair.link == name) {
+                    Some(layered_pair) if !self.is_writable_layer(&layered_pair.layer) => {
+                        return Err(io_error(&format!(
+                            "The link `{}` is in the read-only hop home `{}` and can't be deleted.",
+                            name, layered_pair.layer
+                        )))
+                    },
+                    Some(layered_pair) => vec![layered_pair],
+                    None => return Err(io_error(&format!("Could not find link named:`{}` for deletion", name))),
+                }
+            };
+
+            for layered_pair in name_matches {
+                if !matched.contains(&layered_pair.link_pair) {
+                    matched.push(layered_pair.link_pair.clone())
+                }
+            }
+        }
+
+        Ok(matched)
+    }
+
+    /// The deleted links in the trash of the hop home, newest first. Links older than `trash_days` are removed first.
+    pub fn trashed_links(&self) -> HopEffect<Vec<TrashedLink>> {
+        let _lock = self.lock_home()?;
+        let hop_home = self.hop_home()?;
+        self.expire_trash(&hop_home)?;
+        self.value.trashed_links(&hop_home)
+    }
+
+    /// Moves the most recently deleted link named `link` out of the trash.
+    pub fn restore_link(&self, link: &Link) -> HopEffect<LinkPair> {
+        let _lock = self.lock_home()?;
+        let hop_home = self.hop_home()?;
+        let trashed_links = self.value.trashed_links(&hop_home)?;
+
+        match trashed_links.iter().find(|trashed| &trashed.link_pair.link == link) {
+            Some(trashed) => {
+                let pair = &trashed.link_pair;
+
+                //Restoring is the check for whether the link exists, as for writing a new link
+                self.value.restore_link(&hop_home, trashed).map_err(|e| match e.kind() {
+                    io::ErrorKind::AlreadyExists => io_error(&format!("A link named `{}` already exists. Aborting restore.", link)),
+                    _ => e,
+                })?;
+                self.record_change(&format!("Restore {}", pair), vec![LinkChange::added(pair)])?;
+
+                Ok(pair.clone())
+            },
+            None => Err(io_error(&format!("Could not find link named:`{}` in the trash", link))),
+        }
+    }
+
+    /// Permanently removes every link in the trash.
+    pub fn empty_trash(&self) -> HopEffect<Vec<TrashedLink>> {
+        let _lock = self.lock_home()?;
+        let hop_home = self.hop_home()?;
+        self.value.empty_trash(&hop_home, None)
+    }
+
+    fn expire_trash(&self, hop_home: &Path) -> HopEffect<()> {
+        match self.config.trash_days {
+            0 => Ok(()),
+            days => self.value.empty_trash(hop_home, Some(days)).map(|_| ()),
+        }
+    }
+
+    fn is_writable_layer(&self, layer_name: &str) -> bool {
+        self.hop_homes
+            .iter()
+            .any(|layer| layer.name == layer_name && layer.writable)
+    }
+
+    /// The names of all known profiles, whether they are declared in the config file or have a hop home under
+    /// `$XDG_DATA_HOME/hop-profiles`.
+    pub fn list_profiles(&self) -> HopEffect<Vec<String>> {
+        let mut profiles = self.value.list_profiles()?;
+        profiles.extend(self.config.profiles.keys().cloned());
+        profiles.sort();
+        profiles.dedup();
+
+        Ok(profiles)
+    }
+
+    /// Where `tool` keeps its database when no file is given.
+    pub fn frecency_file(&self, tool: FrecencyTool) -> HopEffect<PathBuf> {
+        Ok(tool.default_file(&self.value.home_dir()?, &self.value.data_home()?))
+    }
+
+    /// Asks whether to mark each of the most visited directories that aren't already marked, stopping after `limit`
+    /// suggestions or when the user quits. Names are made from the directory's name.
+    pub fn suggest_links(&self, mut visits: Vec<DirVisit>, limit: usize) -> HopEffect<Vec<LinkPair>> {
+        let mut link_pairs = self.get_link_pairs()?;
+        let mut marked: Vec<LinkPair> = vec![];
+
+        visits.sort_by(|v1, v2| v2.score.total_cmp(&v1.score));
+
+        let mut suggestions = 0;
+
+        for visit in visits {
+            if suggestions == limit {
+                break
+            }
+
+            let is_marked = link_pairs.iter().any(|lp| normalise(&lp.target.0) == normalise(&visit.dir));
+
+            if is_marked || !visit.dir.is_absolute() || !self.value.dir_exists(&visit.dir)? {
+                continue
+            }
+
+            let name = match visit.dir.file_name().and_then(|name| formats::sanitise_name(&name.to_string_lossy())) {
+                Some(name) => formats::unused_name(&name, &link_pairs)?,
+                None => continue,
+            };
+
+            suggestions += 1;
+
+            let pair = LinkPair::new(name, visit.dir);
+
+            let answer = match self.prompts {
+                PromptMode::Ask => {
+                    self.value.println(&format!("Mark {} ? [y]es, [n]o or [q]uit", pair));
+                    self.value.readln()?
+                },
+                PromptMode::AssumeYes => "y".to_string(),
+                PromptMode::UseDefault => "n".to_string(),
+            };
+
+            match answer.trim() {
+                "Y" | "y" => {
+                    let _lock = self.lock_home()?;
+                    self.mark(&pair, false)?;
+                    self.record_change(&format!("Mark {}", pair), vec![LinkChange::added(&pair)])?;
+                    self.value.println(&format!("Created link from {}", pair));
+                    link_pairs.push(pair.clone());
+                    marked.push(pair)
+                },
+                "Q" | "q" => break,
+                _ => (),
+            }
+        }
+
+        Ok(marked)
+    }
+
+    /// The links `hop scan` proposes for the git repositories, and projects if asked, found under `dir`. Directories
+    /// that are already marked are left out. Links are named after their directory, with a numbered suffix if the
+    /// name is taken.
+    pub fn scan_dirs(&self, dir: &LinkTarget, options: &ScanOptions) -> HopEffect<Vec<LinkPair>> {
+        let dir_path = normalise(&resolve_mark_target(dir)?);
+
+        if !self.value.dir_exists(&dir_path)? {
+            return Err(io_error(&format!(
+                "A directory named `{}` does not exist or you do not have permission to it.",
+                dir
+            )))
+        }
+
+        let project_files: &[&str] = if options.projects { &PROJECT_FILES } else { &[] };
+        let mut link_pairs = self.get_link_pairs()?;
+        let mut proposed: Vec<LinkPair> = vec![];
+
+        for project_dir in self.value.find_projects(&dir_path, options.depth, project_files)? {
+            if link_pairs.iter().any(|lp| normalise(&lp.target.0) == project_dir) {
+                continue
+            }
+
+            let dir_name = project_dir.file_name().map(|name| name.to_string_lossy().to_string());
+
+            let tagged_name = match (&options.tag, dir_name) {
+                (Some(tag), Some(name)) => formats::sanitise_name(&format!("{}-{}", tag, name)),
+                (None, Some(name)) => formats::sanitise_name(&name),
+                (_, None) => None,
+            };
+
+            if let Some(name) = tagged_name {
+                let pair = LinkPair::new(formats::unused_name(&name, &link_pairs)?, project_dir);
+                link_pairs.push(pair.clone());
+                proposed.push(pair)
+            }
+        }
+
+        Ok(proposed)
+    }
+
+    /// Works out the changes to hop and `other` that bring them back in sync. `base` is how the links were after
+    /// the last sync, which tells apart a link added on one side from a link removed from the other.
+    pub fn plan_sync(&self, other: Vec<LinkPair>, base: Vec<LinkPair>) -> HopEffect<SyncPlan> {
+        let hop_home = self.hop_home()?;
+        let hop_links = self.hop_home_links(&hop_home)?;
+
+        let mut plan = SyncPlan {
+            hop: vec![],
+            other: vec![],
+            conflicts: vec![],
+            synced: vec![],
+        };
+
+        let mut names: Vec<&Link> = vec![];
+        for lp in hop_links.iter().chain(other.iter()) {
+            if !names.contains(&&lp.link) {
+                names.push(&lp.link)
+            }
+        }
+
+        let find = |links: &[LinkPair], link: &Link| links.iter().find(|lp| &lp.link == link).cloned();
+        let unchanged = |lp: &LinkPair, base_lp: &LinkPair| lp.target == base_lp.target;
+
+        for name in names {
+            match (find(&hop_links, name), find(&other, name), find(&base, name)) {
+                (Some(hop_lp), Some(other_lp), _) if hop_lp.target == other_lp.target => plan.synced.push(hop_lp),
+                (Some(hop_lp), Some(other_lp), Some(base_lp)) if unchanged(&hop_lp, &base_lp) => {
+                    plan.hop.push(SyncChange::Update(other_lp.clone()));
+                    plan.synced.push(other_lp)
+                },
+                (Some(hop_lp), Some(other_lp), Some(base_lp)) if unchanged(&other_lp, &base_lp) => {
+                    plan.other.push(SyncChange::Update(hop_lp.clone()));
+                    plan.synced.push(hop_lp)
+                },
+                (Some(hop_lp), Some(other_lp), _) => plan.conflicts.push((hop_lp, other_lp)),
+                (Some(hop_lp), None, Some(base_lp)) if unchanged(&hop_lp, &base_lp) => plan.hop.push(SyncChange::Remove(hop_lp)),
+                (Some(hop_lp), None, _) => {
+                    plan.other.push(SyncChange::Add(hop_lp.clone()));
+                    plan.synced.push(hop_lp)
+                },
+                (None, Some(other_lp), Some(base_lp)) if unchanged(&other_lp, &base_lp) => plan.other.push(SyncChange::Remove(other_lp)),
+                (None, Some(other_lp), _) => {
+                    plan.hop.push(SyncChange::Add(other_lp.clone()));
+                    plan.synced.push(other_lp)
+                },
+                (None, None, _) => (),
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// Makes the hop side of a sync with `other`. Targets that don't exist are allowed, as they came from the other
+    /// side.
+    pub fn apply_sync(&self, other: &str, changes: &[SyncChange]) -> HopEffect<()> {
+        let _lock = self.lock_home()?;
+        self.apply_changes(&format!("Sync {} with {}", pluralise(changes.len(), "link"), other), changes)
+    }
+
+    /// Saves the links in the hop home as a snapshot. The oldest snapshots beyond `snapshot_limit` are removed.
+    pub fn create_snapshot(&self, label: Option<&str>) -> HopEffect<Snapshot> {
+        let _lock = self.lock_home()?;
+        self.take_snapshot(label)
+    }
+
+    /// `create_snapshot` for commands that already hold the lock.
+    fn take_snapshot(&self, label: Option<&str>) -> HopEffect<Snapshot> {
+        let hop_home = self.hop_home()?;
+        let links = self.hop_home_links(&hop_home)?;
+        let snapshot = self.value.write_snapshot(&hop_home, label, &links)?;
+
+        if self.config.snapshot_limit > 0 {
+            for old_snapshot in self.value.list_snapshots(&hop_home)?.iter().skip(self.config.snapshot_limit) {
+                self.value.remove_snapshot(&hop_home, &old_snapshot.id)?
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    /// The snapshots of the hop home, newest first.
+    pub fn snapshots(&self) -> HopEffect<Vec<Snapshot>> {
+        let hop_home = self.hop_home()?;
+        self.value.list_snapshots(&hop_home)
+    }
+
+    /// Works out the changes that put the links in the hop home back as they were in the snapshot `id`.
+    pub fn plan_restore(&self, id: &str) -> HopEffect<(Snapshot, Vec<SyncChange>)> {
+        let hop_home = self.hop_home()?;
+
+        let snapshot =
+            self.value
+            .list_snapshots(&hop_home)?
+            .into_iter()
+            .find(|snapshot| snapshot.id == id)
+            .ok_or_else(|| io_error(&format!("Could not find snapshot `{}`", id)))?;
+
+        let links = self.hop_home_links(&hop_home)?;
+        let find = |links: &[LinkPair], link: &Link| links.iter().find(|lp| &lp.link == link).cloned();
+
+        let mut changes: Vec<SyncChange> = vec![];
+
+        for snapshot_lp in &snapshot.links {
+            match find(&links, &snapshot_lp.link) {
+                Some(lp) if normalise(&lp.target.0) == normalise(&snapshot_lp.target.0) => (),
+                Some(_) => changes.push(SyncChange::Update(snapshot_lp.clone())),
+                None => changes.push(SyncChange::Add(snapshot_lp.clone())),
+            }
+        }
+
+        for lp in links {
+            if find(&snapshot.links, &lp.link).is_none() {
+                changes.push(SyncChange::Remove(lp))
+            }
+        }
+
+        Ok((snapshot, changes))
+    }
+
+    /// Makes the changes from `plan_restore`, after taking a snapshot of the links as they are now.
+    pub fn restore_snapshot(&self, snapshot: &Snapshot, changes: &[SyncChange]) -> HopEffect<()> {
+        let _lock = self.lock_home()?;
+
+        if !changes.is_empty() {
+            self.take_snapshot(Some(&format!("before restoring {}", snapshot.id)))?;
+        }
+
+        self.apply_changes(&format!("Restore snapshot {}", snapshot.id), changes)
+    }
+
+    /// Takes a snapshot before a command that changes many links, unless the hop home has no links to lose.
+    fn snapshot_before(&self, command: &str) -> HopEffect<()> {
+        let hop_home = self.hop_home()?;
+
+        if self.hop_home_links(&hop_home)?.is_empty() {
+            Ok(())
+        } else {
+            self.take_snapshot(Some(&format!("before {}", command))).map(|_| ())
+        }
+    }
+
+    /// Makes planned changes under the lock. The changes are checked against the links as they are now, as they may
+    /// have been planned before the lock was taken, and are reverted if any of them fails.
+    fn apply_changes(&self, description: &str, changes: &[SyncChange]) -> HopEffect<()> {
+        let hop_home = self.hop_home()?;
+        let existing = self.hop_home_links(&hop_home)?;
+        let mut link_changes: Vec<LinkChange> = vec![];
+
+        for change in changes {
+            let existing_pair = |pair: &LinkPair| {
+                existing.iter().find(|lp| lp.link == pair.link).cloned().ok_or_else(|| {
+                    io_error(&format!("`{}` has been removed since the changes were planned. Aborting.", pair.link))
+                })
+            };
+
+            match change {
+                SyncChange::Add(pair) => link_changes.push(LinkChange::added(&resolved_pair(pair)?)),
+                SyncChange::Update(pair) => link_changes.push(LinkChange::retargeted(&existing_pair(pair)?, &resolved_pair(pair)?.target)),
+                SyncChange::Remove(pair) => link_changes.push(LinkChange::removed(&existing_pair(pair)?)),
+            }
+        }
+
+        if changes.is_empty() {
+            Ok(())
+        } else {
+            self.apply_all_changes(&hop_home, &link_changes, "change")?;
+            self.record_change(description, link_changes)
+        }
+    }
+
+    /// Checks every operation in a batch against the hop home before applying any of them. If an operation fails
+    /// while they are applied, the operations before it are reverted, so the hop home is left as it was.
+    pub fn run_batch(&self, ops: &[BatchOp]) -> HopEffect<Vec<BatchOutcome>> {
+        let _lock = self.lock_home()?;
+        let hop_home = self.hop_home()?;
+
+        //Play the operations against the links in the hop home, so that each is checked after those before it
+        let mut links = self.hop_home_links(&hop_home)?;
+        let mut plans: Vec<Result<Vec<LinkChange>, String>> = vec![];
+
+        for op in ops {
+            let plan = self.plan_batch_op(&hop_home, op, &links);
+
+            if let Ok(changes) = &plan {
+                for change in changes {
+                    links.retain(|lp| lp.link != change.link);
+
+                    if let Some(after) = &change.after {
+                        links.push(LinkPair { link: change.link.clone(), target: after.clone() })
+                    }
+                }
+            }
+
+            plans.push(plan.map_err(|e| e.to_string()))
+        }
+
+        if plans.iter().any(Result::is_err) {
+            let outcomes =
+                plans
+                .into_iter()
+                .map(|plan| match plan {
+                    Ok(_) => BatchOutcome::NotApplied,
+                    Err(e) => BatchOutcome::Invalid(e),
+                })
+                .collect();
+
+            return Ok(outcomes)
+        }
+
+        let planned_changes: Vec<Vec<LinkChange>> = plans.into_iter().flatten().collect();
+        let is_deletion = |index: usize| matches!(ops[index], BatchOp::Delete(_));
+        let applied = self.apply_link_changes(&hop_home, &planned_changes, "batch", is_deletion, |index| {
+            format!("`{}` failed and the operations before it could not be reverted.", ops[index])
+        })?;
+
+        if let Err((index, e)) = applied {
+            let outcomes =
+                (0..ops.len())
+                .map(|op_index| match op_index.cmp(&index) {
+                    Ordering::Less => BatchOutcome::RolledBack,
+                    Ordering::Equal => BatchOutcome::Failed(e.to_string()),
+                    Ordering::Greater => BatchOutcome::NotApplied,
+                })
+                .collect();
+
+            return Ok(outcomes)
+        }
+
+        if !ops.is_empty() {
+            self.record_change(
+                &format!("Batch of {}", pluralise(ops.len(), "operation")),
+                planned_changes.into_iter().flatten().collect()
+            )?;
+        }
+
+        Ok(ops.iter().map(|_| BatchOutcome::Applied).collect())
+    }
+
+    /// The changes an operation in a batch makes to `links`, or why it can't be applied to them.
+    fn plan_batch_op(&self, hop_home: &Path, op: &BatchOp, links: &[LinkPair]) -> HopEffect<Vec<LinkChange>> {
+        let existing = |link: &Link| {
+            links
+                .iter()
+                .find(|lp| &lp.link == link)
+                .ok_or_else(|| io_error(&format!("Could not find link named:`{}` in {}", link, hop_home.to_string_lossy())))
+        };
+
+        let unused = |link: &Link| match links.iter().any(|lp| &lp.link == link) {
+            true => Err(io_error(&format!("A link named `{}` already exists.", link))),
+            false => Ok(()),
+        };
+
+        match op {
+            BatchOp::Mark(pair) => {
+                validate_link_name(&pair.link, "mark creation")?;
+                unused(&pair.link)?;
+                let target = self.batch_target(&pair.target)?;
+
+                Ok(vec![LinkChange::added(&LinkPair { link: pair.link.clone(), target })])
+            },
+            BatchOp::Delete(link) => Ok(vec![LinkChange::removed(existing(link)?)]),
+            BatchOp::Rename(link, new_link) => {
+                let pair = existing(link)?;
+                validate_link_name(new_link, "rename")?;
+                unused(new_link)?;
+
+                let renamed_pair = LinkPair { link: new_link.clone(), target: pair.target.clone() };
+                Ok(vec![LinkChange::added(&renamed_pair), LinkChange::removed(pair)])
+            },
+            BatchOp::Retarget(pair) => {
+                let existing_pair = existing(&pair.link)?;
+                let target = self.batch_target(&pair.target)?;
+
+                Ok(vec![LinkChange::retargeted(existing_pair, &target)])
+            },
+        }
+    }
+
+    /// Resolves the target of an operation in a batch, which must be an existing directory. A leading `~` is the home
+    /// directory, as batches aren't expanded by a shell.
+    fn batch_target(&self, target: &LinkTarget) -> HopEffect<LinkTarget> {
+        let target_path = match target.0.strip_prefix("~") {
+            Ok(home_relative_path) => self.value.home_dir()?.join(home_relative_path),
+            Err(_) => target.0.clone(),
+        };
+
+        let resolved_target_path = normalise(&resolve_mark_target(&LinkTarget(target_path))?);
+
+        if self.value.dir_exists(&resolved_target_path)? {
+            Ok(LinkTarget(resolved_target_path))
+        } else {
+            Err(io_error(&format!(
+                "A directory named `{}` does not exist or you do not have permission to it.",
+                target
+            )))
+        }
+    }
+
+    /// Applies groups of changes, such as those of each operation in a batch, one link at a time. If a change fails,
+    /// the changes before it are reverted so the hop home is left as it was, and the error is returned along with the
+    /// index of the group that failed. `rollback_failed` describes the group when the revert fails too. Links removed
+    /// by the groups that `is_deletion` picks out are moved to the trash, and taken back out of it when reverted.
+    fn apply_link_changes<F, D>(&self, hop_home: &Path, groups: &[Vec<LinkChange>], action: &str, is_deletion: D, rollback_failed: F) -> HopEffect<Result<(), (usize, io::Error)>>
+    where
+        F: Fn(usize) -> String,
+        D: Fn(usize) -> bool,
+    {
+        let mut applied_steps: Vec<(LinkChange, bool)> = vec![];
+
+        for (index, changes) in groups.iter().enumerate() {
+            for step in changes.iter().flat_map(batch_steps) {
+                let trashed = is_deletion(index) && step.after.is_none();
+
+                let applied = match (&step.before, trashed) {
+                    (Some(before), true) => self.value.trash_link(hop_home, &LinkPair { link: step.link.clone(), target: before.clone() }),
+                    _ => self.apply_step(hop_home, &step, action),
                 };
 
-                self.prompt_user(&prompt_message, yes_action, no_action)
+                if let Err(e) = applied {
+                    for (applied_step, trashed) in applied_steps.iter().rev() {
+                        let reverted = match trashed {
+                            true => self.untrash_link(hop_home, &applied_step.link),
+                            false => self.apply_step(hop_home, &applied_step.reverted(), action),
+                        };
+
+                        reverted.map_err(|rollback_error| {
+                            io_error_ex_nested(&rollback_failed(index), rollback_error, io_error(&e.to_string()))
+                        })?;
+                    }
+
+                    return Ok(Err((index, e)))
+                }
+
+                applied_steps.push((step, trashed))
+            }
+        }
+
+        Ok(Ok(()))
+    }
+
+    /// Moves the most recently deleted link named `link` out of the trash.
+    fn untrash_link(&self, hop_home: &Path, link: &Link) -> HopEffect<()> {
+        let trashed_links = self.value.trashed_links(hop_home)?;
+
+        match trashed_links.iter().find(|trashed| &trashed.link_pair.link == link) {
+            Some(trashed) => self.value.restore_link(hop_home, trashed),
+            None => Err(io_error(&format!("Could not find link named:`{}` in the trash", link))),
+        }
+    }
+
+    /// Applies `changes` all or nothing, for commands that fail as a whole rather than reporting each change.
+    fn apply_all_changes(&self, hop_home: &Path, changes: &[LinkChange], action: &str) -> HopEffect<()> {
+        let groups: Vec<Vec<LinkChange>> = changes.iter().map(|change| vec![change.clone()]).collect();
+
+        self.apply_link_changes(hop_home, &groups, action, |_| false, |index| {
+            format!("Could not change `{}` and the links changed before it could not be put back.", changes[index].link)
+        })?
+        .map_err(|(index, e)| io_error_ex(&format!("Could not change `{}`. No links were changed.", changes[index].link), e))
+    }
+
+    /// Removes or writes a single link. A link is never written over one that already exists.
+    fn apply_step(&self, hop_home: &Path, step: &LinkChange, action: &str) -> HopEffect<()> {
+        if let Some(before) = &step.before {
+            self.value.delete_link(hop_home, &LinkPair { link: step.link.clone(), target: before.clone() })?;
+        }
+
+        if let Some(after) = &step.after {
+            let stored_target_path = self.stored_target(hop_home, &after.0)?;
+            self.write_new_link(&step.link, SymLink(hop_home.join(&step.link)), &stored_target_path, action)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pulls links from the git remote of the hop home and pushes local changes to it. Nothing is pulled or pushed
+    /// when the same link was changed on both sides, unless `prefer` chooses which side wins.
+    pub fn sync_git(&self, prefer: Option<SyncPreference>) -> HopEffect<GitSyncStatus> {
+        if !self.config.git {
+            return Err(io_error("The hop home is not kept in git. Set `git = true` in the config file to sync it."))
+        }
+
+        let _lock = self.lock_home()?;
+        let hop_home = self.hop_home()?;
+        self.value.init_repo(&hop_home)?;
+
+        match &self.config.git_remote {
+            Some(remote) => self.value.set_remote(&hop_home, remote)?,
+            None if !self.value.has_remote(&hop_home)? => {
+                return Err(io_error("There is no remote to sync with. Set `git_remote` in the config file."))
+            },
+            None => (),
+        }
+
+        self.value.commit_all(&hop_home, "Record changes made outside hop")?;
+
+        let conflicts = self.value.pull(&hop_home, prefer)?;
+
+        if conflicts.is_empty() {
+            self.value.push(&hop_home)?;
+            Ok(GitSyncStatus::Synced)
+        } else {
+            Ok(GitSyncStatus::Conflicts(conflicts))
+        }
+    }
+
+    /// The changes in the journal of the hop home, from newest to oldest.
+    pub fn journal(&self) -> HopEffect<Vec<JournalEntry>> {
+        let hop_home = self.hop_home()?;
+        let mut entries = self.value.read_entries(&hop_home)?;
+        entries.reverse();
+
+        Ok(entries)
+    }
+
+    /// Reverts the newest `count` changes in the journal, newest first. Every change is checked before any are
+    /// reverted, so nothing is reverted if a link was changed again outside of hop.
+    pub fn undo(&self, count: usize) -> HopEffect<Vec<JournalEntry>> {
+        let _lock = self.lock_home()?;
+        let entries = self.journal()?;
+
+        if entries.is_empty() {
+            return Err(io_error("There are no changes to undo."))
+        } else if count > entries.len() {
+            return Err(io_error(&format!("Only {} can be undone.", pluralise(entries.len(), "change"))))
+        }
+
+        let hop_home = self.hop_home()?;
+        let undone: Vec<JournalEntry> = entries.into_iter().take(count).collect();
+        let reverted_changes: Vec<&LinkChange> = undone.iter().flat_map(|entry| entry.changes.iter().rev()).collect();
+
+        //Play the changes back against the links as they are now, to make sure each one can be reverted
+        let mut links = self.hop_home_links(&hop_home)?;
+
+        for change in &reverted_changes {
+            let current = links.iter().position(|lp| lp.link == change.link);
+            let current_target = current.map(|index| normalise(&links[index].target.0));
+
+            if current_target != change.after.as_ref().map(|target| normalise(&target.0)) {
+                return Err(io_error(&format!(
+                    "`{}` has been changed since it was last changed by hop. Aborting undo.",
+                    change.link
+                )))
+            }
+
+            if let Some(index) = current {
+                links.remove(index);
+            }
+
+            if let Some(before) = &change.before {
+                links.push(LinkPair { link: change.link.clone(), target: before.clone() })
+            }
+        }
+
+        let trashed_links = self.value.trashed_links(&hop_home)?;
+        let mut restored_ids: Vec<&str> = vec![];
+
+        for change in reverted_changes {
+            if let Some(after) = &change.after {
+                self.value.delete_link(&hop_home, &LinkPair { link: change.link.clone(), target: after.clone() })?;
             }
 
-            None => Err(io_error(&format!(
-                "Could not find link named:`{}` for deletion",
-                link
-            ))),
+            //A deleted link is taken back out of the trash, so that it can't be restored a second time
+            let trashed = change.before.as_ref().filter(|_| change.after.is_none()).and_then(|before| {
+                trashed_links.iter().find(|trashed| {
+                    !restored_ids.contains(&trashed.id.as_str()) &&
+                    trashed.link_pair.link == change.link &&
+                    normalise(&resolve_target(&hop_home, trashed.link_pair.clone()).target.0) == normalise(&before.0)
+                })
+            });
+
+            match (trashed, &change.before) {
+                (Some(trashed), _) => {
+                    self.value.restore_link(&hop_home, trashed)?;
+                    restored_ids.push(&trashed.id)
+                },
+                (None, Some(before)) => {
+                    let stored_target_path = self.stored_target(&hop_home, &before.0)?;
+                    self.value.write_link(&SymLink(hop_home.join(&change.link)), &stored_target_path)?;
+                },
+                (None, None) => (),
+            }
         }
+
+        self.value.remove_entries(&hop_home, count)?;
+
+        let message = match undone.as_slice() {
+            [entry] => format!("Undo {}", entry.description),
+            _ => format!("Undo {}", pluralise(undone.len(), "change")),
+        };
+
+        self.commit_change(&hop_home, &message)?;
+
+        Ok(undone)
+    }
+
+    /// Adds a change to the journal, so that it can be undone, and commits it when the hop home is kept in git.
+    fn record_change(&self, description: &str, changes: Vec<LinkChange>) -> HopEffect<()> {
+        let hop_home = self.hop_home()?;
+
+        self.value
+            .append_entry(&hop_home, description, &changes)
+            .map_err(|e| io_error_ex("The links were changed but the change could not be added to the journal.", e))?;
+
+        self.commit_change(&hop_home, description)
     }
 
+    fn commit_change(&self, hop_home: &Path, message: &str) -> HopEffect<()> {
+        if self.config.git {
+            self.value
+                .init_repo(hop_home)
+                .and_then(|_| self.value.commit_all(hop_home, message))
+                .map_err(|e| io_error_ex("The links were changed but the change could not be committed to git.", e))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Moves everything in the legacy `~/.hop` into the XDG hop home and replaces `~/.hop` with a symlink to the
+    /// XDG hop home. Nothing is moved if any of the links, or anything else in `~/.hop`, already exist in the XDG hop
+    /// home.
+    pub fn migrate_home(&self) -> HopEffect<HomeMigration> {
+        let legacy_home =
+            self.value
+            .legacy_hop_home()?
+            .ok_or_else(|| io_error("Could not find a legacy hop home at ~/.hop to migrate."))?;
+
+        let xdg_home = self.value.xdg_hop_home()?;
+
+        //Hops that have not seen the migration lock `~/.hop`, later ones lock the XDG hop home.
+        let timeout = Duration::from_secs(self.config.lock_timeout);
+        let _legacy_lock = self.value.lock_home(&legacy_home, timeout)?;
+        let _xdg_lock = self.value.lock_home(&xdg_home, timeout)?;
+
+        let link_pairs = self.value.read_dir_links(&legacy_home)?;
+
+        for pair in &link_pairs {
+            if self.value.link_exists(&xdg_home.join(&pair.link))? {
+                return Err(io_error(&format!(
+                    "A link named `{}` already exists in {}. Aborting migration.",
+                    pair.link,
+                    xdg_home.to_string_lossy()
+                )));
+            }
+        }
+
+        self.value.move_hop_home(&legacy_home, &xdg_home)?;
+
+        //Targets stored relative to `~/.hop` point somewhere else from the XDG hop home, so they are stored again
+        let mut migrated = vec![];
+        for pair in link_pairs {
+            let resolved = resolve_target(&legacy_home, pair.clone());
+            if pair.target.0.is_relative() {
+                let target = self.stored_target(&xdg_home, resolved.target.as_ref())?;
+                self.value
+                    .delete_link(&xdg_home, &pair)
+                    .and_then(|_| self.value.write_link(&SymLink(xdg_home.join(&pair.link)), &target))
+                    .map_err(|e| io_error_ex(&format!("Could not rewrite the relative target of link `{}`", pair.link), e))?;
+            }
+            migrated.push(resolved);
+        }
+
+        Ok(HomeMigration {
+            from: legacy_home,
+            to: xdg_home,
+            links: migrated,
+        })
+    }
+
+    /// Asks the user a yes or no question. Always answers yes when confirmation is turned off in the config.
+    pub fn confirm(&self, message: &str) -> HopEffect<bool> {
+        if self.config.confirm {
+            self.prompt_user(message, || Ok(true), || Ok(false))
+        } else {
+            Ok(true)
+        }
+    }
+
+    /// Asks a question that defaults to no.
     fn prompt_user<Y, N, R>(&self, message: &str, yes_action: Y, no_action: N) -> HopEffect<R>
     where
         Y: FnOnce() -> HopEffect<R>,
         N: FnOnce() -> HopEffect<R>,
     {
-        self.value.println(message);
-        let buffer = self.value.readln()?;
-        let response = buffer
-            .lines()
-            .next()
-            .ok_or_else(|| io_error("Could not retrieve lines from stdio"))?;
-        match response {
-            "Y" | "y" => yes_action(),
-            _ => no_action(),
+        if self.ask(message, false)? {
+            yes_action()
+        } else {
+            no_action()
+        }
+    }
+
+    /// Asks a yes or no question, showing the `default` answer that is taken when the answer is left empty. Nothing
+    /// is asked when `prompts` answers every question.
+    fn ask(&self, question: &str, default: bool) -> HopEffect<bool> {
+        match self.prompts {
+            PromptMode::AssumeYes => Ok(true),
+            PromptMode::UseDefault => Ok(default),
+            PromptMode::Ask => {
+                let choices = if default { "[Y/n]" } else { "[y/N]" };
+                self.value.println(&format!("{} {}", question, choices));
+
+                match self.value.readln()?.trim() {
+                    "" => Ok(default),
+                    "Y" | "y" => Ok(true),
+                    _ => Ok(false),
+                }
+            },
         }
     }
 }
 
+/// Relative targets of new links are relative to the current directory.
+fn resolve_mark_target(target: &LinkTarget) -> HopEffect<PathBuf> {
+    let target_path = target.to_path_buf();
+
+    // Check if the path is relative and add the current directory to it
+    if target_path.is_relative() {
+        let current_dir = std::env::current_dir()?;
+        if target.as_ref() == Path::new(".") {
+            // If the user entered ".", then just use the current directory
+            Ok(current_dir)
+        } else {
+            // Otherwise append the current directory to the relative path
+            Ok(current_dir.join(&target_path))
+        }
+    } else {
+        Ok(target_path)
+    }
+}
+
+/// `pair` with its target made absolute as for a new link.
+fn resolved_pair(pair: &LinkPair) -> HopEffect<LinkPair> {
+    Ok(LinkPair { link: pair.link.clone(), target: LinkTarget(resolve_mark_target(&pair.target)?) })
+}
+
+/// Splits a change into removing the link and writing it, so that a change that fails halfway can be reverted.
+fn batch_steps(change: &LinkChange) -> Vec<LinkChange> {
+    let removal = change.before.as_ref().map(|before| LinkChange { after: None, before: Some(before.clone()), ..change.clone() });
+    let addition = change.after.as_ref().map(|after| LinkChange { before: None, after: Some(after.clone()), ..change.clone() });
+
+    removal.into_iter().chain(addition).collect()
+}
+
+/// The link an import action writes, if any.
+fn planned_pair(action: &ImportAction) -> Option<&LinkPair> {
+    match action {
+        ImportAction::Create(pair) | ImportAction::Update(pair) | ImportAction::Rename(_, pair) => Some(pair),
+        ImportAction::Skip(_) => None,
+    }
+}
+
+/// Links are files in the hop home, so their names can't be empty, `.`, `..` or contain a `/`.
+fn validate_link_name(link: &Link, action: &str) -> HopEffect<()> {
+    let name = link.0.as_bytes();
+
+    if name.is_empty() || name == b"." || name == b".." || name.contains(&b'/') {
+        Err(io_error(&format!("`{}` is not a valid link name. Aborting {}.", link, action)))
+    } else {
+        Ok(())
+    }
+}
+
+/// The path to `target` from the directory `from`. Both paths are absolute, so they always share the root.
+fn relative_path(from: &Path, target: &Path) -> PathBuf {
+    let from_components: Vec<Component> = from.components().collect();
+    let target_components: Vec<Component> = target.components().collect();
+
+    let common =
+        from_components
+        .iter()
+        .zip(target_components.iter())
+        .take_while(|(f, t)| f == t)
+        .count();
+
+    let mut relative: PathBuf = from_components[common..].iter().map(|_| Component::ParentDir).collect();
+    relative.extend(&target_components[common..]);
+
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
+    }
+}
+
+/// Relative targets are made absolute against `base_dir` so they can be jumped to from anywhere. This is the hop
+/// home, unless the layer has a base directory, such as a project for `api -> services/api` in the project's `.hop`.
+fn resolve_target(base_dir: &Path, link_pair: LinkPair) -> LinkPair {
+    if link_pair.target.0.is_relative() {
+        LinkPair {
+            target: LinkTarget::new(normalise(&base_dir.join(&link_pair.target.0))),
+            ..link_pair
+        }
+    } else {
+        link_pair
+    }
+}
+
+/// Whether a link name holds any of the glob wildcards `*` and `?`.
+fn is_glob(name: &[u8]) -> bool {
+    name.iter().any(|b| *b == b'*' || *b == b'?')
+}
+
+/// Matches a whole link name against a glob pattern, where `*` matches any run of characters and `?` any one
+/// character.
+fn glob_matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => glob_matches(rest, name) || (!name.is_empty() && glob_matches(pattern, &name[1..])),
+        (Some((b'?', rest)), Some((_, name_rest))) => glob_matches(rest, name_rest),
+        (Some((p, rest)), Some((n, name_rest))) => p == n && glob_matches(rest, name_rest),
+        _ => false,
+    }
+}
+
+/// Removes `.` and `..` from an absolute path without touching the file system.
+fn normalise(path: &Path) -> PathBuf {
+    let mut normalised = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalised.pop();
+            },
+            other => normalised.push(other),
+        }
+    }
+
+    normalised
+}
+
 #[cfg(test)]
 mod tests;
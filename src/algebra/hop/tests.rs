@@ -1,12 +1,23 @@
 use super::HopProgram;
-use crate::algebra::hop::DeleteStatus;
+use crate::algebra::hop::{DeleteOutcome, DeleteStatus};
 use crate::algebra::symlinks::{SymLink, SymLinks};
-use crate::algebra::{directories::Directories, std_io::StdIO, user_dirs::UserDirs};
-use crate::models::{HomeType, HopEffect, Link, LinkPair};
+use crate::algebra::trash::Trash;
+use crate::algebra::{
+    directories::Directories, git::Git, journal::Journal, locks::Locks, snapshots::Snapshots, std_io::StdIO,
+    user_dirs::UserDirs,
+};
+use crate::config::{Config, MatchMode, SortOrder, TargetPaths};
+use crate::formats::FrecencyTool;
+use crate::models::{
+    BatchOp, BatchOutcome, ConflictPolicy, DirVisit, GitConflict, GitSyncStatus, HomeLayer, HomeMigration, HomeType,
+    HopEffect, ImportAction, ImportOptions, JournalEntry, LayeredLinkPair, Link, LinkChange, LinkDiff, LinkPair,
+    LinkTarget, PromptMode, ScanOptions, SyncChange, Snapshot, SyncPreference, TrashedLink,
+};
 
 use std::cell::Cell;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 enum SymLinkDeleteStatus {
     Succeeded,
@@ -28,9 +39,26 @@ struct TestStub<'a> {
     dir_exists: bool,
     link_exists: bool,
     write_link: Option<String>,
+    /// Writing a link to this target fails, while other writes succeed.
+    failing_target: Option<PathBuf>,
     delete_link: SymLinkDeleteStatus,
+    legacy_hop_home: Option<PathBuf>,
+    shared_links: Vec<LinkPair>,
+    written_targets: Cell<Vec<PathBuf>>,
+    commits: Cell<Vec<String>>,
+    git_conflicts: Vec<GitConflict>,
+    pushed: Cell<bool>,
+    journal: Cell<Vec<JournalEntry>>,
+    trash: Cell<Vec<TrashedLink>>,
+    snapshots: Cell<Vec<Snapshot>>,
+    lock_holder: Option<u32>,
+    git_root: Option<PathBuf>,
+    /// Directories found by a scan, and whether each is a git repository rather than a project.
+    projects: Vec<(PathBuf, bool)>,
 }
 
+const SHARED_HOP_HOME: &str = "/xyz/team-hop";
+
 struct Test<'a> {
     stub: TestStub<'a>,
 }
@@ -45,7 +73,20 @@ impl<'a> TestStub<'a> {
             dir_exists: true,
             link_exists: false,
             write_link: None,
+            failing_target: None,
             delete_link: SymLinkDeleteStatus::Succeeded,
+            legacy_hop_home: None,
+            shared_links: Vec::new(),
+            written_targets: Cell::new(vec![]),
+            commits: Cell::new(vec![]),
+            git_conflicts: Vec::new(),
+            pushed: Cell::new(false),
+            journal: Cell::new(vec![]),
+            trash: Cell::new(vec![]),
+            snapshots: Cell::new(vec![]),
+            lock_holder: None,
+            git_root: None,
+            projects: Vec::new(),
         }
     }
 
@@ -78,7 +119,21 @@ impl<'a> TestStub<'a> {
     fn program(stub: Self) -> HopProgram<Test<'a>> {
         HopProgram {
             value: Test{ stub },
-            hop_home_dir: HomeType::Relative(".xyz".to_string()),
+            hop_homes: vec![HomeLayer::personal(HomeType::Xdg)],
+            config: Config::default(),
+            prompts: PromptMode::Ask,
+        }
+    }
+
+    fn layered_program(stub: Self) -> HopProgram<Test<'a>> {
+        HopProgram {
+            value: Test{ stub },
+            hop_homes: vec![
+                HomeLayer::personal(HomeType::Xdg),
+                HomeLayer::shared("team", PathBuf::from(SHARED_HOP_HOME))
+            ],
+            config: Config::default(),
+            prompts: PromptMode::Ask,
         }
     }
 }
@@ -103,23 +158,60 @@ impl UserDirs for Test<'_> {
         match &self.stub.get_hop_home {
             GetHopHomeStatus::Succeeded(path) => Ok(PathBuf::from(path)),
             GetHopHomeStatus::Failed(error) => {
-                Err(io::Error::new(io::ErrorKind::Other, error.to_string()))
+                Err(io::Error::other(error.to_string()))
             }
         }
     }
+
+    fn home_dir(&self) -> HopEffect<PathBuf> {
+        Ok(PathBuf::from("/xyz"))
+    }
+
+    fn data_home(&self) -> HopEffect<PathBuf> {
+        Ok(PathBuf::from("/xyz/.local/share"))
+    }
+
+    fn list_profiles(&self) -> HopEffect<Vec<String>> {
+        Ok(vec!["work".to_string(), "personal".to_string()])
+    }
+
+    fn legacy_hop_home(&self) -> HopEffect<Option<PathBuf>> {
+        Ok(self.stub.legacy_hop_home.clone())
+    }
+
+    fn xdg_hop_home(&self) -> HopEffect<PathBuf> {
+        Ok(PathBuf::from("/xyz/.local/share/hop"))
+    }
+
+    fn move_hop_home(&self, _from: &Path, _to: &Path) -> HopEffect<()> {
+        Ok(())
+    }
 }
 
 impl SymLinks for Test<'_> {
-    fn read_dir_links(&self, _dir_path: &Path) -> HopEffect<Vec<LinkPair>> {
+    fn read_dir_links(&self, dir_path: &Path) -> HopEffect<Vec<LinkPair>> {
+        if dir_path == Path::new(SHARED_HOP_HOME) {
+            return Ok(self.stub.shared_links.to_vec())
+        }
+
         match &self.stub.read_dir_links {
             Ok(links) => Ok(links.to_vec()),
-            Err(error) => Err(io::Error::new(io::ErrorKind::Other, error.to_string())),
+            Err(error) => Err(io::Error::other(error.to_string())),
         }
     }
 
-    fn write_link(&self, _symlink: &SymLink, _target: &Path) -> HopEffect<()> {
+    fn write_link(&self, _symlink: &SymLink, target: &Path) -> HopEffect<()> {
+        if self.stub.link_exists {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists))
+        }
+
+        let written_targets = &mut self.stub.written_targets.take();
+        written_targets.push(target.to_path_buf());
+        self.stub.written_targets.set(written_targets.to_vec());
+
         match &self.stub.write_link {
-            Some(error) => Err(io::Error::new(io::ErrorKind::Other, error.to_string())),
+            Some(error) => Err(io::Error::other(error.to_string())),
+            None if self.stub.failing_target.as_deref() == Some(target) => Err(io::Error::other("Disk full")),
             None => Ok(()),
         }
     }
@@ -131,8 +223,7 @@ impl SymLinks for Test<'_> {
     fn delete_link(&self, _dir_path: &Path, link_pair: &LinkPair) -> HopEffect<()> {
         match &self.stub.delete_link {
             SymLinkDeleteStatus::Succeeded => Ok(()),
-            SymLinkDeleteStatus::Failed => Err(io::Error::new(
-                io::ErrorKind::Other,
+            SymLinkDeleteStatus::Failed => Err(io::Error::other(
                 format!("Failed to delete: {}", &link_pair),
             )),
         }
@@ -143,6 +234,174 @@ impl Directories for Test<'_> {
     fn dir_exists(&self, _dir_path: &Path) -> HopEffect<bool> {
         Ok(self.stub.dir_exists)
     }
+
+    fn git_root(&self, dir_path: &Path) -> HopEffect<Option<PathBuf>> {
+        Ok(self.stub.git_root.clone().filter(|root| dir_path.starts_with(root)))
+    }
+
+    fn find_projects(&self, dir_path: &Path, _depth: usize, project_files: &[&str]) -> HopEffect<Vec<PathBuf>> {
+        let projects =
+            self.stub.projects
+            .iter()
+            .filter(|(project_dir, is_repo)| project_dir.starts_with(dir_path) && (*is_repo || !project_files.is_empty()))
+            .map(|(project_dir, _)| project_dir.clone())
+            .collect();
+
+        Ok(projects)
+    }
+}
+
+impl Git for Test<'_> {
+    fn init_repo(&self, _dir: &Path) -> HopEffect<()> {
+        Ok(())
+    }
+
+    fn commit_all(&self, _dir: &Path, message: &str) -> HopEffect<()> {
+        let commits = &mut self.stub.commits.take();
+        commits.push(message.to_string());
+        self.stub.commits.set(commits.to_vec());
+        Ok(())
+    }
+
+    fn set_remote(&self, _dir: &Path, _url: &str) -> HopEffect<()> {
+        Ok(())
+    }
+
+    fn has_remote(&self, _dir: &Path) -> HopEffect<bool> {
+        Ok(true)
+    }
+
+    fn pull(&self, _dir: &Path, prefer: Option<SyncPreference>) -> HopEffect<Vec<GitConflict>> {
+        match prefer {
+            Some(_) => Ok(vec![]),
+            None => Ok(self.stub.git_conflicts.to_vec()),
+        }
+    }
+
+    fn push(&self, _dir: &Path) -> HopEffect<()> {
+        self.stub.pushed.set(true);
+        Ok(())
+    }
+}
+
+impl Journal for Test<'_> {
+    fn append_entry(&self, _hop_home: &Path, description: &str, changes: &[LinkChange]) -> HopEffect<()> {
+        let entries = &mut self.stub.journal.take();
+        entries.push(journal_entry(description, changes.to_vec()));
+        self.stub.journal.set(entries.to_vec());
+        Ok(())
+    }
+
+    fn read_entries(&self, _hop_home: &Path) -> HopEffect<Vec<JournalEntry>> {
+        let entries = self.stub.journal.take();
+        self.stub.journal.set(entries.to_vec());
+        Ok(entries)
+    }
+
+    fn remove_entries(&self, _hop_home: &Path, count: usize) -> HopEffect<()> {
+        let entries = &mut self.stub.journal.take();
+        entries.truncate(entries.len() - count);
+        self.stub.journal.set(entries.to_vec());
+        Ok(())
+    }
+}
+
+impl Trash for Test<'_> {
+    fn trash_link(&self, _hop_home: &Path, link_pair: &LinkPair) -> HopEffect<()> {
+        match &self.stub.delete_link {
+            SymLinkDeleteStatus::Succeeded => {
+                let trash = &mut self.stub.trash.take();
+                trash.insert(0, trashed_link(link_pair));
+                self.stub.trash.set(trash.to_vec());
+                Ok(())
+            },
+            SymLinkDeleteStatus::Failed => Err(io::Error::other(format!("Failed to delete: {}", &link_pair))),
+        }
+    }
+
+    fn trashed_links(&self, _hop_home: &Path) -> HopEffect<Vec<TrashedLink>> {
+        let trash = self.stub.trash.take();
+        self.stub.trash.set(trash.to_vec());
+        Ok(trash)
+    }
+
+    fn restore_link(&self, _hop_home: &Path, trashed_link: &TrashedLink) -> HopEffect<()> {
+        if self.stub.link_exists {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists))
+        }
+
+        let trash = &mut self.stub.trash.take();
+        trash.retain(|trashed| trashed.id != trashed_link.id);
+        self.stub.trash.set(trash.to_vec());
+        Ok(())
+    }
+
+    //Links are never old enough to expire
+    fn empty_trash(&self, _hop_home: &Path, max_days: Option<u64>) -> HopEffect<Vec<TrashedLink>> {
+        match max_days {
+            Some(_) => Ok(vec![]),
+            None => Ok(self.stub.trash.take()),
+        }
+    }
+}
+
+impl Snapshots for Test<'_> {
+    fn write_snapshot(&self, _hop_home: &Path, label: Option<&str>, links: &[LinkPair]) -> HopEffect<Snapshot> {
+        let snapshots = &mut self.stub.snapshots.take();
+        let snapshot = snapshot(&format!("snapshot-{}", snapshots.len() + 1), label, links.to_vec());
+        snapshots.insert(0, snapshot.clone());
+        self.stub.snapshots.set(snapshots.to_vec());
+        Ok(snapshot)
+    }
+
+    fn list_snapshots(&self, _hop_home: &Path) -> HopEffect<Vec<Snapshot>> {
+        let snapshots = self.stub.snapshots.take();
+        self.stub.snapshots.set(snapshots.to_vec());
+        Ok(snapshots)
+    }
+
+    fn remove_snapshot(&self, _hop_home: &Path, id: &str) -> HopEffect<()> {
+        let snapshots = &mut self.stub.snapshots.take();
+        snapshots.retain(|snapshot| snapshot.id != id);
+        self.stub.snapshots.set(snapshots.to_vec());
+        Ok(())
+    }
+}
+
+impl Locks for Test<'_> {
+    type Lock = ();
+
+    fn lock_home(&self, _hop_home: &Path, _timeout: Duration) -> HopEffect<()> {
+        match self.stub.lock_holder {
+            Some(pid) => Err(io::Error::other(format!("The hop home is locked by pid {}. Try again once it has finished.", pid))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn snapshot(id: &str, label: Option<&str>, links: Vec<LinkPair>) -> Snapshot {
+    Snapshot {
+        id: id.to_string(),
+        label: label.map(str::to_string),
+        created: "2021-06-01T09:30:00+00:00".to_string(),
+        links,
+    }
+}
+
+fn trashed_link(link_pair: &LinkPair) -> TrashedLink {
+    TrashedLink {
+        id: format!("20210601093000-{}", link_pair.link),
+        link_pair: link_pair.clone(),
+        deleted: "2021-06-01T09:30:00+00:00".to_string(),
+    }
+}
+
+fn journal_entry(description: &str, changes: Vec<LinkChange>) -> JournalEntry {
+    JournalEntry {
+        time: "2021-06-01T09:30:00+00:00".to_string(),
+        description: description.to_string(),
+        changes,
+    }
 }
 
 #[test]
@@ -238,8 +497,8 @@ fn tabulate_links_success() {
         Ok(entries) => {
             assert_eq!(
                 &vec![
-                    LinkPair::new("myLink", "/my/path/to/link"),
-                    LinkPair::new("myOtherLink", "/my/path/to/Otherlink")
+                    LayeredLinkPair::new(LinkPair::new("myLink", "/my/path/to/link"), "personal"),
+                    LayeredLinkPair::new(LinkPair::new("myOtherLink", "/my/path/to/Otherlink"), "personal")
                 ],
                 &entries
             );
@@ -262,7 +521,7 @@ fn jump_target_success() {
 
     match program.jump_target(Link::new("myOtherLink")) {
         Ok(link) => {
-            assert_eq!(link, "/my/path/to/Otherlink".to_string());
+            assert_eq!(link, LinkTarget::new("/my/path/to/Otherlink"));
             assert_eq!(&Vec::<String>::new(), &output.into_inner())
         }
         Err(e) => panic!("{}: Expected an Ok but got err", e),
@@ -300,188 +559,1763 @@ fn jump_target_without_links() {
 }
 
 #[test]
-fn mark_dir_success() {
+fn jump_target_prefix_match() {
     let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![
+        LinkPair::new("myLink", "/my/path/to/link"),
+        LinkPair::new("otherLink", "/my/path/to/Otherlink"),
+    ];
 
-    let stub = TestStub::new(&output);
-    let program = TestStub::program(stub);
+    let stub = TestStub::with_read_links(&output, read_links);
+    let mut program = TestStub::program(stub);
+    program.config.match_mode = MatchMode::Prefix;
 
-    match program.mark_dir(&LinkPair::new("myLink", "/my/path/to/link")) {
-        Ok(_) => assert_eq!(&Vec::<String>::new(), &output.into_inner()),
+    match program.jump_target(Link::new("oth")) {
+        Ok(link) => assert_eq!(link, LinkTarget::new("/my/path/to/Otherlink")),
         Err(e) => panic!("{}: Expected an Ok but got err", e),
     }
 }
 
 #[test]
-fn mark_dir_dir_does_not_exist() {
+fn jump_target_prefix_match_prefers_exact_match() {
     let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![
+        LinkPair::new("myLinkOther", "/my/path/to/Otherlink"),
+        LinkPair::new("myLink", "/my/path/to/link"),
+    ];
 
-    let default = TestStub::new(&output);
-    let stub = TestStub {
-        dir_exists: false,
-        ..default
-    };
-    let program = TestStub::program(stub);
+    let stub = TestStub::with_read_links(&output, read_links);
+    let mut program = TestStub::program(stub);
+    program.config.match_mode = MatchMode::Prefix;
 
-    match program.mark_dir(&LinkPair::new("myLink", "/my/path/to/link")) {
-    Ok(_) => panic!("Expected an Err but got Ok"),
-    Err(e) => assert_eq!("A directory named `/my/path/to/link` does not exist or you do not have permission to it.", e.to_string()),
-  }
+    match program.jump_target(Link::new("myLink")) {
+        Ok(link) => assert_eq!(link, LinkTarget::new("/my/path/to/link")),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
 }
 
 #[test]
-fn mark_dir_link_exists() {
+fn jump_target_prefix_match_ambiguous() {
     let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![
+        LinkPair::new("myLink", "/my/path/to/link"),
+        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+    ];
 
-    let default = TestStub::new(&output);
-    let stub = TestStub {
-        link_exists: true,
-        ..default
-    };
-    let program = TestStub::program(stub);
+    let stub = TestStub::with_read_links(&output, read_links);
+    let mut program = TestStub::program(stub);
+    program.config.match_mode = MatchMode::Prefix;
 
-    match program.mark_dir(&LinkPair::new("myLink", "/my/path/to/link")) {
+    match program.jump_target(Link::new("my")) {
         Ok(_) => panic!("Expected an Err but got Ok"),
-        Err(e) => assert_eq!(
-            "A link named `myLink` already exists. Aborting mark creation.",
-            e.to_string()
-        ),
+        Err(e) => assert_eq!(e.to_string(), "`my` matches more than one link: myLink, myOtherLink"),
     }
 }
 
 #[test]
-fn mark_dir_write_link_failed() {
+fn jump_target_exact_match_does_not_match_prefix() {
     let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("myLink", "/my/path/to/link")];
 
-    //Is there a more succinct, yet readable way to do this?
-    let default = TestStub::new(&output);
-    let stub = TestStub {
-        write_link: Some("Could not create link because this is a test".to_string()),
-        ..default
-    };
+    let stub = TestStub::with_read_links(&output, read_links);
     let program = TestStub::program(stub);
 
-    match program.mark_dir(&LinkPair::new("myLink", "/my/path/to/link")) {
+    match program.jump_target(Link::new("my")) {
         Ok(_) => panic!("Expected an Err but got Ok"),
-        Err(e) => assert_eq!(
-            "Could not create link because this is a test",
-            e.to_string()
-        ),
+        Err(e) => assert_eq!(e.to_string(), "Could not find link: my"),
     }
 }
 
 #[test]
-fn delete_link_success() {
-    let output: Cell<Vec<String>> = Cell::new(vec![]);
-    let input: Cell<Vec<String>> = Cell::new(vec!["Y".to_string()]);
+fn tabulate_links_sorted_by_target() {
     let read_links = vec![
-        LinkPair::new("myLink", "/my/path/to/link"),
-        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+        LinkPair::new("a", "/z/path"),
+        LinkPair::new("b", "/a/path"),
     ];
 
-    let stub = TestStub::with_read_links_and_std_in(&output, read_links, &input);
-    let program = TestStub::program(stub);
+    let output = Cell::new(vec![]);
+    let stub = TestStub::with_read_links(&output, read_links);
+    let mut program = TestStub::program(stub);
+    program.config.sort = SortOrder::Target;
 
-    match program.delete_link(&Link::new("myLink")) {
-        Ok(result) => {
-            let expected = vec![
-                "Are you sure you want to delete myLink which links to /my/path/to/link ?"
-                    .to_string(),
-            ];
+    match program.tabulate_links() {
+        Ok(entries) => assert_eq!(
+            &vec![LinkPair::new("b", "/a/path"), LinkPair::new("a", "/z/path")],
+            &entries.into_iter().map(|lp| lp.link_pair).collect::<Vec<_>>()
+        ),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
 
-            assert_eq!(&expected, &output.into_inner());
-            assert_eq!(
-                result,
-                DeleteStatus::DeleteSucceeded(LinkPair::new("myLink", "/my/path/to/link"))
-            );
-            assert_eq!(&Vec::<String>::new(), &input.into_inner());
-        }
-        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+#[test]
+fn tabulate_links_personal_overrides_shared() {
+    let output = Cell::new(vec![]);
+    let default = TestStub::with_read_links(&output, vec![LinkPair::new("docs", "/my/docs")]);
+    let stub = TestStub {
+        shared_links: vec![
+            LinkPair::new("docs", "/team/docs"),
+            LinkPair::new("wiki", "/team/wiki"),
+        ],
+        ..default
+    };
+
+    let program = TestStub::layered_program(stub);
+
+    match program.tabulate_links() {
+        Ok(entries) => assert_eq!(
+            &vec![
+                LayeredLinkPair::new(LinkPair::new("docs", "/my/docs"), "personal"),
+                LayeredLinkPair::new(LinkPair::new("wiki", "/team/wiki"), "team")
+            ],
+            &entries
+        ),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
     }
 }
 
 #[test]
-fn delete_link_aborted() {
-    let output: Cell<Vec<String>> = Cell::new(vec![]);
-    let input: Cell<Vec<String>> = Cell::new(vec!["N".to_string()]);
-    let read_links = vec![
-        LinkPair::new("myLink", "/my/path/to/link"),
-        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
-    ];
+fn jump_target_local_link_resolves_relative_target() {
+    let output = Cell::new(vec![]);
+    let default = TestStub::with_read_links(&output, vec![LinkPair::new("api", "/my/api")]);
+    let stub = TestStub {
+        shared_links: vec![LinkPair::new("api", "services/api")],
+        ..default
+    };
 
-    let stub = TestStub::with_read_links_and_std_in(&output, read_links, &input);
-    let program = TestStub::program(stub);
+    //The read-only home is the project's .hop, so relative targets are resolved against the project directory
+    let program = HopProgram {
+        value: Test{ stub },
+        hop_homes: vec![
+            HomeLayer::local(PathBuf::from(SHARED_HOP_HOME)),
+            HomeLayer::personal(HomeType::Xdg)
+        ],
+        config: Config::default(),
+        prompts: PromptMode::Ask,
+    };
 
-    match program.delete_link(&Link::new("myLink")) {
-        Ok(result) => {
-            let expected = vec![
-                "Are you sure you want to delete myLink which links to /my/path/to/link ?"
-                    .to_string(),
-            ];
+    match program.jump_target(Link::new("api")) {
+        Ok(target) => assert_eq!(LinkTarget::new("/xyz/services/api"), target),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
 
-            assert_eq!(&expected, &output.into_inner());
-            assert_eq!(result, DeleteStatus::DeleteAborted);
-            assert_eq!(&Vec::<String>::new(), &input.into_inner());
-        }
-        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+#[test]
+fn list_links_skips_missing_shared_home() {
+    let output = Cell::new(vec![]);
+    let default = TestStub::with_read_links(&output, vec![LinkPair::new("code", "/my/code")]);
+    let stub = TestStub {
+        shared_links: vec![LinkPair::new("wiki", "/team/wiki")],
+        dir_exists: false,
+        ..default
+    };
+
+    let program = TestStub::layered_program(stub);
+
+    match program.list_links() {
+        Ok(entries) => assert_eq!(&vec![LinkPair::new("code", "/my/code")], &entries),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
     }
 }
 
 #[test]
-fn delete_link_link_not_found() {
-    let output: Cell<Vec<String>> = Cell::new(vec![]);
-    let input: Cell<Vec<String>> = Cell::new(vec!["N".to_string()]);
+fn list_links_sorted_by_name() {
     let read_links = vec![
-        LinkPair::new("myLink", "/my/path/to/link"),
-        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+        LinkPair::new("b", "/a/path"),
+        LinkPair::new("a", "/z/path"),
     ];
 
-    let stub = TestStub::with_read_links_and_std_in(&output, read_links, &input);
+    let output = Cell::new(vec![]);
+    let stub = TestStub::with_read_links(&output, read_links);
     let program = TestStub::program(stub);
 
-    match program.delete_link(&Link::new("notALink")) {
-        Ok(_) => panic!("Expected an Err but got Ok"),
-        Err(e) => {
-            assert_eq!(&Vec::<String>::new(), &output.into_inner());
-            assert_eq!(
-                "Could not find link named:`notALink` for deletion".to_string(),
-                e.to_string()
-            );
-            assert_eq!(&vec!["N"], &input.into_inner());
-        }
+    match program.list_links() {
+        Ok(entries) => assert_eq!(
+            &vec![LinkPair::new("a", "/z/path"), LinkPair::new("b", "/a/path")],
+            &entries
+        ),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
     }
 }
 
 #[test]
-fn delete_link_failed() {
+fn mark_dir_success() {
     let output: Cell<Vec<String>> = Cell::new(vec![]);
-    let input: Cell<Vec<String>> = Cell::new(vec!["Y".to_string()]);
-    let read_links = vec![
-        LinkPair::new("myLink", "/my/path/to/link"),
-        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
-    ];
-
-    let default = TestStub::with_read_links_and_std_in(&output, read_links, &input);
-    let stub = TestStub {
-        delete_link: SymLinkDeleteStatus::Failed,
-        ..default
-    };
 
+    let stub = TestStub::new(&output);
     let program = TestStub::program(stub);
-    match program.delete_link(&Link::new("myLink")) {
-        Ok(_) => panic!("Expected Err but got Ok"),
-        Err(e) => {
-            let expected = vec![
-                "Are you sure you want to delete myLink which links to /my/path/to/link ?"
-                    .to_string(),
-            ];
 
-            assert_eq!(
-                "Failed to delete: myLink -> /my/path/to/link",
-                e.to_string()
+    match program.mark_dir(&LinkPair::new("myLink", "/my/path/to/link")) {
+        Ok(_) => assert_eq!(&Vec::<String>::new(), &output.into_inner()),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn mark_dir_if_missing_skips_existing_link() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("myLink", "/elsewhere")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    match program.mark_dir_if_missing(&LinkPair::new("myLink", "/my/path/to/link")) {
+        Ok(target) => {
+            assert_eq!(None, target);
+            assert_eq!(Vec::<PathBuf>::new(), program.value.stub.written_targets.take())
+        },
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn mark_dir_if_missing_ignores_links_starting_with_the_name() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("myLinkOld", "/elsewhere")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    match program.mark_dir_if_missing(&LinkPair::new("myLink", "/my/path/to/link")) {
+        Ok(target) => {
+            assert_eq!(Some(PathBuf::from("/my/path/to/link")), target);
+            assert_eq!(vec![PathBuf::from("/my/path/to/link")], program.value.stub.written_targets.take())
+        },
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn infer_link_pair_names_link_after_target() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("My-Project", "/elsewhere"), LinkPair::new("My-Project-2", "/elsewhere/2")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    match program.infer_link_pair(None, Some(LinkTarget::new("/code/My Project/./")), false) {
+        Ok(pair) => assert_eq!(LinkPair::new("My-Project-3", "/code/My Project"), pair),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn infer_link_pair_keeps_given_name() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("code", "/elsewhere")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    //A name that is given is never changed, so marking it fails as before
+    match program.infer_link_pair(Some(Link::new("code")), Some(LinkTarget::new("/my/code")), false) {
+        Ok(pair) => assert_eq!(LinkPair::new("code", "/my/code"), pair),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+
+    match program.infer_link_pair(Some(Link::new("here")), None, false) {
+        Ok(pair) => assert_eq!(LinkPair::new("here", "."), pair),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn infer_link_pair_for_root() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    let stub = TestStub::new(&output);
+    let program = TestStub::program(stub);
+
+    match program.infer_link_pair(None, Some(LinkTarget::new("/")), false) {
+        Ok(pair) => panic!("Expected an Err but got Ok: {}", pair),
+        Err(e) => assert_eq!("A link can't be named after `/`. Please give a name.", e.to_string()),
+    }
+}
+
+#[test]
+fn infer_link_pair_marks_git_root() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        git_root: Some(PathBuf::from("/code/hop")),
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+
+    match program.infer_link_pair(None, Some(LinkTarget::new("/code/hop/src/algebra")), true) {
+        Ok(pair) => assert_eq!(LinkPair::new("hop", "/code/hop"), pair),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+
+    match program.infer_link_pair(Some(Link::new("h")), Some(LinkTarget::new("/code/hop")), true) {
+        Ok(pair) => assert_eq!(LinkPair::new("h", "/code/hop"), pair),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+
+    match program.infer_link_pair(None, Some(LinkTarget::new("/code/other")), true) {
+        Ok(pair) => panic!("Expected an Err but got Ok: {}", pair),
+        Err(e) => assert_eq!("`/code/other` is not in a git repository.", e.to_string()),
+    }
+}
+
+#[test]
+fn scan_dirs_proposes_unmarked_repositories() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("hop", "/code/other/hop"), LinkPair::new("api", "/code/work/api")];
+    let default = TestStub::with_read_links(&output, read_links);
+    let stub = TestStub {
+        projects: vec![
+            (PathBuf::from("/code/hop"), true),
+            (PathBuf::from("/code/my app"), true),
+            (PathBuf::from("/code/rusty"), false),
+            (PathBuf::from("/code/work/api"), true),
+            (PathBuf::from("/elsewhere/repo"), true),
+        ],
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+    let options = ScanOptions { depth: 3, projects: false, tag: None };
+
+    match program.scan_dirs(&LinkTarget::new("/code"), &options) {
+        Ok(pairs) => assert_eq!(
+            vec![LinkPair::new("hop-2", "/code/hop"), LinkPair::new("my-app", "/code/my app")],
+            pairs
+        ),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn scan_dirs_with_projects_and_tag() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("w-hop", "/elsewhere/hop")];
+    let default = TestStub::with_read_links(&output, read_links);
+    let stub = TestStub {
+        projects: vec![(PathBuf::from("/code/hop"), true), (PathBuf::from("/code/rusty"), false)],
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+    let options = ScanOptions { depth: 3, projects: true, tag: Some("w".to_string()) };
+
+    match program.scan_dirs(&LinkTarget::new("/code"), &options) {
+        Ok(pairs) => assert_eq!(
+            vec![LinkPair::new("w-hop-2", "/code/hop"), LinkPair::new("w-rusty", "/code/rusty")],
+            pairs
+        ),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn mark_links_records_one_change() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let stub = TestStub::new(&output);
+    let program = TestStub::program(stub);
+
+    let pairs = vec![LinkPair::new("hop", "/code/hop"), LinkPair::new("api", "/code/api")];
+
+    match program.mark_links(&pairs) {
+        Ok(marked_pairs) => {
+            assert_eq!(pairs, marked_pairs);
+            assert_eq!(
+                vec![journal_entry("Mark 2 links", pairs.iter().map(LinkChange::added).collect())],
+                program.journal().expect("expected a journal")
+            )
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn mark_links_rolls_back_when_a_link_fails() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        failing_target: Some(PathBuf::from("/code/api")),
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+
+    let pairs = vec![LinkPair::new("hop", "/code/hop"), LinkPair::new("api", "/code/api")];
+
+    //The hop link is removed again when the api link fails
+    match program.mark_links(&pairs) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => {
+            assert_eq!("Could not change `api`. No links were changed.\nDisk full", e.to_string());
+            assert_eq!(
+                vec![PathBuf::from("/code/hop"), PathBuf::from("/code/api")],
+                program.value.stub.written_targets.take()
             );
+            assert!(program.journal().expect("expected a journal").is_empty())
+        },
+    }
+}
+
+#[test]
+fn mark_dir_commits_to_git() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    let stub = TestStub::new(&output);
+    let mut program = TestStub::program(stub);
+    program.config.git = true;
+
+    match program.mark_dir(&LinkPair::new("myLink", "/my/path/to/link")) {
+        Ok(_) => assert_eq!(vec!["Mark myLink -> /my/path/to/link".to_string()], program.value.stub.commits.take()),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn mark_dir_home_relative_target() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    let stub = TestStub::new(&output);
+    let mut program = TestStub::program(stub);
+    program.config.targets = TargetPaths::Home;
+
+    match program.mark_dir(&LinkPair::new("myLink", "/xyz/code/project")) {
+        Ok(target) => {
+            assert_eq!(PathBuf::from("/xyz/code/project"), target);
+            assert_eq!(vec![PathBuf::from("~/code/project")], program.value.stub.written_targets.take())
+        },
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn mark_dir_hop_home_relative_target() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    let stub = TestStub::new(&output);
+    let mut program = TestStub::program(stub);
+    program.config.targets = TargetPaths::HopHome;
+
+    match program.mark_dir(&LinkPair::new("myLink", "/xyz/code/project")) {
+        Ok(target) => {
+            assert_eq!(PathBuf::from("/xyz/code/project"), target);
+            assert_eq!(vec![PathBuf::from("../code/project")], program.value.stub.written_targets.take())
+        },
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn list_links_resolves_hop_home_relative_targets() {
+    let output = Cell::new(vec![]);
+    let stub = TestStub::with_read_links(&output, vec![LinkPair::new("project", "../code/./project")]);
+    let program = TestStub::program(stub);
+
+    match program.tabulate_links() {
+        Ok(entries) => assert_eq!(
+            vec![LayeredLinkPair::new(LinkPair::new("project", "/xyz/code/project"), "personal")],
+            entries
+        ),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn mark_dir_dir_does_not_exist() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        dir_exists: false,
+        ..default
+    };
+    let program = TestStub::program(stub);
+
+    match program.mark_dir(&LinkPair::new("myLink", "/my/path/to/link")) {
+    Ok(_) => panic!("Expected an Err but got Ok"),
+    Err(e) => assert_eq!("A directory named `/my/path/to/link` does not exist or you do not have permission to it.", e.to_string()),
+  }
+}
+
+#[test]
+fn mark_dir_link_exists() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        link_exists: true,
+        ..default
+    };
+    let program = TestStub::program(stub);
+
+    match program.mark_dir(&LinkPair::new("myLink", "/my/path/to/link")) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => assert_eq!(
+            "A link named `myLink` already exists. Aborting mark creation.",
+            e.to_string()
+        ),
+    }
+}
+
+#[test]
+fn mark_dir_hop_home_locked() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        lock_holder: Some(1234),
+        ..default
+    };
+    let program = TestStub::program(stub);
+
+    match program.mark_dir(&LinkPair::new("myLink", "/my/path/to/link")) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => {
+            assert_eq!("The hop home is locked by pid 1234. Try again once it has finished.", e.to_string());
+            assert_eq!(Vec::<PathBuf>::new(), program.value.stub.written_targets.take())
+        },
+    }
+}
+
+#[test]
+fn mark_dir_write_link_failed() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    //Is there a more succinct, yet readable way to do this?
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        write_link: Some("Could not create link because this is a test".to_string()),
+        ..default
+    };
+    let program = TestStub::program(stub);
+
+    match program.mark_dir(&LinkPair::new("myLink", "/my/path/to/link")) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => assert_eq!(
+            "Could not create link because this is a test",
+            e.to_string()
+        ),
+    }
+}
+
+#[test]
+fn delete_link_success() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let input: Cell<Vec<String>> = Cell::new(vec!["Y".to_string()]);
+    let read_links = vec![
+        LinkPair::new("myLink", "/my/path/to/link"),
+        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+    ];
+
+    let stub = TestStub::with_read_links_and_std_in(&output, read_links, &input);
+    let program = TestStub::program(stub);
+
+    match program.delete_links(&[Link::new("myLink")]) {
+        Ok(result) => {
+            let expected = vec![
+                "Are you sure you want to delete myLink which links to /my/path/to/link? [y/N]"
+                    .to_string(),
+            ];
+
             assert_eq!(&expected, &output.into_inner());
-            assert_eq!(&Vec::<String>::new(), &input.into_inner())
+            assert_eq!(
+                result,
+                DeleteStatus::DeleteCompleted(vec![DeleteOutcome::Deleted(LinkPair::new("myLink", "/my/path/to/link"))])
+            );
+            assert_eq!(&Vec::<String>::new(), &input.into_inner());
+        }
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn delete_link_moves_link_to_trash() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("myLink", "/my/path/to/link")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let mut program = TestStub::program(stub);
+    program.config.confirm = false;
+
+    match program.delete_links(&[Link::new("myLink")]) {
+        Ok(_) => {
+            let expected = vec![trashed_link(&LinkPair::new("myLink", "/my/path/to/link"))];
+            assert_eq!(expected, program.trashed_links().expect("expected trashed links"))
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn restore_link_success() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let pair = LinkPair::new("myLink", "/my/path/to/link");
+
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        trash: Cell::new(vec![trashed_link(&pair)]),
+        ..default
+    };
+    let program = TestStub::program(stub);
+
+    match program.restore_link(&Link::new("myLink")) {
+        Ok(restored) => {
+            assert_eq!(pair, restored);
+            assert_eq!(Vec::<TrashedLink>::new(), program.value.stub.trash.take());
+            assert_eq!(
+                vec![journal_entry("Restore myLink -> /my/path/to/link", vec![LinkChange::added(&pair)])],
+                program.value.stub.journal.take()
+            )
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn restore_link_to_existing_link() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let pair = LinkPair::new("myLink", "/my/path/to/link");
+
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        trash: Cell::new(vec![trashed_link(&pair)]),
+        link_exists: true,
+        ..default
+    };
+    let program = TestStub::program(stub);
+
+    match program.restore_link(&Link::new("myLink")) {
+        Err(e) => assert_eq!(e.to_string(), "A link named `myLink` already exists. Aborting restore."),
+        Ok(_) => panic!("Expected an Err but got Ok"),
+    }
+}
+
+#[test]
+fn restore_link_not_in_trash() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    let stub = TestStub::new(&output);
+    let program = TestStub::program(stub);
+
+    match program.restore_link(&Link::new("myLink")) {
+        Err(e) => assert_eq!(e.to_string(), "Could not find link named:`myLink` in the trash"),
+        Ok(_) => panic!("Expected an Err but got Ok"),
+    }
+}
+
+#[test]
+fn delete_link_aborted() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let input: Cell<Vec<String>> = Cell::new(vec!["N".to_string()]);
+    let read_links = vec![
+        LinkPair::new("myLink", "/my/path/to/link"),
+        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+    ];
+
+    let stub = TestStub::with_read_links_and_std_in(&output, read_links, &input);
+    let program = TestStub::program(stub);
+
+    match program.delete_links(&[Link::new("myLink")]) {
+        Ok(result) => {
+            let expected = vec![
+                "Are you sure you want to delete myLink which links to /my/path/to/link? [y/N]"
+                    .to_string(),
+            ];
+
+            assert_eq!(&expected, &output.into_inner());
+            assert_eq!(result, DeleteStatus::DeleteAborted);
+            assert_eq!(&Vec::<String>::new(), &input.into_inner());
+        }
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn delete_link_without_confirmation() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("myLink", "/my/path/to/link")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let mut program = TestStub::program(stub);
+    program.config.confirm = false;
+
+    match program.delete_links(&[Link::new("myLink")]) {
+        Ok(result) => {
+            assert_eq!(&Vec::<String>::new(), &output.into_inner());
+            assert_eq!(
+                result,
+                DeleteStatus::DeleteCompleted(vec![DeleteOutcome::Deleted(LinkPair::new("myLink", "/my/path/to/link"))])
+            );
+        }
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn delete_link_assuming_yes() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("myLink", "/my/path/to/link")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let mut program = TestStub::program(stub);
+    program.prompts = PromptMode::AssumeYes;
+
+    match program.delete_links(&[Link::new("myLink")]) {
+        Ok(result) => {
+            assert_eq!(&Vec::<String>::new(), &output.into_inner());
+            assert_eq!(
+                result,
+                DeleteStatus::DeleteCompleted(vec![DeleteOutcome::Deleted(LinkPair::new("myLink", "/my/path/to/link"))])
+            );
+        }
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn delete_link_empty_answer_takes_default() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let input: Cell<Vec<String>> = Cell::new(vec!["".to_string()]);
+    let read_links = vec![LinkPair::new("myLink", "/my/path/to/link")];
+
+    let stub = TestStub::with_read_links_and_std_in(&output, read_links, &input);
+    let program = TestStub::program(stub);
+
+    match program.delete_links(&[Link::new("myLink")]) {
+        Ok(result) => assert_eq!(result, DeleteStatus::DeleteAborted),
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn delete_link_without_input_takes_default() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("myLink", "/my/path/to/link")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let mut program = TestStub::program(stub);
+    program.prompts = PromptMode::UseDefault;
+
+    match program.delete_links(&[Link::new("myLink")]) {
+        Ok(result) => {
+            assert_eq!(&Vec::<String>::new(), &output.into_inner());
+            assert_eq!(result, DeleteStatus::DeleteAborted);
+        }
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn delete_links_matching_patterns() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let input: Cell<Vec<String>> = Cell::new(vec!["y".to_string()]);
+    let read_links = vec![
+        LinkPair::new("tmp-1", "/tmp/one"),
+        LinkPair::new("code", "/my/code"),
+        LinkPair::new("tmp-2", "/tmp/two"),
+        LinkPair::new("old", "/my/old"),
+    ];
+
+    let stub = TestStub::with_read_links_and_std_in(&output, read_links, &input);
+    let program = TestStub::program(stub);
+
+    match program.delete_links(&[Link::new("tmp-*"), Link::new("old"), Link::new("tmp-?")]) {
+        Ok(result) => {
+            let expected = vec![
+                "  tmp-1 -> /tmp/one".to_string(),
+                "  tmp-2 -> /tmp/two".to_string(),
+                "  old -> /my/old".to_string(),
+                "Are you sure you want to delete these 3 links? [y/N]".to_string(),
+            ];
+
+            assert_eq!(&expected, &output.take());
+            assert_eq!(
+                result,
+                DeleteStatus::DeleteCompleted(vec![
+                    DeleteOutcome::Deleted(LinkPair::new("tmp-1", "/tmp/one")),
+                    DeleteOutcome::Deleted(LinkPair::new("tmp-2", "/tmp/two")),
+                    DeleteOutcome::Deleted(LinkPair::new("old", "/my/old")),
+                ])
+            );
+
+            let journal = program.journal().expect("expected a journal");
+            assert_eq!(1, journal.len());
+            assert_eq!("Delete 3 links", journal[0].description);
+        }
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn delete_links_pattern_not_matched() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("tmp-1", "/tmp/one"), LinkPair::new("code", "/my/code")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    match program.delete_links(&[Link::new("tmp-*"), Link::new("scratch*")]) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => {
+            assert_eq!(&Vec::<String>::new(), &output.take());
+            assert_eq!("No links match `scratch*` for deletion", e.to_string());
+            assert!(program.trashed_links().expect("expected trashed links").is_empty())
         }
     }
 }
+
+#[test]
+fn delete_links_pattern_skips_shared_home() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let default = TestStub::with_read_links(&output, vec![LinkPair::new("docs-mine", "/my/docs")]);
+    let stub = TestStub {
+        shared_links: vec![LinkPair::new("docs-team", "/team/docs")],
+        ..default
+    };
+
+    let mut program = TestStub::layered_program(stub);
+    program.prompts = PromptMode::AssumeYes;
+
+    match program.delete_links(&[Link::new("docs-*")]) {
+        Ok(result) => assert_eq!(
+            result,
+            DeleteStatus::DeleteCompleted(vec![DeleteOutcome::Deleted(LinkPair::new("docs-mine", "/my/docs"))])
+        ),
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn glob_matches_link_names() {
+    assert!(super::glob_matches(b"tmp-*", b"tmp-"));
+    assert!(super::glob_matches(b"tmp-*", b"tmp-scratch"));
+    assert!(super::glob_matches(b"*-old", b"code-old"));
+    assert!(super::glob_matches(b"t?p*x", b"tmp-x"));
+    assert!(!super::glob_matches(b"tmp-*", b"tm"));
+    assert!(!super::glob_matches(b"t?p", b"tp"));
+    assert!(!super::glob_matches(b"*-old", b"code-older"));
+}
+
+#[test]
+fn run_batch_applies_every_operation() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("old", "/my/old"), LinkPair::new("code", "/my/code")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    let ops = vec![
+        BatchOp::Mark(LinkPair::new("proj", "/my/proj")),
+        BatchOp::Rename(Link::new("old"), Link::new("older")),
+        BatchOp::Retarget(LinkPair::new("code", "/my/new-code")),
+        BatchOp::Delete(Link::new("older")),
+    ];
+
+    match program.run_batch(&ops) {
+        Ok(outcomes) => {
+            assert_eq!(vec![BatchOutcome::Applied; 4], outcomes);
+            assert_eq!(vec![trashed_link(&LinkPair::new("older", "/my/old"))], program.value.stub.trash.take());
+
+            let expected_changes = vec![
+                LinkChange::added(&LinkPair::new("proj", "/my/proj")),
+                LinkChange::added(&LinkPair::new("older", "/my/old")),
+                LinkChange::removed(&LinkPair::new("old", "/my/old")),
+                LinkChange::retargeted(&LinkPair::new("code", "/my/code"), &LinkTarget::new("/my/new-code")),
+                LinkChange::removed(&LinkPair::new("older", "/my/old")),
+            ];
+
+            assert_eq!(
+                vec![journal_entry("Batch of 4 operations", expected_changes)],
+                program.journal().expect("expected a journal")
+            )
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn run_batch_checks_every_operation_first() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("code", "/my/code")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    let ops = vec![
+        BatchOp::Mark(LinkPair::new("proj", "/my/proj")),
+        BatchOp::Delete(Link::new("missing")),
+        BatchOp::Rename(Link::new("code"), Link::new("proj")),
+    ];
+
+    match program.run_batch(&ops) {
+        Ok(outcomes) => {
+            let expected = vec![
+                BatchOutcome::NotApplied,
+                BatchOutcome::Invalid("Could not find link named:`missing` in /xyz/.your-hop".to_string()),
+                BatchOutcome::Invalid("A link named `proj` already exists.".to_string()),
+            ];
+
+            assert_eq!(expected, outcomes);
+            assert_eq!(Vec::<PathBuf>::new(), program.value.stub.written_targets.take());
+            assert!(program.journal().expect("expected a journal").is_empty())
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn run_batch_rolls_back_when_an_operation_fails() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("code", "/my/code")];
+
+    let default = TestStub::with_read_links(&output, read_links);
+    let stub = TestStub {
+        delete_link: SymLinkDeleteStatus::Failed,
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+
+    let ops = vec![
+        BatchOp::Mark(LinkPair::new("proj", "/my/proj")),
+        BatchOp::Delete(Link::new("code")),
+        BatchOp::Mark(LinkPair::new("docs", "/my/docs")),
+    ];
+
+    //Reverting the mark deletes it, which fails as well
+    match program.run_batch(&ops) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => {
+            assert_eq!(
+                "`delete code` failed and the operations before it could not be reverted.\n\
+                 Failed to delete: proj -> /my/proj\n\
+                 Failed to delete: code -> /my/code",
+                e.to_string()
+            );
+            assert!(program.journal().expect("expected a journal").is_empty())
+        },
+    }
+}
+
+#[test]
+fn run_batch_restores_deleted_links_from_trash_on_rollback() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("code", "/my/code")];
+
+    let default = TestStub::with_read_links(&output, read_links);
+    let stub = TestStub {
+        failing_target: Some(PathBuf::from("/my/docs")),
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+
+    let ops = vec![
+        BatchOp::Delete(Link::new("code")),
+        BatchOp::Mark(LinkPair::new("docs", "/my/docs")),
+    ];
+
+    //The deleted link goes to the trash and is taken back out of it when the mark fails
+    match program.run_batch(&ops) {
+        Ok(outcomes) => {
+            assert_eq!(vec![BatchOutcome::RolledBack, BatchOutcome::Failed("Disk full".to_string())], outcomes);
+            assert!(program.value.stub.trash.take().is_empty());
+            assert_eq!(vec![PathBuf::from("/my/docs")], program.value.stub.written_targets.take());
+            assert!(program.journal().expect("expected a journal").is_empty())
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn delete_link_in_shared_home() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        shared_links: vec![LinkPair::new("docs", "/team/docs")],
+        ..default
+    };
+
+    let program = TestStub::layered_program(stub);
+
+    match program.delete_links(&[Link::new("docs")]) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => {
+            assert_eq!(&Vec::<String>::new(), &output.into_inner());
+            assert_eq!(
+                "The link `docs` is in the read-only hop home `team` and can't be deleted.",
+                e.to_string()
+            )
+        }
+    }
+}
+
+#[test]
+fn delete_link_link_not_found() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let input: Cell<Vec<String>> = Cell::new(vec!["N".to_string()]);
+    let read_links = vec![
+        LinkPair::new("myLink", "/my/path/to/link"),
+        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+    ];
+
+    let stub = TestStub::with_read_links_and_std_in(&output, read_links, &input);
+    let program = TestStub::program(stub);
+
+    match program.delete_links(&[Link::new("notALink")]) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => {
+            assert_eq!(&Vec::<String>::new(), &output.into_inner());
+            assert_eq!(
+                "Could not find link named:`notALink` for deletion".to_string(),
+                e.to_string()
+            );
+            assert_eq!(&vec!["N"], &input.into_inner());
+        }
+    }
+}
+
+#[test]
+fn delete_link_failed() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let input: Cell<Vec<String>> = Cell::new(vec!["Y".to_string()]);
+    let read_links = vec![
+        LinkPair::new("myLink", "/my/path/to/link"),
+        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+    ];
+
+    let default = TestStub::with_read_links_and_std_in(&output, read_links, &input);
+    let stub = TestStub {
+        delete_link: SymLinkDeleteStatus::Failed,
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+    match program.delete_links(&[Link::new("myLink")]) {
+        Ok(result) => {
+            let expected = vec![
+                "Are you sure you want to delete myLink which links to /my/path/to/link? [y/N]"
+                    .to_string(),
+            ];
+
+            assert_eq!(
+                result,
+                DeleteStatus::DeleteCompleted(vec![DeleteOutcome::Failed(
+                    LinkPair::new("myLink", "/my/path/to/link"),
+                    "Failed to delete: myLink -> /my/path/to/link".to_string()
+                )])
+            );
+            assert_eq!(&expected, &output.into_inner());
+            assert_eq!(&Vec::<String>::new(), &input.into_inner())
+        }
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn migrate_home_success() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![
+        LinkPair::new("myLink", "/my/path/to/link"),
+        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+    ];
+
+    let default = TestStub::with_read_links(&output, read_links);
+    let stub = TestStub {
+        legacy_hop_home: Some(PathBuf::from("/xyz/.hop")),
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+
+    match program.migrate_home() {
+        Ok(migration) => assert_eq!(
+            migration,
+            HomeMigration {
+                from: PathBuf::from("/xyz/.hop"),
+                to: PathBuf::from("/xyz/.local/share/hop"),
+                links: vec![
+                    LinkPair::new("myLink", "/my/path/to/link"),
+                    LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+                ]
+            }
+        ),
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn migrate_home_restores_relative_targets() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![
+        LinkPair::new("myLink", "../code/project"),
+        LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+    ];
+
+    let default = TestStub::with_read_links(&output, read_links);
+    let stub = TestStub {
+        legacy_hop_home: Some(PathBuf::from("/xyz/.hop")),
+        ..default
+    };
+
+    let mut program = TestStub::program(stub);
+    program.config.targets = TargetPaths::HopHome;
+
+    match program.migrate_home() {
+        Ok(migration) => {
+            assert_eq!(
+                migration.links,
+                vec![
+                    LinkPair::new("myLink", "/xyz/code/project"),
+                    LinkPair::new("myOtherLink", "/my/path/to/Otherlink"),
+                ]
+            );
+            assert_eq!(vec![PathBuf::from("../../../code/project")], program.value.stub.written_targets.take())
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn migrate_home_without_legacy_home() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let stub = TestStub::new(&output);
+    let program = TestStub::program(stub);
+
+    match program.migrate_home() {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => assert_eq!(
+            "Could not find a legacy hop home at ~/.hop to migrate.",
+            e.to_string()
+        ),
+    }
+}
+
+#[test]
+fn migrate_home_link_exists() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("myLink", "/my/path/to/link")];
+
+    let default = TestStub::with_read_links(&output, read_links);
+    let stub = TestStub {
+        legacy_hop_home: Some(PathBuf::from("/xyz/.hop")),
+        link_exists: true,
+        delete_link: SymLinkDeleteStatus::Failed,
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+
+    match program.migrate_home() {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => assert_eq!(
+            "A link named `myLink` already exists in /xyz/.local/share/hop. Aborting migration.",
+            e.to_string()
+        ),
+    }
+}
+
+#[test]
+fn list_profiles_includes_config_profiles() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let stub = TestStub::new(&output);
+    let mut program = TestStub::program(stub);
+    program.config.profiles.insert("client".to_string(), PathBuf::from("/xyz/client-hop"));
+    program.config.profiles.insert("work".to_string(), PathBuf::from("/xyz/work-hop"));
+
+    match program.list_profiles() {
+        Ok(profiles) => assert_eq!(profiles, vec!["client", "personal", "work"]),
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+fn import_options(on_conflict: ConflictPolicy) -> ImportOptions {
+    ImportOptions {
+        on_conflict,
+        dry_run: false,
+        allow_missing: false,
+    }
+}
+
+#[test]
+fn import_links_with_conflicts() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![
+        LinkPair::new("code", "/my/code"),
+        LinkPair::new("docs", "/my/docs"),
+    ];
+
+    let imported = || vec![
+        LinkPair::new("code", "/my/code"),
+        LinkPair::new("docs", "/their/docs"),
+        LinkPair::new("web", "/their/web"),
+    ];
+
+    let expected = |conflict_action: ImportAction| vec![
+        ImportAction::Skip(LinkPair::new("code", "/my/code")),
+        conflict_action,
+        ImportAction::Create(LinkPair::new("web", "/their/web")),
+    ];
+
+    let policies = vec![
+        (ConflictPolicy::Skip, ImportAction::Skip(LinkPair::new("docs", "/their/docs"))),
+        (ConflictPolicy::Overwrite, ImportAction::Update(LinkPair::new("docs", "/their/docs"))),
+        (ConflictPolicy::Rename, ImportAction::Rename(Link::new("docs"), LinkPair::new("docs-2", "/their/docs"))),
+    ];
+
+    for (policy, conflict_action) in policies {
+        let stub = TestStub::with_read_links(&output, read_links.clone());
+        let program = TestStub::program(stub);
+
+        match program.import_links(imported(), &import_options(policy)) {
+            Ok(actions) => assert_eq!(expected(conflict_action), actions),
+            Err(e) => panic!("{}: Expected an Ok but got err", e),
+        }
+    }
+}
+
+#[test]
+fn import_links_renames_past_imported_names() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("docs", "/my/docs")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    let imported = vec![
+        LinkPair::new("docs-2", "/their/old-docs"),
+        LinkPair::new("docs", "/their/docs"),
+    ];
+
+    match program.import_links(imported, &import_options(ConflictPolicy::Rename)) {
+        Ok(actions) => assert_eq!(
+            vec![
+                ImportAction::Create(LinkPair::new("docs-2", "/their/old-docs")),
+                ImportAction::Rename(Link::new("docs"), LinkPair::new("docs-3", "/their/docs")),
+            ],
+            actions
+        ),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn import_links_takes_snapshot() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("code", "/my/code")];
+
+    let stub = TestStub::with_read_links(&output, read_links.clone());
+    let program = TestStub::program(stub);
+
+    match program.import_links(vec![LinkPair::new("web", "/their/web")], &import_options(ConflictPolicy::Skip)) {
+        Ok(_) => assert_eq!(vec![snapshot("snapshot-1", Some("before import"), read_links)], program.value.stub.snapshots.take()),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn import_links_rolls_back_when_a_link_fails() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("web", "/my/web")];
+
+    let default = TestStub::with_read_links(&output, read_links);
+    let stub = TestStub {
+        failing_target: Some(PathBuf::from("/their/web")),
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+
+    let imported = vec![
+        LinkPair::new("docs", "/their/docs"),
+        LinkPair::new("web", "/their/web"),
+    ];
+
+    //The existing web link is written back and the new docs link is removed
+    match program.import_links(imported, &import_options(ConflictPolicy::Overwrite)) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => {
+            assert_eq!("Could not change `web`. No links were changed.\nDisk full", e.to_string());
+            assert_eq!(
+                vec![PathBuf::from("/their/docs"), PathBuf::from("/their/web"), PathBuf::from("/my/web")],
+                program.value.stub.written_targets.take()
+            );
+            assert!(program.journal().expect("expected a journal").is_empty())
+        },
+    }
+}
+
+#[test]
+fn import_links_fails_on_conflict() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("docs", "/my/docs")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    let imported = vec![
+        LinkPair::new("web", "/their/web"),
+        LinkPair::new("docs", "/their/docs"),
+    ];
+
+    match program.import_links(imported, &import_options(ConflictPolicy::Fail)) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => {
+            assert_eq!("A link named `docs` already exists. Aborting import.", e.to_string());
+            assert_eq!(Vec::<PathBuf>::new(), program.value.stub.written_targets.take())
+        },
+    }
+}
+
+#[test]
+fn import_links_with_missing_targets() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        dir_exists: false,
+        ..default
+    };
+
+    let program = TestStub::program(stub);
+    let imported = || vec![LinkPair::new("web", "/their/web")];
+
+    match program.import_links(imported(), &import_options(ConflictPolicy::Skip)) {
+        Ok(_) => panic!("Expected an Err but got Ok"),
+        Err(e) => assert_eq!(
+            "These targets do not exist or you do not have permission to them. Aborting import.\nweb -> /their/web",
+            e.to_string()
+        ),
+    }
+
+    let options = ImportOptions { allow_missing: true, ..import_options(ConflictPolicy::Skip) };
+
+    match program.import_links(imported(), &options) {
+        Ok(actions) => {
+            assert_eq!(vec![ImportAction::Create(LinkPair::new("web", "/their/web"))], actions);
+            assert_eq!(vec![PathBuf::from("/their/web")], program.value.stub.written_targets.take())
+        },
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn import_links_dry_run() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let stub = TestStub::new(&output);
+    let program = TestStub::program(stub);
+
+    let options = ImportOptions { dry_run: true, ..import_options(ConflictPolicy::Skip) };
+
+    match program.import_links(vec![LinkPair::new("web", "/their/web")], &options) {
+        Ok(actions) => {
+            assert_eq!(vec![ImportAction::Create(LinkPair::new("web", "/their/web"))], actions);
+            assert_eq!(Vec::<PathBuf>::new(), program.value.stub.written_targets.take())
+        },
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+fn diff_read_links() -> Vec<LinkPair> {
+    vec![
+        LinkPair::new("code", "/xyz/code"),
+        LinkPair::new("docs", "/xyz/docs"),
+        LinkPair::new("proj", "/xyz/project"),
+        LinkPair::new("mine", "/xyz/mine"),
+    ]
+}
+
+fn diff_other_links() -> Vec<LinkPair> {
+    vec![
+        LinkPair::new("code", "/xyz/code"),
+        LinkPair::new("docs", "/xyz/documents"),
+        LinkPair::new("project", "/xyz/project"),
+        LinkPair::new("wiki", "/xyz/wiki"),
+    ]
+}
+
+#[test]
+fn diff_links_with_other_links() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let stub = TestStub::with_read_links(&output, diff_read_links());
+    let program = TestStub::program(stub);
+
+    match program.diff_links(diff_other_links()) {
+        Ok(diff) => assert_eq!(
+            LinkDiff {
+                only_here: vec![LinkPair::new("mine", "/xyz/mine")],
+                only_there: vec![LinkPair::new("wiki", "/xyz/wiki")],
+                different_targets: vec![(LinkPair::new("docs", "/xyz/docs"), LinkPair::new("docs", "/xyz/documents"))],
+                different_names: vec![(LinkPair::new("proj", "/xyz/project"), LinkPair::new("project", "/xyz/project"))],
+            },
+            diff
+        ),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn merge_links_with_chosen_sides() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let input: Cell<Vec<String>> = Cell::new(vec!["y".to_string(), "n".to_string()]);
+    let stub = TestStub::with_read_links_and_std_in(&output, diff_read_links(), &input);
+    let program = TestStub::program(stub);
+
+    match program.merge_links(diff_other_links(), "team.json") {
+        Ok(actions) => {
+            assert_eq!(
+                vec![
+                    ImportAction::Create(LinkPair::new("wiki", "/xyz/wiki")),
+                    ImportAction::Update(LinkPair::new("docs", "/xyz/documents")),
+                    ImportAction::Skip(LinkPair::new("proj", "/xyz/project")),
+                ],
+                actions
+            );
+            assert_eq!(
+                vec![
+                    "`docs` links to /xyz/docs here and to /xyz/documents in team.json. Use the target from team.json? [y/N]".to_string(),
+                    "/xyz/project is marked as `proj` here and as `project` in team.json. Use the name from team.json? [y/N]".to_string(),
+                ],
+                output.take()
+            );
+            assert_eq!(vec![PathBuf::from("/xyz/wiki"), PathBuf::from("/xyz/documents")], program.value.stub.written_targets.take())
+        },
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn suggest_links_for_unmarked_dirs() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let input: Cell<Vec<String>> = Cell::new(vec!["y".to_string(), "n".to_string(), "q".to_string()]);
+    let read_links = vec![LinkPair::new("api", "/my/api")];
+
+    let stub = TestStub::with_read_links_and_std_in(&output, read_links, &input);
+    let program = TestStub::program(stub);
+
+    let visits = vec![
+        DirVisit { dir: PathBuf::from("/my/api"), score: 100.0 },
+        DirVisit { dir: PathBuf::from("/their/api"), score: 50.0 },
+        DirVisit { dir: PathBuf::from("/my/web"), score: 20.0 },
+        DirVisit { dir: PathBuf::from("/my/docs"), score: 10.0 },
+        DirVisit { dir: PathBuf::from("/my/other"), score: 5.0 },
+    ];
+
+    match program.suggest_links(visits, 10) {
+        Ok(marked) => {
+            assert_eq!(vec![LinkPair::new("api-2", "/their/api")], marked);
+            assert_eq!(
+                vec![
+                    "Mark api-2 -> /their/api ? [y]es, [n]o or [q]uit",
+                    "Created link from api-2 -> /their/api",
+                    "Mark web -> /my/web ? [y]es, [n]o or [q]uit",
+                    "Mark docs -> /my/docs ? [y]es, [n]o or [q]uit"
+                ],
+                output.into_inner()
+            );
+            assert_eq!(Vec::<String>::new(), input.into_inner())
+        },
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn suggest_links_skips_dirs_marked_with_a_different_path() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let input: Cell<Vec<String>> = Cell::new(vec!["q".to_string()]);
+    let read_links = vec![LinkPair::new("web", "/my/src/../web/.")];
+
+    let stub = TestStub::with_read_links_and_std_in(&output, read_links, &input);
+    let program = TestStub::program(stub);
+
+    let visits = vec![
+        DirVisit { dir: PathBuf::from("/my/web"), score: 100.0 },
+        DirVisit { dir: PathBuf::from("/my/docs"), score: 10.0 },
+    ];
+
+    match program.suggest_links(visits, 10) {
+        Ok(marked) => {
+            assert!(marked.is_empty());
+            assert_eq!(vec!["Mark docs -> /my/docs ? [y]es, [n]o or [q]uit"], output.into_inner())
+        },
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn frecency_file_in_data_home() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let program = TestStub::program(TestStub::new(&output));
+
+    let files = [FrecencyTool::Z, FrecencyTool::Autojump]
+        .iter()
+        .map(|tool| program.frecency_file(*tool))
+        .collect::<HopEffect<Vec<PathBuf>>>();
+
+    match files {
+        Ok(files) => assert_eq!(vec![PathBuf::from("/xyz/.z"), PathBuf::from("/xyz/.local/share/autojump/autojump.txt")], files),
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn plan_sync_with_last_synced_links() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![
+        LinkPair::new("same", "/same"),
+        LinkPair::new("hop-added", "/hop/added"),
+        LinkPair::new("other-removed", "/other/removed"),
+        LinkPair::new("other-changed", "/old"),
+        LinkPair::new("both-changed", "/hop/new"),
+    ];
+
+    let other = vec![
+        LinkPair::new("same", "/same"),
+        LinkPair::new("other-added", "/other/added"),
+        LinkPair::new("hop-removed", "/hop/removed"),
+        LinkPair::new("other-changed", "/new"),
+        LinkPair::new("both-changed", "/other/new"),
+    ];
+
+    let base = vec![
+        LinkPair::new("same", "/same"),
+        LinkPair::new("other-removed", "/other/removed"),
+        LinkPair::new("hop-removed", "/hop/removed"),
+        LinkPair::new("other-changed", "/old"),
+        LinkPair::new("both-changed", "/old"),
+    ];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    match program.plan_sync(other, base) {
+        Ok(plan) => {
+            assert_eq!(
+                vec![
+                    SyncChange::Remove(LinkPair::new("other-removed", "/other/removed")),
+                    SyncChange::Update(LinkPair::new("other-changed", "/new")),
+                    SyncChange::Add(LinkPair::new("other-added", "/other/added")),
+                ],
+                plan.hop
+            );
+            assert_eq!(
+                vec![
+                    SyncChange::Add(LinkPair::new("hop-added", "/hop/added")),
+                    SyncChange::Remove(LinkPair::new("hop-removed", "/hop/removed")),
+                ],
+                plan.other
+            );
+            assert_eq!(
+                vec![(LinkPair::new("both-changed", "/hop/new"), LinkPair::new("both-changed", "/other/new"))],
+                plan.conflicts
+            );
+            assert_eq!(
+                vec![
+                    LinkPair::new("same", "/same"),
+                    LinkPair::new("hop-added", "/hop/added"),
+                    LinkPair::new("other-changed", "/new"),
+                    LinkPair::new("other-added", "/other/added"),
+                ],
+                plan.synced
+            );
+        },
+        Err(e) => panic!("{}: Expected an Ok but got err", e),
+    }
+}
+
+#[test]
+fn sync_git_without_git() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    let stub = TestStub::new(&output);
+    let program = TestStub::program(stub);
+
+    match program.sync_git(None) {
+        Err(e) => assert_eq!(e.to_string(), "The hop home is not kept in git. Set `git = true` in the config file to sync it."),
+        Ok(_) => panic!("Expected an Err but got Ok"),
+    }
+}
+
+#[test]
+fn sync_git_with_conflicts() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let conflict = GitConflict {
+        link: Link::new("code"),
+        local: Some(LinkTarget::new("/xyz/code")),
+        remote: None,
+    };
+
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        git_conflicts: vec![conflict.clone()],
+        ..default
+    };
+    let mut program = TestStub::program(stub);
+    program.config.git = true;
+
+    match program.sync_git(None) {
+        Ok(status) => {
+            assert_eq!(GitSyncStatus::Conflicts(vec![conflict]), status);
+            assert!(!program.value.stub.pushed.get())
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+
+    match program.sync_git(Some(SyncPreference::Local)) {
+        Ok(status) => {
+            assert_eq!(GitSyncStatus::Synced, status);
+            assert!(program.value.stub.pushed.get())
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn undo_reverts_newest_changes() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("myNewLink", "/my/path/to/link")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let mut program = TestStub::program(stub);
+    program.config.git = true;
+
+    let marked = journal_entry("Mark myLink -> /my/path/to/link", vec![LinkChange::added(&LinkPair::new("myLink", "/my/path/to/link"))]);
+    let renamed = journal_entry(
+        "Rename myLink to myNewLink",
+        vec![
+            LinkChange::removed(&LinkPair::new("myLink", "/my/path/to/link")),
+            LinkChange::added(&LinkPair::new("myNewLink", "/my/path/to/link")),
+        ]
+    );
+    let deleted = journal_entry("Delete other -> /other", vec![LinkChange::removed(&LinkPair::new("other", "/other"))]);
+    program.value.stub.journal.set(vec![marked.clone(), renamed.clone(), deleted.clone()]);
+
+    match program.undo(2) {
+        Ok(undone) => {
+            assert_eq!(vec![deleted, renamed], undone);
+            assert_eq!(vec![PathBuf::from("/other"), PathBuf::from("/my/path/to/link")], program.value.stub.written_targets.take());
+            assert_eq!(vec![marked], program.value.stub.journal.take());
+            assert_eq!(vec!["Undo 2 changes".to_string()], program.value.stub.commits.take())
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn undo_delete_takes_link_out_of_trash() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let pair = LinkPair::new("other", "/other");
+
+    let default = TestStub::new(&output);
+    let stub = TestStub {
+        trash: Cell::new(vec![trashed_link(&pair)]),
+        ..default
+    };
+    let program = TestStub::program(stub);
+
+    program.value.stub.journal.set(vec![journal_entry("Delete other -> /other", vec![LinkChange::removed(&pair)])]);
+
+    match program.undo(1) {
+        Ok(_) => {
+            assert_eq!(Vec::<TrashedLink>::new(), program.value.stub.trash.take());
+            assert_eq!(Vec::<PathBuf>::new(), program.value.stub.written_targets.take())
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn undo_link_changed_since() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("myLink", "/my/other/path")];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    let marked = journal_entry("Mark myLink -> /my/path/to/link", vec![LinkChange::added(&LinkPair::new("myLink", "/my/path/to/link"))]);
+    program.value.stub.journal.set(vec![marked.clone()]);
+
+    match program.undo(1) {
+        Err(e) => {
+            assert_eq!(e.to_string(), "`myLink` has been changed since it was last changed by hop. Aborting undo.");
+            assert_eq!(Vec::<PathBuf>::new(), program.value.stub.written_targets.take());
+            assert_eq!(vec![marked], program.value.stub.journal.take())
+        },
+        Ok(_) => panic!("Expected an Err but got Ok"),
+    }
+}
+
+#[test]
+fn undo_without_changes() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+
+    let stub = TestStub::new(&output);
+    let program = TestStub::program(stub);
+
+    match program.undo(1) {
+        Err(e) => assert_eq!(e.to_string(), "There are no changes to undo."),
+        Ok(_) => panic!("Expected an Err but got Ok"),
+    }
+}
+
+#[test]
+fn create_snapshot_removes_oldest_snapshots() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![LinkPair::new("code", "/my/code")];
+
+    let stub = TestStub::with_read_links(&output, read_links.clone());
+    let mut program = TestStub::program(stub);
+    program.config.snapshot_limit = 2;
+
+    let older = snapshot("older", None, vec![]);
+    let oldest = snapshot("oldest", None, vec![]);
+    program.value.stub.snapshots.set(vec![older.clone(), oldest]);
+
+    match program.create_snapshot(Some("tidy")) {
+        Ok(created) => {
+            assert_eq!(snapshot("snapshot-3", Some("tidy"), read_links), created);
+            assert_eq!(vec![created, older], program.value.stub.snapshots.take())
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+}
+
+#[test]
+fn plan_restore_changes() {
+    let output: Cell<Vec<String>> = Cell::new(vec![]);
+    let read_links = vec![
+        LinkPair::new("code", "/my/code"),
+        LinkPair::new("docs", "/my/new/docs"),
+        LinkPair::new("web", "/my/web"),
+    ];
+
+    let stub = TestStub::with_read_links(&output, read_links);
+    let program = TestStub::program(stub);
+
+    let saved = snapshot(
+        "saved",
+        None,
+        vec![
+            LinkPair::new("code", "/my/code"),
+            LinkPair::new("docs", "/my/docs"),
+            LinkPair::new("wiki", "/my/wiki"),
+        ]
+    );
+    program.value.stub.snapshots.set(vec![saved.clone()]);
+
+    match program.plan_restore("saved") {
+        Ok((snapshot, changes)) => {
+            let expected = vec![
+                SyncChange::Update(LinkPair::new("docs", "/my/docs")),
+                SyncChange::Add(LinkPair::new("wiki", "/my/wiki")),
+                SyncChange::Remove(LinkPair::new("web", "/my/web")),
+            ];
+
+            assert_eq!(saved, snapshot);
+            assert_eq!(expected, changes)
+        },
+        Err(e) => panic!("Expected an Ok but got Err: {}", e),
+    }
+
+    match program.plan_restore("missing") {
+        Err(e) => assert_eq!(e.to_string(), "Could not find snapshot `missing`"),
+        Ok(_) => panic!("Expected an Err but got Ok"),
+    }
+}
@@ -0,0 +1,14 @@
+use crate::models::{HopEffect, JournalEntry, LinkChange};
+use std::path::Path;
+
+/// A record of the changes made to a hop home, so that they can be undone.
+pub trait Journal {
+    /// Adds a change to the end of the journal of `hop_home`, stamped with the current time.
+    fn append_entry(&self, hop_home: &Path, description: &str, changes: &[LinkChange]) -> HopEffect<()>;
+
+    /// The journal of `hop_home`, from oldest to newest.
+    fn read_entries(&self, hop_home: &Path) -> HopEffect<Vec<JournalEntry>>;
+
+    /// Removes the newest `count` entries once they have been undone.
+    fn remove_entries(&self, hop_home: &Path, count: usize) -> HopEffect<()>;
+}
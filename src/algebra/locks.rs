@@ -0,0 +1,12 @@
+use crate::models::HopEffect;
+use std::path::Path;
+use std::time::Duration;
+
+/// Stops hops running at the same time from changing the same hop home at once.
+pub trait Locks {
+    /// Releases the lock when dropped.
+    type Lock;
+
+    /// Waits up to `timeout` for an exclusive lock on `hop_home`.
+    fn lock_home(&self, hop_home: &Path, timeout: Duration) -> HopEffect<Self::Lock>;
+}
@@ -1,5 +1,10 @@
 pub mod directories;
+pub mod git;
 pub mod hop;
+pub mod journal;
+pub mod locks;
+pub mod snapshots;
 pub mod std_io;
 pub mod symlinks;
+pub mod trash;
 pub mod user_dirs;
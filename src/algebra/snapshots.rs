@@ -0,0 +1,13 @@
+use crate::models::{HopEffect, LinkPair, Snapshot};
+use std::path::Path;
+
+/// Keeps copies of the links in a hop home so that it can be put back as it was.
+pub trait Snapshots {
+    /// Saves `links` as a new snapshot of `hop_home`, stamped with the current time.
+    fn write_snapshot(&self, hop_home: &Path, label: Option<&str>, links: &[LinkPair]) -> HopEffect<Snapshot>;
+
+    /// The snapshots of `hop_home`, newest first.
+    fn list_snapshots(&self, hop_home: &Path) -> HopEffect<Vec<Snapshot>>;
+
+    fn remove_snapshot(&self, hop_home: &Path, id: &str) -> HopEffect<()>;
+}
@@ -0,0 +1,19 @@
+use crate::models::{HopEffect, LinkPair, TrashedLink};
+use std::path::Path;
+
+/// Keeps deleted links so that they can be restored.
+pub trait Trash {
+    /// Moves a link from `hop_home` into its trash, stamped with the current time.
+    fn trash_link(&self, hop_home: &Path, link_pair: &LinkPair) -> HopEffect<()>;
+
+    /// The links in the trash of `hop_home`, newest first.
+    fn trashed_links(&self, hop_home: &Path) -> HopEffect<Vec<TrashedLink>>;
+
+    /// Moves a link out of the trash and back into `hop_home` under its name. Fails with `AlreadyExists` rather than
+    /// replace a link with that name.
+    fn restore_link(&self, hop_home: &Path, trashed_link: &TrashedLink) -> HopEffect<()>;
+
+    /// Removes the links that were deleted more than `max_days` ago from the trash, or every link without a
+    /// `max_days`. Returns the links that were removed.
+    fn empty_trash(&self, hop_home: &Path, max_days: Option<u64>) -> HopEffect<Vec<TrashedLink>>;
+}
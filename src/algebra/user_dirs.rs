@@ -1,6 +1,25 @@
 use crate::models::{HomeType, HopEffect};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub trait UserDirs {
     fn get_hop_home(&self, path: &HomeType) -> HopEffect<PathBuf>;
+
+    /// The user's home directory.
+    fn home_dir(&self) -> HopEffect<PathBuf>;
+
+    /// `$XDG_DATA_HOME`, or `~/.local/share` if it is not set.
+    fn data_home(&self) -> HopEffect<PathBuf>;
+
+    /// The names of profiles that have a hop home under `$XDG_DATA_HOME/hop-profiles`.
+    fn list_profiles(&self) -> HopEffect<Vec<String>>;
+
+    /// The legacy `~/.hop` directory, if it exists as a real directory.
+    fn legacy_hop_home(&self) -> HopEffect<Option<PathBuf>>;
+
+    /// The XDG hop home, which is created if it does not exist.
+    fn xdg_hop_home(&self) -> HopEffect<PathBuf>;
+
+    /// Moves everything in the directory `from` into `to` and replaces `from` with a symlink to `to`. Nothing is
+    /// moved if anything in `from` already exists in `to`.
+    fn move_hop_home(&self, from: &Path, to: &Path) -> HopEffect<()>;
 }
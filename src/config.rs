@@ -0,0 +1,546 @@
+use crate::models::{HomeLayer, HomeType, HopEffect, LinkPair, StoredOsString, LOCAL_LAYER, PERSONAL_LAYER};
+use crate::program::{io_error, io_error_ex};
+use dirs::home_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Environment variable holding an absolute path to the hop home.
+pub const HOP_HOME_ENV: &str = "HOP_HOME";
+
+/// Environment variable holding default flags that are applied before any flags on the command line.
+pub const HOP_OPTS_ENV: &str = "HOP_OPTS";
+
+/// Environment variable holding the base directory for user specific configuration files.
+pub const XDG_CONFIG_HOME_ENV: &str = "XDG_CONFIG_HOME";
+
+/// Environment variable holding the directory for state that should outlive a restart but isn't worth backing up.
+pub const XDG_STATE_HOME_ENV: &str = "XDG_STATE_HOME";
+
+/// Where the hop home was configured from, listed from highest to lowest precedence.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HomeSource {
+    Flag,
+    ProfileFlag,
+    HomeEnv,
+    OptsEnv,
+    OptsProfile,
+    ActiveProfile,
+    ConfigFile,
+    Default,
+}
+
+/// The hop home to use along with where it was configured from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HopHome {
+    pub home_type: HomeType,
+    pub source: HomeSource,
+    /// The profile the hop home belongs to, if any.
+    pub profile: Option<String>,
+}
+
+/// The flags that choose a hop home.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HomeFlags<'a> {
+    pub home: Option<&'a OsStr>,
+    pub profile: Option<&'a str>,
+}
+
+impl fmt::Display for HomeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self {
+            HomeSource::Flag => "-c/--c flag".to_string(),
+            HomeSource::ProfileFlag => "--profile flag".to_string(),
+            HomeSource::HomeEnv => format!("{} environment variable", HOP_HOME_ENV),
+            HomeSource::OptsEnv => format!("-c/--c flag in the {} environment variable", HOP_OPTS_ENV),
+            HomeSource::OptsProfile => format!("--profile flag in the {} environment variable", HOP_OPTS_ENV),
+            HomeSource::ActiveProfile => "active profile".to_string(),
+            HomeSource::ConfigFile => "home in the config file".to_string(),
+            HomeSource::Default => "default".to_string(),
+        };
+
+        write!(f, "{}", source)
+    }
+}
+
+/// The flags allowed in `HOP_OPTS` that take a value.
+const VALUE_OPTS: [&str; 3] = ["-c", "--c", "--profile"];
+
+/// The flags allowed in `HOP_OPTS` that don't take a value.
+const SWITCH_OPTS: [&str; 4] = ["--no-local", "-y", "--yes", "--no-input"];
+
+/// Splits the value of `HOP_OPTS` on whitespace into separate arguments.
+pub fn default_opts(hop_opts: Option<OsString>) -> Vec<OsString> {
+    hop_opts
+        .map(|opts| {
+            opts.as_bytes()
+                .split(|b| b.is_ascii_whitespace())
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| OsStr::from_bytes(arg).to_os_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Checks that `default_opts` only holds option flags, which change how hop runs rather than what it does. An
+/// action such as `-l` in `HOP_OPTS` would otherwise take over every command.
+pub fn validate_default_opts(default_opts: &[OsString]) -> Result<(), String> {
+    let mut opts = default_opts.iter().map(|opt| opt.to_string_lossy());
+
+    while let Some(opt) = opts.next() {
+        let (flag, inline_value) = match opt.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (opt.to_string(), None),
+        };
+
+        if VALUE_OPTS.contains(&flag.as_str()) {
+            if inline_value.is_none() && opts.next().is_none() {
+                return Err(format!("`{}` in {} needs a value.", flag, HOP_OPTS_ENV))
+            }
+        } else if !SWITCH_OPTS.contains(&flag.as_str()) || inline_value.is_some() {
+            return Err(format!(
+                "`{}` can't be used in {}. Only {} can.",
+                opt,
+                HOP_OPTS_ENV,
+                VALUE_OPTS.iter().chain(SWITCH_OPTS.iter()).copied().collect::<Vec<_>>().join(", ")
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// Inserts the `default_opts` between the program name and the supplied arguments, so that any flags supplied on
+/// the command line override them.
+pub fn with_default_opts<I>(args: I, default_opts: &[OsString]) -> Vec<OsString>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut args = args.into_iter();
+    let mut all_args: Vec<OsString> = args.next().into_iter().collect();
+    all_args.extend(default_opts.iter().cloned());
+    all_args.extend(args);
+    all_args
+}
+
+/// Chooses the hop home in order of precedence:
+///
+/// 1. `-c/--c` on the command line
+/// 2. `--profile` on the command line
+/// 3. `HOP_HOME`
+/// 4. `-c/--c` or `--profile` within `HOP_OPTS`
+/// 5. The active profile chosen with `hop profile use`
+/// 6. `home` in the config file
+/// 7. `$XDG_DATA_HOME/hop` (or `~/.hop` if it already exists)
+///
+/// `flags` are the values on the command line alone and `opts_flags` are the values within `HOP_OPTS` alone.
+pub fn resolve_hop_home(
+    flags: HomeFlags,
+    opts_flags: HomeFlags,
+    home_env: Option<OsString>,
+    active_profile: Option<&str>,
+    config: &Config,
+) -> HopHome {
+    let home_env = home_env.filter(|home| !home.is_empty());
+
+    let absolute = |path: &Path, source: HomeSource| HopHome {
+        home_type: HomeType::Absolute(path.to_path_buf()),
+        source,
+        profile: None,
+    };
+
+    let profile_home = |profile: &str, source: HomeSource| HopHome {
+        home_type: config
+            .profiles
+            .get(profile)
+            .map(|path| HomeType::Absolute(path.clone()))
+            .unwrap_or_else(|| HomeType::Profile(profile.to_string())),
+        source,
+        profile: Some(profile.to_string()),
+    };
+
+    if let Some(path) = flags.home {
+        absolute(Path::new(path), HomeSource::Flag)
+    } else if let Some(profile) = flags.profile {
+        profile_home(profile, HomeSource::ProfileFlag)
+    } else if let Some(path) = home_env {
+        absolute(Path::new(&path), HomeSource::HomeEnv)
+    } else if let Some(path) = opts_flags.home {
+        absolute(Path::new(path), HomeSource::OptsEnv)
+    } else if let Some(profile) = opts_flags.profile {
+        profile_home(profile, HomeSource::OptsProfile)
+    } else if let Some(profile) = active_profile {
+        profile_home(profile, HomeSource::ActiveProfile)
+    } else if let Some(path) = &config.home {
+        absolute(path, HomeSource::ConfigFile)
+    } else {
+        HopHome {
+            home_type: HomeType::Xdg,
+            source: HomeSource::Default,
+            profile: None,
+        }
+    }
+}
+
+/// The hop homes to read links from, from highest to lowest precedence: the project's `.hop` directory, the
+/// personal hop home and then any shared hop homes in the config file.
+pub fn home_layers(hop_home: &HopHome, config: &Config, local_home: Option<PathBuf>) -> Vec<HomeLayer> {
+    let mut layers: Vec<HomeLayer> = local_home.into_iter().map(HomeLayer::local).collect();
+    layers.push(HomeLayer::personal(hop_home.home_type.clone()));
+    layers.extend(config.shared.iter().map(|shared| HomeLayer::shared(&shared.name, shared.home.clone())));
+    layers
+}
+
+/// Finds the nearest `.hop` directory in `working_dir` or any of its parents. The legacy `~/.hop` is a hop home
+/// and not a project, so it is never returned, nor is any directory that is really one of the `hop_homes`, such as
+/// the personal hop home reached through a symlink.
+pub fn find_local_hop_home(working_dir: &Path, hop_homes: &[PathBuf]) -> Option<PathBuf> {
+    let home = home_dir();
+    let hop_homes: Vec<PathBuf> = hop_homes.iter().filter_map(|hop_home| fs::canonicalize(hop_home).ok()).collect();
+
+    working_dir
+        .ancestors()
+        .filter(|dir| Some(*dir) != home.as_deref())
+        .map(|dir| dir.join(LOCAL_HOP_HOME))
+        .filter(|local_home| local_home.is_dir())
+        .find(|local_home| fs::canonicalize(local_home).is_ok_and(|local_home| !hop_homes.contains(&local_home)))
+}
+
+/// The name of the directory holding project-local links.
+const LOCAL_HOP_HOME: &str = ".hop";
+
+/// Profile names become directory names, so they are limited to letters, numbers, `-` and `_`.
+pub fn validate_profile_name(profile: &str) -> Result<(), String> {
+    let valid =
+        !profile.is_empty() &&
+        profile.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid profile name `{}`. Profile names may only contain letters, numbers, `-` and `_`.", profile))
+    }
+}
+
+/// How a name given to `-j` is matched against the names of links.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// The name must match a link exactly.
+    Exact,
+    /// The name may be a prefix of a single link. Exact matches always win.
+    Prefix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColourMode {
+    /// Colour output only when stdout is a terminal.
+    Auto,
+    Always,
+    Never,
+}
+
+/// The order in which links are listed and tabulated.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Name,
+    Target,
+    /// The order in which links are read from the hop home.
+    None,
+}
+
+/// How the targets of new links are written.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetPaths {
+    Absolute,
+    /// Targets within the home directory are written as `~/path`.
+    Home,
+    /// Targets are written relative to the hop home.
+    HopHome,
+}
+
+/// Defaults and behaviour read from the config file. Every key is optional. Keys that aren't fields are reported
+/// as warnings by `parse_config`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub home: Option<PathBuf>,
+    pub match_mode: MatchMode,
+    pub output: OutputFormat,
+    #[serde(alias = "color")]
+    pub colour: ColourMode,
+    /// Whether to ask before deleting a link.
+    pub confirm: bool,
+    pub sort: SortOrder,
+    /// Relative targets let a hop home be shared between machines with different home directories.
+    pub targets: TargetPaths,
+    /// Profiles with a hop home other than `$XDG_DATA_HOME/hop-profiles/<profile>`.
+    pub profiles: BTreeMap<String, PathBuf>,
+    /// Read-only hop homes layered underneath the personal hop home, from highest to lowest precedence.
+    pub shared: Vec<SharedHome>,
+    /// Whether the hop home is a git repository with a commit for every change.
+    pub git: bool,
+    /// The repository `hop sync` pulls from and pushes to. Defaults to the `origin` remote of the hop home.
+    pub git_remote: Option<String>,
+    /// Deleted links are removed from the trash after this many days. 0 keeps them until the trash is emptied.
+    pub trash_days: u64,
+    /// The most snapshots kept for each hop home. Older snapshots are removed when a new one is taken. 0 keeps every
+    /// snapshot.
+    pub snapshot_limit: usize,
+    /// How many seconds to wait for another hop to finish changing the hop home before giving up.
+    pub lock_timeout: u64,
+}
+
+/// A read-only hop home, such as a team's hop home on a network share.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SharedHome {
+    pub name: String,
+    pub home: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            home: None,
+            match_mode: MatchMode::Exact,
+            output: OutputFormat::Text,
+            colour: ColourMode::Always,
+            confirm: true,
+            sort: SortOrder::Name,
+            targets: TargetPaths::Absolute,
+            profiles: BTreeMap::new(),
+            shared: vec![],
+            git: false,
+            git_remote: None,
+            trash_days: 30,
+            snapshot_limit: 20,
+            lock_timeout: 5,
+        }
+    }
+}
+
+/// A config file that has been read and parsed, along with any warnings about its contents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub config: Config,
+    pub warnings: Vec<String>,
+}
+
+/// `$XDG_CONFIG_HOME/hop/config.toml` if `XDG_CONFIG_HOME` is set to an absolute path, otherwise
+/// `~/.config/hop/config.toml`.
+pub fn config_file_path(xdg_config_home: Option<OsString>) -> HopEffect<PathBuf> {
+    Ok(config_home(xdg_config_home)?.join("hop").join("config.toml"))
+}
+
+/// `$XDG_CONFIG_HOME/gtk-3.0/bookmarks`, where GTK file managers keep the places in their sidebar.
+pub fn gtk_bookmarks_path(xdg_config_home: Option<OsString>) -> HopEffect<PathBuf> {
+    Ok(config_home(xdg_config_home)?.join("gtk-3.0").join("bookmarks"))
+}
+
+fn config_home(xdg_config_home: Option<OsString>) -> HopEffect<PathBuf> {
+    match xdg_config_home.map(PathBuf::from) {
+        Some(config_home) if config_home.is_absolute() => Ok(config_home),
+        _ => Ok(get_home()?.join(".config")),
+    }
+}
+
+/// Reads the config file at `path`. Returns `None` if there is no config file.
+pub fn read_config_file(path: &Path) -> HopEffect<Option<ConfigFile>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            let (config, warnings) = parse_config(&contents).map_err(|e| {
+                io_error_ex(&format!("Could not parse config file: {}", path.to_string_lossy()), e)
+            })?;
+
+            Ok(Some(ConfigFile {
+                path: path.to_path_buf(),
+                config,
+                warnings,
+            }))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(io_error_ex(&format!("Could not read config file: {}", path.to_string_lossy()), e)),
+    }
+}
+
+/// Parses the contents of a config file. Unknown keys are returned as warnings instead of failing the parse so
+/// that a config file written for a newer version of hop can still be used.
+pub fn parse_config(contents: &str) -> HopEffect<(Config, Vec<String>)> {
+    let mut unknown_keys: Vec<String> = vec![];
+    let mut deserializer = toml::Deserializer::new(contents);
+
+    let mut config: Config =
+        serde_ignored::deserialize(&mut deserializer, |path| unknown_keys.push(path.to_string()))
+        .map_err(|e| io_error(&e.to_string()))?;
+
+    config.home = config.home.map(expand_home).transpose()?;
+
+    if let Some(home) = config.home.as_ref().filter(|home| home.is_relative()) {
+        return Err(io_error(&format!("home must be an absolute path but got: {}", home.to_string_lossy())));
+    }
+
+    for (profile, home) in config.profiles.iter_mut() {
+        validate_profile_name(profile).map_err(|e| io_error(&e))?;
+        *home = expand_home(home.clone())?;
+
+        if home.is_relative() {
+            return Err(io_error(&format!("home for profile `{}` must be an absolute path but got: {}", profile, home.to_string_lossy())));
+        }
+    }
+
+    for shared_home in config.shared.iter_mut() {
+        shared_home.home = expand_home(shared_home.home.clone())?;
+
+        if shared_home.home.is_relative() {
+            return Err(io_error(&format!("home for shared hop home `{}` must be an absolute path but got: {}", shared_home.name, shared_home.home.to_string_lossy())));
+        }
+    }
+
+    for (index, shared_home) in config.shared.iter().enumerate() {
+        if shared_home.name == PERSONAL_LAYER || shared_home.name == LOCAL_LAYER || config.shared[..index].iter().any(|other| other.name == shared_home.name) {
+            return Err(io_error(&format!("The name `{}` is used by more than one hop home", shared_home.name)));
+        }
+    }
+
+    let warnings =
+        unknown_keys
+        .iter()
+        .map(|key| format!("Unknown key `{}` in config file", key))
+        .collect();
+
+    Ok((config, warnings))
+}
+
+/// `$XDG_CONFIG_HOME/hop/profile`, which holds the name of the profile chosen with `hop profile use`.
+pub fn active_profile_path(xdg_config_home: Option<OsString>) -> HopEffect<PathBuf> {
+    config_file_path(xdg_config_home).map(|path| path.with_file_name("profile"))
+}
+
+/// Reads the active profile. Returns `None` if no profile has been chosen.
+pub fn read_active_profile(path: &Path) -> HopEffect<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents.trim().to_string()).filter(|profile| !profile.is_empty())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(io_error_ex(&format!("Could not read active profile: {}", path.to_string_lossy()), e)),
+    }
+}
+
+/// Persists the active profile. `None` goes back to using the default hop home.
+pub fn write_active_profile(path: &Path, profile: Option<&str>) -> HopEffect<()> {
+    let result = match profile {
+        Some(profile) => path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, format!("{}\n", profile))),
+        None => match fs::remove_file(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        },
+    };
+
+    result.map_err(|e| io_error_ex(&format!("Could not save active profile: {}", path.to_string_lossy()), e))
+}
+
+/// `$XDG_STATE_HOME/hop/sync-<name>.json`, which holds the links as they were after the last sync with `name`
+/// for each hop home. Without it, a link that is only on one side could have been added there or removed from the
+/// other side.
+pub fn sync_state_path(xdg_state_home: Option<OsString>, name: &str) -> HopEffect<PathBuf> {
+    Ok(state_home(xdg_state_home)?.join("hop").join(format!("sync-{}.json", name)))
+}
+
+/// Reads the links as they were after the last sync of `hop_home`. Returns no links if it has never been synced.
+pub fn read_sync_state(path: &Path, hop_home: &Path) -> HopEffect<Vec<LinkPair>> {
+    let mut state = read_sync_states(path)?;
+
+    let links =
+        state
+        .remove(&hop_home.to_string_lossy().to_string())
+        .map(|records| records.into_iter().map(|record| LinkPair::new(record.name.into_os_string(), record.target.into_os_string())).collect())
+        .unwrap_or_default();
+
+    Ok(links)
+}
+
+/// Saves the links as they are after a sync of `hop_home`, keeping the state of other hop homes.
+pub fn write_sync_state(path: &Path, hop_home: &Path, links: &[LinkPair]) -> HopEffect<()> {
+    let mut state = read_sync_states(path)?;
+    state.insert(
+        hop_home.to_string_lossy().to_string(),
+        links.iter().map(|lp| SyncRecord { name: StoredOsString::new(&lp.link.0), target: StoredOsString::new(&lp.target.0) }).collect()
+    );
+
+    serde_json::to_string_pretty(&state)
+        .map_err(io::Error::other)
+        .and_then(|json| {
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(path, format!("{}\n", json)))
+        })
+        .map_err(|e| io_error_ex(&format!("Could not save sync state: {}", path.to_string_lossy()), e))
+}
+
+/// A synced link. Sync state is keyed by the path of the hop home.
+#[derive(Debug, Serialize, Deserialize)]
+struct SyncRecord {
+    name: StoredOsString,
+    target: StoredOsString,
+}
+
+/// `$XDG_STATE_HOME/hop/journal.json`, which holds the recent changes made to each hop home so that they can be
+/// undone.
+pub fn journal_path(xdg_state_home: Option<OsString>) -> HopEffect<PathBuf> {
+    Ok(state_home(xdg_state_home)?.join("hop").join("journal.json"))
+}
+
+/// `$XDG_STATE_HOME/hop/snapshots`, which holds a compressed file for each snapshot of every hop home.
+pub fn snapshots_dir(xdg_state_home: Option<OsString>) -> HopEffect<PathBuf> {
+    Ok(state_home(xdg_state_home)?.join("hop").join("snapshots"))
+}
+
+/// `$XDG_STATE_HOME` if it is set to an absolute path, otherwise `~/.local/state` as per the XDG base directory
+/// specification.
+fn state_home(xdg_state_home: Option<OsString>) -> HopEffect<PathBuf> {
+    match xdg_state_home.map(PathBuf::from) {
+        Some(state_home) if state_home.is_absolute() => Ok(state_home),
+        _ => Ok(get_home()?.join(".local").join("state")),
+    }
+}
+
+fn read_sync_states(path: &Path) -> HopEffect<BTreeMap<String, Vec<SyncRecord>>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            serde_json::from_str(&contents)
+                .map_err(|e| io_error(&format!("Could not read sync state: {}\n{}", path.to_string_lossy(), e)))
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(io_error_ex(&format!("Could not read sync state: {}", path.to_string_lossy()), e)),
+    }
+}
+
+/// Expands a leading `~` to the user's home directory.
+pub fn expand_home(path: PathBuf) -> HopEffect<PathBuf> {
+    match path.strip_prefix("~") {
+        Ok(relative_path) => Ok(get_home()?.join(relative_path)),
+        Err(_) => Ok(path),
+    }
+}
+
+fn get_home() -> HopEffect<PathBuf> {
+    home_dir().ok_or_else(|| io_error("Could not get home directory"))
+}
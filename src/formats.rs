@@ -0,0 +1,546 @@
+use crate::models::{BatchOp, DirVisit, HopEffect, LayeredLinkPair, Link, LinkPair, SyncChange};
+use crate::program::io_error;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+/// Text formats that links can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkFormat {
+    Json,
+    Toml,
+    Csv,
+    /// A shell script of `hop -m` commands that only mark links that don't already exist.
+    Sh,
+}
+
+impl LinkFormat {
+    pub const NAMES: [&'static str; 4] = ["json", "toml", "csv", "sh"];
+
+    pub fn from_name(name: &str) -> Option<LinkFormat> {
+        match name {
+            "json" => Some(LinkFormat::Json),
+            "toml" => Some(LinkFormat::Toml),
+            "csv" => Some(LinkFormat::Csv),
+            "sh" => Some(LinkFormat::Sh),
+            _ => None,
+        }
+    }
+
+    /// The format of a file from its extension.
+    pub fn from_path(path: &Path) -> Option<LinkFormat> {
+        path.extension().and_then(|extension| extension.to_str()).and_then(LinkFormat::from_name)
+    }
+}
+
+/// Where links are imported from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportSource {
+    /// A file written by `hop export`. The format is worked out from the file's extension if it is not given.
+    Export(Option<LinkFormat>),
+    Bookmarks(BookmarkTool),
+}
+
+/// Other directory bookmarking tools that links can be imported from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BookmarkTool {
+    Bashmarks,
+    Wd,
+    Apparix,
+}
+
+impl BookmarkTool {
+    pub const NAMES: [&'static str; 3] = ["bashmarks", "wd", "apparix"];
+
+    pub fn from_name(name: &str) -> Option<BookmarkTool> {
+        match name {
+            "bashmarks" => Some(BookmarkTool::Bashmarks),
+            "wd" => Some(BookmarkTool::Wd),
+            "apparix" => Some(BookmarkTool::Apparix),
+            _ => None,
+        }
+    }
+
+    /// Where the tool keeps its bookmarks, relative to the home directory.
+    pub fn default_file(&self) -> &'static str {
+        match self {
+            BookmarkTool::Bashmarks => ".sdirs",
+            BookmarkTool::Wd => ".warprc",
+            BookmarkTool::Apparix => ".apparixrc",
+        }
+    }
+}
+
+/// Tools that track how often directories are visited, which marks can be suggested from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrecencyTool {
+    Z,
+    Autojump,
+    Fasd,
+}
+
+impl FrecencyTool {
+    pub const NAMES: [&'static str; 3] = ["z", "autojump", "fasd"];
+
+    pub fn from_name(name: &str) -> Option<FrecencyTool> {
+        match name {
+            "z" => Some(FrecencyTool::Z),
+            "autojump" => Some(FrecencyTool::Autojump),
+            "fasd" => Some(FrecencyTool::Fasd),
+            _ => None,
+        }
+    }
+
+    /// Where the tool keeps its database by default.
+    pub fn default_file(&self, home: &Path, data_home: &Path) -> PathBuf {
+        match self {
+            FrecencyTool::Z => home.join(".z"),
+            FrecencyTool::Autojump => data_home.join("autojump").join("autojump.txt"),
+            FrecencyTool::Fasd => home.join(".fasd"),
+        }
+    }
+}
+
+/// A link as it is written to an export. Links whose name or target is not valid UTF-8 are not exported.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkRecord {
+    pub name: String,
+    pub target: String,
+    /// The hop home the link was read from. This is ignored when importing.
+    #[serde(default)]
+    pub layer: String,
+}
+
+impl LinkRecord {
+    /// Exports are text, so a link whose name or target is not valid UTF-8 can't be written without pointing
+    /// somewhere else.
+    pub fn from_layered(layered_pair: &LayeredLinkPair) -> HopEffect<Self> {
+        let pair = &layered_pair.link_pair;
+
+        match (pair.link.0.to_str(), pair.target.0.to_str()) {
+            (Some(name), Some(target)) => Ok(LinkRecord {
+                name: name.to_string(),
+                target: target.to_string(),
+                layer: layered_pair.layer.clone(),
+            }),
+            _ => Err(io_error(&format!("`{}` can't be exported as its name or target is not valid UTF-8.", pair.link))),
+        }
+    }
+}
+
+/// TOML documents must be tables, so links are written as an array of `[[links]]` tables.
+#[derive(Debug, Serialize, Deserialize)]
+struct TomlLinks {
+    links: Vec<LinkRecord>,
+}
+
+pub fn export_links(links: &[LayeredLinkPair], format: LinkFormat) -> HopEffect<String> {
+    let records = links.iter().map(LinkRecord::from_layered).collect::<HopEffect<Vec<LinkRecord>>>()?;
+
+    match format {
+        LinkFormat::Json => {
+            serde_json::to_string_pretty(&records)
+                .map(|json| format!("{}\n", json))
+                .map_err(|e| io_error(&format!("Could not write JSON: {}", e)))
+        },
+        LinkFormat::Toml => toml::to_string(&TomlLinks { links: records }).map_err(|e| io_error(&format!("Could not write TOML: {}", e))),
+        LinkFormat::Csv => Ok(to_csv(&records)),
+        LinkFormat::Sh => Ok(to_sh(&records)),
+    }
+}
+
+/// Reads the links in an export. Names and targets must be valid UTF-8.
+pub fn parse_links(contents: &str, format: LinkFormat) -> HopEffect<Vec<LinkRecord>> {
+    match format {
+        LinkFormat::Json => serde_json::from_str(contents).map_err(|e| io_error(&format!("Could not read JSON: {}", e))),
+        LinkFormat::Toml => {
+            toml::from_str::<TomlLinks>(contents)
+                .map(|toml_links| toml_links.links)
+                .map_err(|e| io_error(&format!("Could not read TOML: {}", e)))
+        },
+        LinkFormat::Csv => from_csv(contents),
+        LinkFormat::Sh => Err(io_error("An sh export can't be imported. Run it with sh instead.")),
+    }
+}
+
+/// Reads the bookmarks of another tool. Lines that aren't bookmarks, such as comments, are ignored. Targets that
+/// start with `~` or `$HOME` are resolved against `home`.
+pub fn parse_bookmarks(contents: &str, tool: BookmarkTool, home: &Path) -> Vec<LinkRecord> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter_map(|line| match tool {
+            //export DIR_name="/path"
+            BookmarkTool::Bashmarks => {
+                let (name, target) = line.strip_prefix("export DIR_")?.split_once('=')?;
+                Some((name, target.trim_matches('"')))
+            },
+            //name:/path
+            BookmarkTool::Wd => line.split_once(':'),
+            //j,name,/path
+            BookmarkTool::Apparix => line.strip_prefix("j,")?.split_once(','),
+        })
+        .filter(|(name, target)| !name.is_empty() && !target.is_empty())
+        .map(|(name, target)| LinkRecord {
+            name: name.to_string(),
+            target: expand_bookmark_home(target, home),
+            layer: String::new(),
+        })
+        .collect()
+}
+
+/// Reads the directories in a tool's database. Lines that can't be read are ignored.
+pub fn parse_visits(contents: &str, tool: FrecencyTool) -> Vec<DirVisit> {
+    contents
+        .lines()
+        .filter_map(|line| match tool {
+            //path|rank|time
+            FrecencyTool::Z | FrecencyTool::Fasd => {
+                let mut fields = line.rsplitn(3, '|');
+                let _time = fields.next()?;
+                let rank = fields.next()?;
+                Some((fields.next()?, rank))
+            },
+            //weight<tab>path
+            FrecencyTool::Autojump => line.split_once('\t').map(|(weight, path)| (path, weight)),
+        })
+        .filter_map(|(dir, score)| {
+            Some(DirVisit {
+                dir: PathBuf::from(dir),
+                score: score.trim().parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Reads the operations in a `hop batch` script. Each line is either an operation written as words, such as
+/// `mark NAME TARGET`, or a JSON object, such as `{"op": "mark", "name": "NAME", "target": "TARGET"}`. Blank lines
+/// and lines starting with `#` are ignored. The target is the rest of the line, so it may hold spaces.
+pub fn parse_batch(contents: &str) -> HopEffect<Vec<BatchOp>> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            let op = if line.starts_with('{') {
+                serde_json::from_str::<BatchRecord>(line)
+                    .map(BatchRecord::into_op)
+                    .map_err(|e| e.to_string())
+            } else {
+                batch_op_from_words(line)
+            };
+
+            op.map_err(|e| io_error(&format!("Could not read line {} of the batch: {}", number, e)))
+        })
+        .collect()
+}
+
+/// An operation in a batch written as JSON.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase", deny_unknown_fields)]
+enum BatchRecord {
+    Mark { name: String, target: String },
+    Delete { name: String },
+    Rename { name: String, new_name: String },
+    Retarget { name: String, target: String },
+}
+
+impl BatchRecord {
+    fn into_op(self) -> BatchOp {
+        match self {
+            BatchRecord::Mark { name, target } => BatchOp::Mark(LinkPair::new(name, target)),
+            BatchRecord::Delete { name } => BatchOp::Delete(Link::new(name)),
+            BatchRecord::Rename { name, new_name } => BatchOp::Rename(Link::new(name), Link::new(new_name)),
+            BatchRecord::Retarget { name, target } => BatchOp::Retarget(LinkPair::new(name, target)),
+        }
+    }
+}
+
+fn batch_op_from_words(line: &str) -> Result<BatchOp, String> {
+    let (op, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let args = args.trim();
+    let (name, rest) = args.split_once(char::is_whitespace).map_or((args, ""), |(name, rest)| (name, rest.trim()));
+
+    match (op, name, rest) {
+        ("mark", name, target) if !name.is_empty() && !target.is_empty() => Ok(BatchOp::Mark(LinkPair::new(name, target))),
+        ("delete", name, "") if !name.is_empty() => Ok(BatchOp::Delete(Link::new(name))),
+        ("rename", name, new_name) if !name.is_empty() && !new_name.is_empty() && !new_name.contains(char::is_whitespace) => {
+            Ok(BatchOp::Rename(Link::new(name), Link::new(new_name)))
+        },
+        ("retarget", name, target) if !name.is_empty() && !target.is_empty() => {
+            Ok(BatchOp::Retarget(LinkPair::new(name, target)))
+        },
+        ("mark", ..) => Err("expected `mark NAME TARGET`".to_string()),
+        ("delete", ..) => Err("expected `delete NAME`".to_string()),
+        ("rename", ..) => Err("expected `rename NAME NEW_NAME`".to_string()),
+        ("retarget", ..) => Err("expected `retarget NAME TARGET`".to_string()),
+        (op, ..) => Err(format!("`{}` is not an operation. Use mark, delete, rename or retarget.", op)),
+    }
+}
+
+fn expand_bookmark_home(target: &str, home: &Path) -> String {
+    let home_relative =
+        ["~", "$HOME", "${HOME}"]
+        .iter()
+        .find_map(|prefix| target.strip_prefix(prefix).filter(|rest| rest.is_empty() || rest.starts_with('/')));
+
+    match home_relative {
+        Some(rest) => format!("{}{}", home.to_string_lossy(), rest),
+        None => target.to_string(),
+    }
+}
+
+/// Changes a name from another tool so it can be used with hop. Names are limited to letters, numbers, `.`, `_`
+/// and `-`, and can't start with `.` or `-`, so they can always be typed on the command line. Any other characters
+/// are replaced with `-`. Returns `None` if nothing is left.
+pub fn sanitise_name(name: &str) -> Option<String> {
+    let mut sanitised = String::new();
+
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-' {
+            sanitised.push(c)
+        } else if !sanitised.ends_with('-') {
+            sanitised.push('-')
+        }
+    }
+
+    let sanitised = sanitised.trim_start_matches(['.', '-']).trim_end_matches('-');
+
+    Some(sanitised.to_string()).filter(|name| !name.is_empty())
+}
+
+/// `name`, or `name` with the first numbered suffix, such as `code-2`, that isn't used by any of the `link_pairs`.
+pub fn unused_name(name: &str, link_pairs: &[LinkPair]) -> HopEffect<String> {
+    let is_taken = |candidate: &str| link_pairs.iter().any(|lp| lp.link.0 == candidate);
+
+    std::iter::once(name.to_string())
+        .chain((2..usize::MAX).map(|suffix| format!("{}-{}", name, suffix)))
+        .find(|candidate| !is_taken(candidate))
+        .ok_or_else(|| io_error(&format!("Could not find an unused name for `{}`.", name)))
+}
+
+/// Reads the `file://` bookmarks of GTK file managers. A bookmark is named after its label, or its directory if it
+/// has no label, and names are made to follow hop's naming rules. Other bookmarks, such as `sftp://`, are ignored.
+pub fn parse_gtk_bookmarks(contents: &str) -> Vec<LinkPair> {
+    gtk_bookmark_lines(contents).into_iter().filter_map(|(_, link_pair)| link_pair).collect()
+}
+
+/// Makes the GTK side of a sync. Bookmarks that aren't changed, including any that aren't `file://` bookmarks, are
+/// kept as they are and new bookmarks are added at the end.
+pub fn update_gtk_bookmarks(contents: &str, changes: &[SyncChange]) -> String {
+    let mut lines: Vec<String> = vec![];
+
+    for (line, link_pair) in gtk_bookmark_lines(contents) {
+        let change = link_pair.and_then(|lp| {
+            changes.iter().find(|change| match change {
+                SyncChange::Update(pair) | SyncChange::Remove(pair) => pair.link == lp.link,
+                SyncChange::Add(_) => false,
+            })
+        });
+
+        match change {
+            Some(SyncChange::Update(pair)) => lines.push(gtk_bookmark_line(pair)),
+            Some(_) => (),
+            None => lines.push(line.to_string()),
+        }
+    }
+
+    for change in changes {
+        if let SyncChange::Add(pair) = change {
+            lines.push(gtk_bookmark_line(pair))
+        }
+    }
+
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+fn gtk_bookmark_lines(contents: &str) -> Vec<(&str, Option<LinkPair>)> {
+    let mut link_pairs: Vec<LinkPair> = vec![];
+
+    contents
+        .lines()
+        .map(|line| {
+            let (uri, label) = line.split_once(' ').unwrap_or((line, ""));
+            let link_pair = uri.strip_prefix("file://").and_then(|encoded_path| {
+                let dir = PathBuf::from(OsString::from_vec(percent_decode(encoded_path)));
+                let name = match label.trim() {
+                    "" => dir.file_name().map(|name| name.to_string_lossy().to_string())?,
+                    label => label.to_string(),
+                };
+
+                let pair = LinkPair::new(unused_name(&sanitise_name(&name)?, &link_pairs).ok()?, dir);
+                link_pairs.push(pair.clone());
+                Some(pair)
+            });
+
+            (line, link_pair)
+        })
+        .collect()
+}
+
+fn gtk_bookmark_line(link_pair: &LinkPair) -> String {
+    format!("file://{} {}", percent_encode(link_pair.target.as_os_str().as_bytes()), link_pair.link)
+}
+
+/// Decodes `%XX` escapes in a URI path. Anything that isn't a valid escape is kept as it is.
+fn percent_decode(encoded: &str) -> Vec<u8> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = vec![];
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped =
+            Some(bytes[index])
+            .filter(|&byte| byte == b'%')
+            .and_then(|_| bytes.get(index + 1..index + 3))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3
+            },
+            None => {
+                decoded.push(bytes[index]);
+                index += 1
+            },
+        }
+    }
+
+    decoded
+}
+
+/// Escapes everything in a path other than unreserved URI characters and `/`.
+fn percent_encode(path: &[u8]) -> String {
+    path
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+const CSV_HEADER: &str = "name,target,layer";
+
+fn to_csv(records: &[LinkRecord]) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+
+    for record in records {
+        csv.push_str(&format!("{},{},{}\n", csv_field(&record.name), csv_field(&record.target), csv_field(&record.layer)))
+    }
+
+    csv
+}
+
+/// The first row must be a header with `name` and `target` columns. Any other columns are ignored.
+fn from_csv(contents: &str) -> HopEffect<Vec<LinkRecord>> {
+    let mut rows = csv_rows(contents)?.into_iter();
+    let header = rows.next().ok_or_else(|| io_error("Could not read CSV: Missing the header row"))?;
+
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| column == name)
+            .ok_or_else(|| io_error(&format!("Could not read CSV: Missing the `{}` column", name)))
+    };
+
+    let name_column = column("name")?;
+    let target_column = column("target")?;
+    let layer_column = column("layer").ok();
+
+    rows
+        .enumerate()
+        .filter(|(_, row)| row.iter().any(|field| !field.is_empty()))
+        .map(|(index, row)| {
+            let field = |column: usize| {
+                row
+                    .get(column)
+                    .cloned()
+                    .ok_or_else(|| io_error(&format!("Could not read CSV: Row {} has too few columns", index + 2)))
+            };
+
+            Ok(LinkRecord {
+                name: field(name_column)?,
+                target: field(target_column)?,
+                layer: layer_column.map_or_else(|| Ok(String::new()), field)?,
+            })
+        })
+        .collect()
+}
+
+/// Splits CSV into rows of fields. Quoted fields may contain separators, doubled quotes and line breaks.
+fn csv_rows(contents: &str) -> HopEffect<Vec<Vec<String>>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = contents.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"')
+            },
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => (),
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row))
+            },
+            (false, c) => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(io_error("Could not read CSV: A quoted field is missing its closing quote"))
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row)
+    }
+
+    Ok(rows)
+}
+
+/// Quotes a field if it contains a separator, quote or line break, doubling any quotes within it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Each link is only marked if the hop home has no link with exactly its name, so the script can be run any number
+/// of times. Jumping to the name instead would also match links in shared or local hop homes, or by prefix.
+fn to_sh(records: &[LinkRecord]) -> String {
+    let mut sh = String::from("#!/bin/sh\n");
+
+    for record in records {
+        sh.push_str(&format!(
+            "\n# {}\nhop --if-missing -m {} {}\n",
+            record.layer.replace('\n', " "),
+            sh_quote(&record.name),
+            sh_quote(&record.target)
+        ))
+    }
+
+    sh
+}
+
+/// Wraps `value` in single quotes, which stop the shell from interpreting anything but a single quote.
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
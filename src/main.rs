@@ -1,9 +1,15 @@
 use algebra::hop;
-use clap::{App, Arg};
-use models::{Link, LinkPair, HomeType};
+use algebra::user_dirs::UserDirs;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use models::{Link, LinkPair, LinkTarget, PromptMode};
 use prod::prod_models::Prod;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
 mod algebra;
+mod config;
+mod formats;
 mod models;
 mod prod;
 mod program;
@@ -16,13 +22,43 @@ fn main() {
         .version(APPVERSION)
         .author("Sanj Sahayam")
         .about("Hop to frequently used directories")
+        .after_help("Default options, such as -c, --profile, --no-local, --yes and --no-input, can be supplied through the HOP_OPTS environment variable. Flags on the command line override them.")
         .arg(
             Arg::with_name("config")
                 .short("c")
                 .long("c")
                 .value_name("HOP_HOME")
-                .help("Absolute path to the hop home directory. Defaults to ~/.hop if not specified")
-                .takes_value(true),
+                .help("Absolute path to the hop home directory. Defaults to $HOP_HOME or $XDG_DATA_HOME/hop if not specified")
+                .takes_value(true)
+                .overrides_with("config"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .value_name("PROFILE")
+                .help("Use the hop home of the named profile")
+                .takes_value(true)
+                .validator(|profile| config::validate_profile_name(&profile))
+                .overrides_with("profile"),
+        )
+        .arg(
+            Arg::with_name("no-local")
+                .long("no-local")
+                .help("Ignores links in the nearest .hop directory of the working directory or its parents"),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .short("y")
+                .long("yes")
+                .help("Answers yes to every question, such as when deleting a mark")
+                .conflicts_with("no-input")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("no-input")
+                .long("no-input")
+                .help("Takes the default answer of every question instead of reading stdin")
+                .global(true),
         )
         .arg(
             Arg::with_name("list")
@@ -48,49 +84,562 @@ fn main() {
             Arg::with_name("mark")
                 .short("m")
                 .long("mark")
-                .value_names(&["NAME", "PATH"])
-                .help("Mark a named directory")
-                .takes_value(true),
+                .value_name("NAME")
+                .help("Mark a directory with `hop -m NAME PATH`. PATH defaults to the working directory and NAME to the name of the directory")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(2),
+        )
+        .arg(
+            Arg::with_name("git-root")
+                .long("git-root")
+                .help("Marks the root of the git repository holding PATH, rather than PATH itself")
+                .requires("mark"),
+        )
+        .arg(
+            Arg::with_name("if-missing")
+                .long("if-missing")
+                .help("Only marks if there is no link with the same name in the hop home, and succeeds either way")
+                .requires("mark"),
         )
         .arg(
             Arg::with_name("delete")
                 .short("d")
                 .long("delete")
                 .value_name("NAME")
-                .help("Delete a named directory")
-                .takes_value(true),
+                .help("Delete named directories. Names may be glob patterns using * and ?")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Inspect hop configuration")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows the hop home in use and where it was configured from"),
+                )
+                .subcommand(
+                    SubCommand::with_name("check")
+                        .about("Validates the config file"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("profile")
+                .about("Manage profiles, each with their own hop home")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("use")
+                        .about("Makes a profile active until another is chosen. Use `default` to go back to the default hop home")
+                        .arg(
+                            Arg::with_name("name")
+                                .value_name("PROFILE")
+                                .required(true)
+                                .validator(|profile| config::validate_profile_name(&profile)),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists known profiles. The active profile is marked with *"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Exports links, along with the hop home each is from, to stdout or a file")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Format of the export. `sh` writes hop commands that mark any missing links")
+                        .possible_values(&formats::LinkFormat::NAMES)
+                        .default_value("json"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Writes the export to FILE instead of stdout")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Imports links from a file written by hop export or from another bookmarking tool")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("File to import. Defaults to the bookmarks file of the tool given to --from")
+                        .required_unless("from"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Format of FILE. Defaults to the extension of FILE")
+                        .possible_values(&["json", "toml", "csv"])
+                        .conflicts_with("from"),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("TOOL")
+                        .help("Imports bookmarks from another tool. Names are changed to follow hop's naming rules")
+                        .possible_values(&formats::BookmarkTool::NAMES),
+                )
+                .arg(
+                    Arg::with_name("on-conflict")
+                        .long("on-conflict")
+                        .value_name("POLICY")
+                        .help("What to do when a link with the same name already exists")
+                        .possible_values(&models::ConflictPolicy::NAMES)
+                        .default_value("skip"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Shows what would be imported without changing any links"),
+                )
+                .arg(
+                    Arg::with_name("allow-missing")
+                        .long("allow-missing")
+                        .help("Imports links to directories that don't exist"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compares your links with another hop home or a file written by hop export")
+                .arg(
+                    Arg::with_name("other")
+                        .value_name("OTHER")
+                        .help("A hop home directory or export file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Format of OTHER when it is a file. Defaults to the extension of OTHER")
+                        .possible_values(&["json", "toml", "csv"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("merge")
+                .about("Adds the links from another hop home or export file, asking which side wins for each link that differs")
+                .arg(
+                    Arg::with_name("other")
+                        .value_name("OTHER")
+                        .help("A hop home directory or export file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Format of OTHER when it is a file. Defaults to the extension of OTHER")
+                        .possible_values(&["json", "toml", "csv"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("suggest")
+                .about("Suggests marks for your most visited directories from the database of z, autojump or fasd")
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("TOOL")
+                        .help("The tool to read visited directories from")
+                        .possible_values(&formats::FrecencyTool::NAMES)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("The tool's database. Defaults to where the tool keeps it"),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .long("limit")
+                        .value_name("COUNT")
+                        .help("The most directories to suggest")
+                        .default_value("10")
+                        .validator(|limit| limit.parse::<usize>().map(|_| ()).map_err(|_| format!("Invalid limit `{}`", limit))),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("scan")
+                .about("Finds git repositories in a directory tree and marks them, showing the marks before they are made")
+                .arg(
+                    Arg::with_name("dir")
+                        .value_name("DIR")
+                        .help("The directory to search. Defaults to the working directory")
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .value_name("DEPTH")
+                        .help("How many levels of directories below DIR to search")
+                        .default_value("3")
+                        .validator(|depth| depth.parse::<usize>().map(|_| ()).map_err(|_| format!("Invalid depth `{}`", depth))),
+                )
+                .arg(
+                    Arg::with_name("projects")
+                        .long("projects")
+                        .help("Also finds directories holding a Cargo.toml or package.json"),
+                )
+                .arg(
+                    Arg::with_name("tag")
+                        .long("tag")
+                        .value_name("TAG")
+                        .help("Starts the name of each mark with TAG, as in TAG-name")
+                        .validator(|tag| formats::sanitise_name(&tag).filter(|name| *name == tag).map(|_| ()).ok_or_else(|| format!("`{}` can't be used in a link name", tag))),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Shows the marks without making them"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sync")
+                .about("Pulls and pushes links to the git remote of the hop home, or keeps hop in sync with other bookmarks")
+                .arg(
+                    Arg::with_name("prefer")
+                        .long("prefer")
+                        .value_name("SIDE")
+                        .help("Which side wins when a link was changed both in the hop home and in the remote")
+                        .possible_values(&models::SyncPreference::NAMES),
+                )
+                .subcommand(
+                    SubCommand::with_name("gtk")
+                        .about("Syncs with the bookmarks in the sidebar of GTK file managers, showing the changes before they are made")
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Shows the changes without making them"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("undo")
+                .about("Reverts the last changes made to your links")
+                .arg(
+                    Arg::with_name("count")
+                        .value_name("COUNT")
+                        .help("How many changes to revert")
+                        .default_value("1")
+                        .validator(|count| count.parse::<usize>().ok().filter(|count| *count > 0).map(|_| ()).ok_or_else(|| format!("Invalid count `{}`", count))),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("log")
+                .about("Lists the changes made to your links, newest first")
+                .arg(
+                    Arg::with_name("limit")
+                        .short("n")
+                        .long("limit")
+                        .value_name("COUNT")
+                        .help("The most changes to list")
+                        .default_value("10")
+                        .validator(|limit| limit.parse::<usize>().map(|_| ()).map_err(|_| format!("Invalid limit `{}`", limit))),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("batch")
+                .about("Applies a script of mark, delete, rename and retarget operations, either all of them or none")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("The script to apply, one operation per line. Reads stdin if not given or `-`"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Saves and restores every link in the hop home")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .about("Saves the links in the hop home as a snapshot")
+                        .arg(
+                            Arg::with_name("label")
+                                .value_name("LABEL")
+                                .help("A note to tell the snapshot apart"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists the snapshots of the hop home, newest first"),
+                )
+                .subcommand(
+                    SubCommand::with_name("restore")
+                        .about("Puts the links back as they were in a snapshot, showing the changes before they are made")
+                        .arg(
+                            Arg::with_name("id")
+                                .value_name("ID")
+                                .help("The id of the snapshot, as shown by hop snapshot list")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Shows the changes without making them"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("trash")
+                .about("Lists, restores or removes deleted links")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists the deleted links in the trash, newest first"),
+                )
+                .subcommand(
+                    SubCommand::with_name("restore")
+                        .about("Restores the most recently deleted link with a name")
+                        .arg(
+                            Arg::with_name("name")
+                                .value_name("NAME")
+                                .help("The name of the deleted link")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("empty")
+                        .about("Permanently removes every link in the trash"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate-home")
+                .about("Moves links from ~/.hop to $XDG_DATA_HOME/hop and leaves a symlink at ~/.hop"),
         );
 
     let mut app2 = app.clone(); //we need this close to display usage on error
-    let matches = app.get_matches();
+    let opts_app = app.clone();
+    let args_app = app.clone();
 
-    let hop_home =
+    let default_opts = config::default_opts(env::var_os(config::HOP_OPTS_ENV));
+    if let Err(e) = config::validate_default_opts(&default_opts) {
+        clap::Error::with_description(&e, clap::ErrorKind::InvalidValue).exit()
+    }
+
+    let matches = app.get_matches_from(config::with_default_opts(env::args_os(), &default_opts));
+
+    //Parse HOP_OPTS and the command line on their own, so we know which flags came from where
+    let opts_matches =
+        opts_app
+        .get_matches_from_safe(config::with_default_opts(vec![OsString::from("hop")], &default_opts))
+        .ok();
+
+    let args_matches = args_app.get_matches_from_safe(env::args_os()).ok();
+
+    let config_path = config::config_file_path(env::var_os(config::XDG_CONFIG_HOME_ENV)).ok();
+
+    let checking_config =
         matches
-        .value_of("config")
-        .map(|hd| HomeType::Absolute(hd.to_string()))
-        .unwrap_or_else(|| HomeType::Relative(".hop".to_string()));
+        .subcommand_matches("config")
+        .and_then(|m| m.subcommand_matches("check"))
+        .is_some();
+
+    //Don't report problems with the config file twice when checking it
+    let config_file =
+        config_path
+        .as_ref()
+        .and_then(|path| program::load_config_file(path, !checking_config));
+
+    let hop_config = config_file.map(|file| file.config).unwrap_or_default();
+
+    let active_profile_path = config::active_profile_path(env::var_os(config::XDG_CONFIG_HOME_ENV)).ok();
+    let active_profile =
+        active_profile_path
+        .as_ref()
+        .and_then(|path| program::load_active_profile(path, &hop_config));
+
+    let hop_home =
+        config::resolve_hop_home(
+            args_matches.as_ref().map(|m| home_flags(m)).unwrap_or_default(),
+            opts_matches.as_ref().map(|m| home_flags(m)).unwrap_or_default(),
+            env::var_os(config::HOP_HOME_ENV),
+            active_profile.as_deref(),
+            &hop_config
+        );
+
+    let local_home =
+        if matches.is_present("no-local") {
+            None
+        } else {
+            let hop_homes: Vec<PathBuf> =
+                Prod.get_hop_home(&hop_home.home_type)
+                .into_iter()
+                .chain(hop_config.shared.iter().map(|shared| shared.home.clone()))
+                .collect();
+
+            env::current_dir().ok().and_then(|working_dir| config::find_local_hop_home(&working_dir, &hop_homes))
+        };
 
     let hop_program = hop::HopProgram {
         value: Prod,
-        hop_home_dir: hop_home,
+        hop_homes: config::home_layers(&hop_home, &hop_config, local_home),
+        config: hop_config,
+        prompts: prompt_mode(&matches),
     };
 
-    if matches.is_present("list") {
+    if let ("config", Some(config_matches)) = matches.subcommand() {
+        if config_matches.subcommand_matches("show").is_some() {
+            program::handle_config_show(&hop_program, &hop_home, config_path.as_deref())
+        } else if config_matches.subcommand_matches("check").is_some() {
+            program::handle_config_check(&hop_program, config_path.as_deref())
+        }
+    } else if let ("profile", Some(profile_matches)) = matches.subcommand() {
+        if let Some(use_matches) = profile_matches.subcommand_matches("use") {
+            let profile = use_matches.value_of("name").expect("expected profile name");
+            program::handle_profile_use(&hop_program, active_profile_path.as_deref(), profile)
+        } else if profile_matches.subcommand_matches("list").is_some() {
+            program::handle_profile_list(&hop_program, hop_home.profile.as_deref())
+        }
+    } else if let Some(export_matches) = matches.subcommand_matches("export") {
+        let format =
+            export_matches
+            .value_of("format")
+            .and_then(formats::LinkFormat::from_name)
+            .expect("expected export format");
+
+        program::handle_export(&hop_program, format, export_matches.value_of_os("output").map(Path::new))
+    } else if let Some(import_matches) = matches.subcommand_matches("import") {
+        let import_path = import_matches.value_of_os("file").map(Path::new);
+        let source = match import_matches.value_of("from").and_then(formats::BookmarkTool::from_name) {
+            Some(tool) => formats::ImportSource::Bookmarks(tool),
+            None => formats::ImportSource::Export(import_matches.value_of("format").and_then(formats::LinkFormat::from_name)),
+        };
+
+        let options = models::ImportOptions {
+            on_conflict:
+                import_matches
+                .value_of("on-conflict")
+                .and_then(models::ConflictPolicy::from_name)
+                .expect("expected conflict policy"),
+            dry_run: import_matches.is_present("dry-run"),
+            allow_missing: import_matches.is_present("allow-missing"),
+        };
+
+        program::handle_import(&hop_program, import_path, source, &options)
+    } else if let Some(diff_matches) = matches.subcommand_matches("diff") {
+        let other_path = diff_matches.value_of_os("other").map(Path::new).expect("expected other hop home");
+        let format = diff_matches.value_of("format").and_then(formats::LinkFormat::from_name);
+
+        program::handle_diff(&hop_program, other_path, format)
+    } else if let Some(merge_matches) = matches.subcommand_matches("merge") {
+        let other_path = merge_matches.value_of_os("other").map(Path::new).expect("expected other hop home");
+        let format = merge_matches.value_of("format").and_then(formats::LinkFormat::from_name);
+
+        program::handle_merge(&hop_program, other_path, format)
+    } else if let Some(suggest_matches) = matches.subcommand_matches("suggest") {
+        let tool =
+            suggest_matches
+            .value_of("from")
+            .and_then(formats::FrecencyTool::from_name)
+            .expect("expected tool");
+
+        let limit =
+            suggest_matches
+            .value_of("limit")
+            .and_then(|limit| limit.parse().ok())
+            .expect("expected limit");
+
+        program::handle_suggest(&hop_program, tool, suggest_matches.value_of_os("file").map(Path::new), limit)
+    } else if let Some(sync_matches) = matches.subcommand_matches("sync") {
+        if let Some(gtk_matches) = sync_matches.subcommand_matches("gtk") {
+            let bookmarks_path = config::gtk_bookmarks_path(env::var_os(config::XDG_CONFIG_HOME_ENV)).ok();
+            let state_path = config::sync_state_path(env::var_os(config::XDG_STATE_HOME_ENV), "gtk").ok();
+
+            program::handle_sync_gtk(&hop_program, bookmarks_path.as_deref(), state_path.as_deref(), gtk_matches.is_present("dry-run"))
+        } else {
+            let prefer = sync_matches.value_of("prefer").and_then(models::SyncPreference::from_name);
+            program::handle_sync_git(&hop_program, prefer)
+        }
+    } else if let Some(undo_matches) = matches.subcommand_matches("undo") {
+        let count =
+            undo_matches
+            .value_of("count")
+            .and_then(|count| count.parse().ok())
+            .expect("expected count");
+
+        program::handle_undo(&hop_program, count)
+    } else if let Some(log_matches) = matches.subcommand_matches("log") {
+        let limit =
+            log_matches
+            .value_of("limit")
+            .and_then(|limit| limit.parse().ok())
+            .expect("expected limit");
+
+        program::handle_log(&hop_program, limit)
+    } else if let Some(scan_matches) = matches.subcommand_matches("scan") {
+        let dir = LinkTarget::new(scan_matches.value_of_os("dir").expect("expected dir"));
+
+        let options = models::ScanOptions {
+            depth: scan_matches.value_of("depth").and_then(|depth| depth.parse().ok()).expect("expected depth"),
+            projects: scan_matches.is_present("projects"),
+            tag: scan_matches.value_of("tag").map(str::to_string),
+        };
+
+        program::handle_scan(&hop_program, &dir, &options, scan_matches.is_present("dry-run"))
+    } else if let Some(batch_matches) = matches.subcommand_matches("batch") {
+        let batch_path = batch_matches.value_of_os("file").filter(|path| *path != "-").map(Path::new);
+        program::handle_batch(&hop_program, batch_path)
+    } else if let ("snapshot", Some(snapshot_matches)) = matches.subcommand() {
+        if let Some(create_matches) = snapshot_matches.subcommand_matches("create") {
+            program::handle_snapshot_create(&hop_program, create_matches.value_of("label"))
+        } else if snapshot_matches.subcommand_matches("list").is_some() {
+            program::handle_snapshot_list(&hop_program)
+        } else if let Some(restore_matches) = snapshot_matches.subcommand_matches("restore") {
+            let id = restore_matches.value_of("id").expect("expected snapshot id");
+            program::handle_snapshot_restore(&hop_program, id, restore_matches.is_present("dry-run"))
+        }
+    } else if let ("trash", Some(trash_matches)) = matches.subcommand() {
+        if trash_matches.subcommand_matches("list").is_some() {
+            program::handle_trash_list(&hop_program)
+        } else if let Some(restore_matches) = trash_matches.subcommand_matches("restore") {
+            let link = restore_matches.value_of_os("name").expect("expected link name");
+            program::handle_trash_restore(&hop_program, &Link::new(link))
+        } else if trash_matches.subcommand_matches("empty").is_some() {
+            program::handle_trash_empty(&hop_program)
+        }
+    } else if matches.subcommand_matches("migrate-home").is_some() {
+        program::handle_migrate_home(&hop_program)
+    } else if matches.is_present("list") {
         program::handle_list(&hop_program)
     } else if matches.is_present("table") {
-        program::handle_table(&hop_program)
-    } else if let Some(jump_target) = matches.value_of("jump") {
-        program::handle_jump(&hop_program, jump_target)
-    } else if let Some(m) = matches.values_of("mark") {
-        let mut values = m.clone();
-        let link = values.next().expect("expected link name");
-        let target = values.next().expect("expected target value");
-
-        program::handle_mark(&hop_program, &LinkPair::new(link, target))
-    } else if let Some(d) = matches.value_of("delete") {
-        program::handle_delete(&hop_program, &Link(d.to_string()))
+        program::handle_table(&hop_program, hop_home.profile.as_deref())
+    } else if let Some(jump_target) = matches.value_of_os("jump") {
+        program::handle_jump(&hop_program, &Link::new(jump_target))
+    } else if matches.is_present("mark") {
+        let mut values = matches.values_of_os("mark").unwrap_or_default();
+        let link = values.next().map(Link::new);
+        let target = values.next().map(LinkTarget::new);
+
+        program::handle_mark(&hop_program, link, target, matches.is_present("git-root"), matches.is_present("if-missing"))
+    } else if let Some(names) = matches.values_of_os("delete") {
+        let links: Vec<Link> = names.map(Link::new).collect();
+        program::handle_delete(&hop_program, &links)
     } else {
         let _result = app2.print_help();
         println!();
     };
 }
+
+fn home_flags<'a>(matches: &'a ArgMatches<'_>) -> config::HomeFlags<'a> {
+    config::HomeFlags {
+        home: matches.value_of_os("config"),
+        profile: matches.value_of("profile"),
+    }
+}
+
+/// Global flags are only propagated down to subcommands, so the most deeply nested subcommand is checked.
+fn prompt_mode(matches: &ArgMatches<'_>) -> PromptMode {
+    match matches.subcommand() {
+        (_, Some(sub_matches)) => prompt_mode(sub_matches),
+        _ if matches.is_present("yes") => PromptMode::AssumeYes,
+        _ if matches.is_present("no-input") => PromptMode::UseDefault,
+        _ => PromptMode::Ask,
+    }
+}
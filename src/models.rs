@@ -1,24 +1,64 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
 
 pub type HopEffect<T> = io::Result<T>;
 
+/// The name of a mark. Stored as an `OsString` so that names which are not valid UTF-8 survive a round trip
+/// through the hop home. Use the `Display` impl only for human-facing output.
 #[derive(Debug, Clone, PartialEq)]
-pub struct Link(pub String);
+pub struct Link(pub OsString);
 
 impl Link {
-    pub fn new(link: &str) -> Self {
-        Self(link.to_string())
+    pub fn new<S: Into<OsString>>(link: S) -> Self {
+        Self(link.into())
     }
 }
 
+/// The directory a mark points to. Stored as a `PathBuf` so that targets which are not valid UTF-8 are not
+/// corrupted. Use the `Display` impl only for human-facing output.
 #[derive(Debug, Clone, PartialEq)]
-pub struct LinkTarget(pub String);
+pub struct LinkTarget(pub PathBuf);
 
 impl LinkTarget {
+    pub fn new<P: Into<PathBuf>>(target: P) -> Self {
+        Self(target.into())
+    }
+
     pub fn to_path_buf(&self) -> PathBuf {
-        PathBuf::from(&self.0)
+        self.0.clone()
+    }
+
+    pub fn as_os_str(&self) -> &OsStr {
+        self.0.as_os_str()
+    }
+}
+
+/// A name or target as it is saved in hop's JSON files. Valid UTF-8 is saved as a string so that the files stay
+/// readable. Anything else is saved as its raw bytes, so that saving it never changes it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StoredOsString {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl StoredOsString {
+    pub fn new<S: AsRef<OsStr>>(value: S) -> Self {
+        match value.as_ref().to_str() {
+            Some(text) => StoredOsString::Text(text.to_string()),
+            None => StoredOsString::Bytes(value.as_ref().as_bytes().to_vec()),
+        }
+    }
+
+    pub fn into_os_string(self) -> OsString {
+        match self {
+            StoredOsString::Text(text) => OsString::from(text),
+            StoredOsString::Bytes(bytes) => OsString::from_vec(bytes),
+        }
     }
 }
 
@@ -30,30 +70,352 @@ pub struct LinkPair {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum HomeType {
-    Relative(String),
-    Absolute(String),
+    /// `$XDG_DATA_HOME/hop`, or the legacy `~/.hop` if it already exists.
+    Xdg,
+    /// `$XDG_DATA_HOME/hop-profiles/<profile>`
+    Profile(String),
+    Absolute(PathBuf),
 }
 
 impl LinkPair {
-    pub fn new(link: &str, target: &str) -> Self {
+    pub fn new<S: Into<OsString>, P: Into<PathBuf>>(link: S, target: P) -> Self {
         LinkPair {
-            link: Link(link.to_string()),
-            target: LinkTarget(target.to_string()),
+            link: Link::new(link),
+            target: LinkTarget::new(target),
         }
     }
 }
 
+/// A hop home that links are read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HomeLayer {
+    pub name: String,
+    pub home_type: HomeType,
+    /// Links are only written to writable layers. Read-only layers that don't exist are skipped.
+    pub writable: bool,
+    /// Relative link targets are resolved against this directory instead of the hop home.
+    pub relative_to: Option<PathBuf>,
+}
+
+impl HomeLayer {
+    pub fn personal(home_type: HomeType) -> Self {
+        HomeLayer {
+            name: PERSONAL_LAYER.to_string(),
+            home_type,
+            writable: true,
+            relative_to: None,
+        }
+    }
+
+    pub fn shared(name: &str, home: PathBuf) -> Self {
+        HomeLayer {
+            name: name.to_string(),
+            home_type: HomeType::Absolute(home),
+            writable: false,
+            relative_to: None,
+        }
+    }
+
+    /// A `.hop` directory within a project. Relative targets are resolved against the directory holding `.hop`.
+    pub fn local(home: PathBuf) -> Self {
+        HomeLayer {
+            name: LOCAL_LAYER.to_string(),
+            relative_to: home.parent().map(Path::to_path_buf),
+            home_type: HomeType::Absolute(home),
+            writable: false,
+        }
+    }
+}
+
+/// The name of the layer for the hop home chosen through `-c`, `HOP_HOME`, profiles or the default.
+pub const PERSONAL_LAYER: &str = "personal";
+
+/// The name of the layer for the `.hop` directory found by walking up from the working directory.
+pub const LOCAL_LAYER: &str = "local";
+
+/// A link along with the name of the layer it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayeredLinkPair {
+    pub link_pair: LinkPair,
+    pub layer: String,
+}
+
+impl LayeredLinkPair {
+    pub fn new(link_pair: LinkPair, layer: &str) -> Self {
+        LayeredLinkPair {
+            link_pair,
+            layer: layer.to_string(),
+        }
+    }
+}
+
+/// What to do when an imported link has the same name as an existing link.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictPolicy {
+    /// Keep the existing link.
+    Skip,
+    /// Point the existing link at the imported target.
+    Overwrite,
+    /// Import the link under a new name, such as `code-2`.
+    Rename,
+    /// Abort the import before any links are written.
+    Fail,
+}
+
+impl ConflictPolicy {
+    pub const NAMES: [&'static str; 4] = ["skip", "overwrite", "rename", "fail"];
+
+    pub fn from_name(name: &str) -> Option<ConflictPolicy> {
+        match name {
+            "skip" => Some(ConflictPolicy::Skip),
+            "overwrite" => Some(ConflictPolicy::Overwrite),
+            "rename" => Some(ConflictPolicy::Rename),
+            "fail" => Some(ConflictPolicy::Fail),
+            _ => None,
+        }
+    }
+}
+
+/// How hop answers the questions it asks before changing links.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptMode {
+    /// Ask on stdout and read the answer from stdin.
+    Ask,
+    /// Answer yes to every question.
+    AssumeYes,
+    /// Take the default answer of every question without reading stdin.
+    UseDefault,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImportOptions {
+    pub on_conflict: ConflictPolicy,
+    /// Work out what would be imported without writing any links.
+    pub dry_run: bool,
+    /// Import links to targets that don't exist on this machine.
+    pub allow_missing: bool,
+}
+
+/// Where `hop scan` looks for directories to mark and how it names them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanOptions {
+    /// How many levels of directories below the scanned directory are searched.
+    pub depth: usize,
+    /// Also find directories holding a `Cargo.toml` or `package.json`, rather than only git repositories.
+    pub projects: bool,
+    /// Put at the start of each name, as in `TAG-name`, so that the marks can be found together.
+    pub tag: Option<String>,
+}
+
+/// What happens to a link when it is imported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportAction {
+    Create(LinkPair),
+    Update(LinkPair),
+    /// The link was imported under a new name because its original name was taken.
+    Rename(Link, LinkPair),
+    Skip(LinkPair),
+}
+
+/// A directory from the database of a tool that tracks how often directories are visited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirVisit {
+    pub dir: PathBuf,
+    /// How highly the tool ranks the directory. Only the order matters, as each tool scores differently.
+    pub score: f64,
+}
+
+/// A change to one side of a sync.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncChange {
+    Add(LinkPair),
+    /// Point the link with the same name at a new target.
+    Update(LinkPair),
+    Remove(LinkPair),
+}
+
+/// The changes that bring hop and another set of bookmarks back in sync.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncPlan {
+    pub hop: Vec<SyncChange>,
+    pub other: Vec<SyncChange>,
+    /// Links with the same name that were changed to different targets on both sides. They are left as they are.
+    pub conflicts: Vec<(LinkPair, LinkPair)>,
+    /// The links both sides will have once the changes are made.
+    pub synced: Vec<LinkPair>,
+}
+
+/// A change to a single link. A missing target means the link didn't exist before or after the change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkChange {
+    pub link: Link,
+    pub before: Option<LinkTarget>,
+    pub after: Option<LinkTarget>,
+}
+
+impl LinkChange {
+    pub fn added(pair: &LinkPair) -> Self {
+        LinkChange {
+            link: pair.link.clone(),
+            before: None,
+            after: Some(pair.target.clone()),
+        }
+    }
+
+    pub fn removed(pair: &LinkPair) -> Self {
+        LinkChange {
+            link: pair.link.clone(),
+            before: Some(pair.target.clone()),
+            after: None,
+        }
+    }
+
+    pub fn retargeted(pair: &LinkPair, target: &LinkTarget) -> Self {
+        LinkChange {
+            link: pair.link.clone(),
+            before: Some(pair.target.clone()),
+            after: Some(target.clone()),
+        }
+    }
+
+    /// The change that puts the link back as it was before this one.
+    pub fn reverted(&self) -> Self {
+        LinkChange {
+            link: self.link.clone(),
+            before: self.after.clone(),
+            after: self.before.clone(),
+        }
+    }
+}
+
+/// A change to the hop home that can be undone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    /// When the change was made, in RFC 3339 format.
+    pub time: String,
+    pub description: String,
+    pub changes: Vec<LinkChange>,
+}
+
+/// An operation in a script run by `hop batch`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOp {
+    Mark(LinkPair),
+    Delete(Link),
+    Rename(Link, Link),
+    /// Point an existing link at a new target.
+    Retarget(LinkPair),
+}
+
+/// What happened to an operation in a batch. Either every operation in a batch is applied or none are.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOutcome {
+    Applied,
+    /// The operation can't be applied to the hop home, so the batch was not started.
+    Invalid(String),
+    /// The operation could be applied, but the batch was not started or stopped before reaching it.
+    NotApplied,
+    /// The operation was applied and then reverted as a later operation failed.
+    RolledBack,
+    Failed(String),
+}
+
+/// A deleted link that is kept in the trash of a hop home until it is restored or expires.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashedLink {
+    /// Tells apart links with the same name that were deleted at different times.
+    pub id: String,
+    pub link_pair: LinkPair,
+    /// When the link was deleted, in RFC 3339 format.
+    pub deleted: String,
+}
+
+/// The links of a hop home as they were at a point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub id: String,
+    pub label: Option<String>,
+    /// When the snapshot was taken, in RFC 3339 format.
+    pub created: String,
+    pub links: Vec<LinkPair>,
+}
+
+/// How the links in the hop home differ from another set of links, such as a teammate's hop home.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkDiff {
+    pub only_here: Vec<LinkPair>,
+    pub only_there: Vec<LinkPair>,
+    /// Links with the same name and different targets, as they are here and there.
+    pub different_targets: Vec<(LinkPair, LinkPair)>,
+    /// Links to the same target with different names, as they are here and there.
+    pub different_names: Vec<(LinkPair, LinkPair)>,
+}
+
+impl LinkDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_here.is_empty() &&
+        self.only_there.is_empty() &&
+        self.different_targets.is_empty() &&
+        self.different_names.is_empty()
+    }
+}
+
+/// Which side wins when a link was changed both in the hop home and in its git remote.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncPreference {
+    Local,
+    Remote,
+}
+
+impl SyncPreference {
+    pub const NAMES: [&'static str; 2] = ["local", "remote"];
+
+    pub fn from_name(name: &str) -> Option<SyncPreference> {
+        match name {
+            "local" => Some(SyncPreference::Local),
+            "remote" => Some(SyncPreference::Remote),
+            _ => None,
+        }
+    }
+}
+
+/// A link that was changed differently in the hop home and in its git remote. A missing target means the link was
+/// deleted on that side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitConflict {
+    pub link: Link,
+    pub local: Option<LinkTarget>,
+    pub remote: Option<LinkTarget>,
+}
+
+/// The result of syncing a git-backed hop home with its remote.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GitSyncStatus {
+    Synced,
+    /// Nothing was pulled or pushed, as these links were changed on both sides.
+    Conflicts(Vec<GitConflict>),
+}
+
+/// The result of moving the links in the legacy `~/.hop` into the XDG hop home.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HomeMigration {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub links: Vec<LinkPair>,
+}
+
 impl fmt::Display for Link {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.0.to_string_lossy())
     }
 }
 
 impl fmt::Display for HomeType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let path_type = match self {
-            HomeType::Absolute(path) => format!("Absolute({})", path),
-            HomeType::Relative(path) => format!("Relative({})", path),
+            HomeType::Absolute(path) => format!("Absolute({})", path.to_string_lossy()),
+            HomeType::Xdg => "Xdg".to_string(),
+            HomeType::Profile(profile) => format!("Profile({})", profile),
         };
 
         write!(f, "{}", path_type)
@@ -74,7 +436,7 @@ impl AsRef<Path> for LinkTarget {
 
 impl fmt::Display for LinkTarget {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.0.to_string_lossy())
     }
 }
 
@@ -83,3 +445,14 @@ impl fmt::Display for LinkPair {
         write!(f, "{} -> {}", self.link, self.target)
     }
 }
+
+impl fmt::Display for BatchOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchOp::Mark(pair) => write!(f, "mark {}", pair),
+            BatchOp::Delete(link) => write!(f, "delete {}", link),
+            BatchOp::Rename(link, new_link) => write!(f, "rename {} to {}", link, new_link),
+            BatchOp::Retarget(pair) => write!(f, "retarget {}", pair),
+        }
+    }
+}
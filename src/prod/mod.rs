@@ -1,5 +1,10 @@
 pub mod prod_directories;
+pub mod prod_git;
+pub mod prod_journal;
+pub mod prod_locks;
 pub mod prod_models;
+pub mod prod_snapshots;
 pub mod prod_std_io;
 pub mod prod_symlinks;
+pub mod prod_trash;
 pub mod prod_user_dirs;
@@ -1,11 +1,77 @@
 use super::prod_models::Prod;
 use crate::algebra::directories::Directories;
 use crate::models::HopEffect;
+use crate::program::io_error_ex;
 
-use std::path::Path;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
 impl Directories for Prod {
     fn dir_exists(&self, dir_path: &Path) -> HopEffect<bool> {
         Ok(dir_path.exists() && dir_path.is_dir())
     }
+
+    fn git_root(&self, dir_path: &Path) -> HopEffect<Option<PathBuf>> {
+        for dir in dir_path.ancestors() {
+            if is_git_root(dir)? {
+                return Ok(Some(dir.to_path_buf()))
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn find_projects(&self, dir_path: &Path, depth: usize, project_files: &[&str]) -> HopEffect<Vec<PathBuf>> {
+        let mut projects = vec![];
+        let mut dirs = vec![(dir_path.to_path_buf(), 0)];
+
+        while let Some((dir, level)) = dirs.pop() {
+            if is_git_root(&dir)? || project_files.iter().any(|file| dir.join(file).is_file()) {
+                projects.push(dir);
+                continue
+            }
+
+            if level == depth {
+                continue
+            }
+
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                //Directories that can't be read are skipped, as with the rest of a large tree
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied && dir != dir_path => continue,
+                Err(e) => return Err(io_error_ex(&format!("Could not read directory: {}", dir.to_string_lossy()), e)),
+            };
+
+            for entry in entries {
+                let entry = entry?;
+
+                //Symlinks are not followed, so that a directory is never found twice
+                if entry.file_type()?.is_dir() && !entry.file_name().to_string_lossy().starts_with('.') {
+                    dirs.push((entry.path(), level + 1))
+                }
+            }
+        }
+
+        projects.sort();
+        Ok(projects)
+    }
+}
+
+/// Whether `dir` is the root of a git working tree.
+fn is_git_root(dir: &Path) -> HopEffect<bool> {
+    let dot_git = dir.join(".git");
+
+    match fs::metadata(&dot_git) {
+        Ok(metadata) if metadata.is_dir() => Ok(true),
+        //Worktrees and submodules have a .git file that points to where the repository is kept
+        Ok(metadata) if metadata.is_file() => {
+            fs::read_to_string(&dot_git)
+                .map(|contents| contents.starts_with("gitdir:"))
+                .map_err(|e| io_error_ex(&format!("Could not read {}", dot_git.to_string_lossy()), e))
+        },
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(io_error_ex(&format!("Could not read {}", dot_git.to_string_lossy()), e)),
+    }
 }
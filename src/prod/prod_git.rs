@@ -0,0 +1,230 @@
+use super::prod_locks::LOCK_FILE;
+use super::prod_models::Prod;
+use super::prod_trash::TRASH_DIR;
+use crate::algebra::git::Git;
+use crate::models::{GitConflict, HopEffect, Link, LinkTarget, SyncPreference};
+use crate::program::{io_error, io_error_ex};
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// The branch of repositories created by hop, so that hop homes created on different machines share a branch.
+const BRANCH: &str = "main";
+
+impl Git for Prod {
+    fn init_repo(&self, dir: &Path) -> HopEffect<()> {
+        if !dir.join(".git").exists() {
+            git(dir, &["init", "-q", &format!("--initial-branch={}", BRANCH)])?;
+        }
+
+        exclude_local_state(dir)
+    }
+
+    fn commit_all(&self, dir: &Path, message: &str) -> HopEffect<()> {
+        git(dir, &["add", "-A"])?;
+
+        let status = git(dir, &["status", "--porcelain"])?;
+
+        if status.is_empty() {
+            Ok(())
+        } else {
+            git_as_hop(dir, &["commit", "-q", "-m", message]).map(|_| ())
+        }
+    }
+
+    fn set_remote(&self, dir: &Path, url: &str) -> HopEffect<()> {
+        if self.has_remote(dir)? {
+            git(dir, &["remote", "set-url", "origin", url]).map(|_| ())
+        } else {
+            git(dir, &["remote", "add", "origin", url]).map(|_| ())
+        }
+    }
+
+    fn has_remote(&self, dir: &Path) -> HopEffect<bool> {
+        let remotes = git(dir, &["remote"])?;
+        Ok(remotes.split(|b| *b == b'\n').any(|remote| remote == b"origin"))
+    }
+
+    fn pull(&self, dir: &Path, prefer: Option<SyncPreference>) -> HopEffect<Vec<GitConflict>> {
+        git(dir, &["fetch", "-q", "origin"])?;
+
+        let remote_branch = format!("refs/remotes/origin/{}", current_branch(dir)?);
+
+        //Nothing has been pushed to the remote yet
+        if !run(dir, &["rev-parse", "-q", "--verify", &remote_branch])?.status.success() {
+            return Ok(vec![])
+        }
+
+        //Links with the same target look like renames to git, so the resolve strategy is used as it doesn't detect
+        //renames. It needs a common commit, which hop homes that were created separately don't have.
+        let has_common_commit = run(dir, &["merge-base", "HEAD", &remote_branch])?.status.success();
+        let strategy = if has_common_commit { "resolve" } else { "ort" };
+
+        let merge = run_as_hop(dir, &["merge", "-q", "--no-edit", "--allow-unrelated-histories", "-s", strategy, &remote_branch])?;
+
+        if merge.status.success() {
+            return Ok(vec![])
+        }
+
+        let conflicted_paths: Vec<OsString> =
+            git(dir, &["diff", "--name-only", "-z", "--diff-filter=U"])?
+            .split(|b| *b == 0)
+            .filter(|path| !path.is_empty())
+            .map(|path| OsStr::from_bytes(path).to_os_string())
+            .collect();
+
+        if conflicted_paths.is_empty() {
+            return Err(io_error(&format!("Could not merge the remote: {}", String::from_utf8_lossy(&merge.stderr).trim())))
+        }
+
+        match prefer {
+            None => {
+                let conflicts =
+                    conflicted_paths
+                    .iter()
+                    .map(|path| Ok(GitConflict {
+                        link: Link(path.clone()),
+                        local: staged_target(dir, 2, path)?,
+                        remote: staged_target(dir, 3, path)?,
+                    }))
+                    .collect::<HopEffect<Vec<_>>>()?;
+
+                git(dir, &["merge", "--abort"])?;
+                Ok(conflicts)
+            },
+            Some(preference) => {
+                let (stage, side) = match preference {
+                    SyncPreference::Local => (2, "--ours"),
+                    SyncPreference::Remote => (3, "--theirs"),
+                };
+
+                for path in &conflicted_paths {
+                    let path_args = |args: &[&str]| {
+                        let mut all_args: Vec<OsString> = args.iter().map(OsString::from).collect();
+                        all_args.push(OsString::from("--"));
+                        all_args.push(path.clone());
+                        all_args
+                    };
+
+                    if staged_target(dir, stage, path)?.is_some() {
+                        git(dir, &path_args(&["checkout", side]))?;
+                        git(dir, &path_args(&["add"]))?;
+                    } else {
+                        git(dir, &path_args(&["rm", "-q"]))?;
+                    }
+                }
+
+                git_as_hop(dir, &["commit", "-q", "--no-edit"])?;
+                Ok(vec![])
+            },
+        }
+    }
+
+    fn push(&self, dir: &Path) -> HopEffect<()> {
+        //There is nothing to push until the first link has been committed
+        if run(dir, &["rev-parse", "-q", "--verify", "HEAD"])?.status.success() {
+            let branch = current_branch(dir)?;
+            git(dir, &["push", "-q", "origin", &format!("HEAD:refs/heads/{}", branch)]).map(|_| ())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Deleted links only need to be restored on the machine they were deleted on, and the lock is only for the hops on
+/// it, so the trash and the lock file are kept out of the repository.
+fn exclude_local_state(dir: &Path) -> HopEffect<()> {
+    let exclude_path = dir.join(".git").join("info").join("exclude");
+    let patterns = [format!("/{}/", TRASH_DIR), format!("/{}", LOCK_FILE)];
+
+    let excludes = match fs::read_to_string(&exclude_path) {
+        Ok(excludes) if excludes.is_empty() || excludes.ends_with('\n') => excludes,
+        Ok(excludes) => format!("{}\n", excludes),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(io_error_ex(&format!("Could not read {}", exclude_path.to_string_lossy()), e)),
+    };
+
+    let missing: Vec<&String> = patterns.iter().filter(|pattern| !excludes.lines().any(|line| &line == pattern)).collect();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        let added: String = missing.iter().map(|pattern| format!("{}\n", pattern)).collect();
+
+        exclude_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&exclude_path, format!("{}{}", excludes, added)))
+            .map_err(|e| io_error_ex(&format!("Could not write {}", exclude_path.to_string_lossy()), e))
+    }
+}
+
+fn current_branch(dir: &Path) -> HopEffect<String> {
+    let branch = git(dir, &["symbolic-ref", "--short", "HEAD"])?;
+    Ok(String::from_utf8_lossy(&branch).trim().to_string())
+}
+
+/// The target of the link at `path` in a stage of a conflicted merge: 2 for the hop home and 3 for the remote.
+/// Returns `None` if the link was deleted on that side.
+fn staged_target(dir: &Path, stage: u8, path: &OsStr) -> HopEffect<Option<LinkTarget>> {
+    let mut object = OsString::from(format!(":{}:", stage));
+    object.push(path);
+
+    let output = run(dir, &[OsStr::new("show"), &object])?;
+
+    if output.status.success() {
+        Ok(Some(LinkTarget(PathBuf::from(OsString::from_vec(output.stdout)))))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Runs git in `dir`, returning stdout or an error holding git's message if it fails.
+fn git<S: AsRef<OsStr>>(dir: &Path, args: &[S]) -> HopEffect<Vec<u8>> {
+    check(args, run(dir, args)?)
+}
+
+/// Runs a git command that writes a commit. hop is used as the author if git doesn't know who the user is.
+fn git_as_hop(dir: &Path, args: &[&str]) -> HopEffect<Vec<u8>> {
+    check(args, run_as_hop(dir, args)?)
+}
+
+fn run_as_hop(dir: &Path, args: &[&str]) -> HopEffect<Output> {
+    let has_identity = run(dir, &["config", "user.email"])?.status.success();
+
+    let mut all_args = if has_identity {
+        vec![]
+    } else {
+        vec!["-c", "user.name=hop", "-c", "user.email=hop@localhost"]
+    };
+    all_args.extend(args);
+
+    run(dir, &all_args)
+}
+
+fn run<S: AsRef<OsStr>>(dir: &Path, args: &[S]) -> HopEffect<Output> {
+    Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| io_error_ex("Could not run git. Please check that it is installed.", e))
+}
+
+fn check<S: AsRef<OsStr>>(args: &[S], output: Output) -> HopEffect<Vec<u8>> {
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        let command: Vec<String> = args.iter().map(|arg| arg.as_ref().to_string_lossy().to_string()).collect();
+
+        Err(io_error(&format!(
+            "`git {}` failed: {}",
+            command.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
@@ -0,0 +1,153 @@
+use super::prod_models::Prod;
+use crate::algebra::journal::Journal;
+use crate::config;
+use crate::models::{HopEffect, JournalEntry, Link, LinkChange, LinkTarget, StoredOsString};
+use crate::program::{io_error, io_error_ex};
+
+use chrono::{Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The most entries kept for each hop home. Older entries are dropped and can no longer be undone.
+const JOURNAL_LIMIT: usize = 100;
+
+impl Journal for Prod {
+    fn append_entry(&self, hop_home: &Path, description: &str, changes: &[LinkChange]) -> HopEffect<()> {
+        let path = journal_path()?;
+        let _lock = lock_journal(&path)?;
+        let mut journals = read_journals(&path)?;
+        let entries = journals.entry(journal_key(hop_home)).or_default();
+
+        entries.push(EntryRecord {
+            time: Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+            description: description.to_string(),
+            changes: changes.iter().map(ChangeRecord::from_change).collect(),
+        });
+
+        let excess = entries.len().saturating_sub(JOURNAL_LIMIT);
+        entries.drain(..excess);
+
+        write_journals(&path, &journals)
+    }
+
+    fn read_entries(&self, hop_home: &Path) -> HopEffect<Vec<JournalEntry>> {
+        let mut journals = read_journals(&journal_path()?)?;
+
+        let entries =
+            journals
+            .remove(&journal_key(hop_home))
+            .unwrap_or_default()
+            .into_iter()
+            .map(EntryRecord::into_entry)
+            .collect();
+
+        Ok(entries)
+    }
+
+    fn remove_entries(&self, hop_home: &Path, count: usize) -> HopEffect<()> {
+        let path = journal_path()?;
+        let _lock = lock_journal(&path)?;
+        let mut journals = read_journals(&path)?;
+
+        if let Some(entries) = journals.get_mut(&journal_key(hop_home)) {
+            let remaining = entries.len().saturating_sub(count);
+            entries.truncate(remaining);
+        }
+
+        write_journals(&path, &journals)
+    }
+}
+
+/// An entry in the journal of a hop home. The journal is keyed by the path of the hop home.
+#[derive(Debug, Serialize, Deserialize)]
+struct EntryRecord {
+    time: String,
+    description: String,
+    changes: Vec<ChangeRecord>,
+}
+
+impl EntryRecord {
+    fn into_entry(self) -> JournalEntry {
+        JournalEntry {
+            time: self.time,
+            description: self.description,
+            changes: self.changes.into_iter().map(ChangeRecord::into_change).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChangeRecord {
+    name: StoredOsString,
+    before: Option<StoredOsString>,
+    after: Option<StoredOsString>,
+}
+
+impl ChangeRecord {
+    fn from_change(change: &LinkChange) -> Self {
+        ChangeRecord {
+            name: StoredOsString::new(&change.link.0),
+            before: change.before.as_ref().map(|target| StoredOsString::new(&target.0)),
+            after: change.after.as_ref().map(|target| StoredOsString::new(&target.0)),
+        }
+    }
+
+    fn into_change(self) -> LinkChange {
+        LinkChange {
+            link: Link::new(self.name.into_os_string()),
+            before: self.before.map(|target| LinkTarget::new(target.into_os_string())),
+            after: self.after.map(|target| LinkTarget::new(target.into_os_string())),
+        }
+    }
+}
+
+fn journal_path() -> HopEffect<PathBuf> {
+    config::journal_path(env::var_os(config::XDG_STATE_HOME_ENV))
+}
+
+/// The journal holds the entries of every hop home, while the lock taken by a command is only for its own hop home.
+/// Hops changing different hop homes at once each take this lock while they change the journal, so that neither
+/// writes over the entries of the other. The lock is released when the returned file is dropped.
+fn lock_journal(path: &Path) -> HopEffect<File> {
+    let lock_path = path.with_extension("lock");
+
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| OpenOptions::new().write(true).create(true).truncate(false).open(&lock_path))
+        .and_then(|file| file.lock().map(|_| file))
+        .map_err(|e| io_error_ex(&format!("Could not lock journal: {}", lock_path.to_string_lossy()), e))
+}
+
+fn journal_key(hop_home: &Path) -> String {
+    hop_home.to_string_lossy().to_string()
+}
+
+fn read_journals(path: &Path) -> HopEffect<BTreeMap<String, Vec<EntryRecord>>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            serde_json::from_str(&contents)
+                .map_err(|e| io_error(&format!("Could not read journal: {}\n{}", path.to_string_lossy(), e)))
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(io_error_ex(&format!("Could not read journal: {}", path.to_string_lossy()), e)),
+    }
+}
+
+/// The journal is written to a temporary file that then replaces it, so that it is never read half written.
+fn write_journals(path: &Path, journals: &BTreeMap<String, Vec<EntryRecord>>) -> HopEffect<()> {
+    let temp_path = path.with_extension(format!("json.{}", std::process::id()));
+
+    serde_json::to_string_pretty(journals)
+        .map_err(io::Error::other)
+        .and_then(|json| {
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&temp_path, format!("{}\n", json)))
+                .and_then(|_| fs::rename(&temp_path, path))
+        })
+        .map_err(|e| io_error_ex(&format!("Could not save journal: {}", path.to_string_lossy()), e))
+}
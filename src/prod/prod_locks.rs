@@ -0,0 +1,79 @@
+use super::prod_models::Prod;
+use crate::algebra::locks::Locks;
+use crate::models::HopEffect;
+use crate::program::{io_error, io_error_ex};
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Seek, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait between attempts to take a lock that is held by another hop.
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The lock file in each hop home. It is kept out of git along with the trash.
+pub const LOCK_FILE: &str = ".lock";
+
+impl Locks for Prod {
+    type Lock = File;
+
+    fn lock_home(&self, hop_home: &Path, timeout: Duration) -> HopEffect<File> {
+        let path = hop_home.join(LOCK_FILE);
+        let started = Instant::now();
+
+        let mut file = loop {
+            let file = open_lock_file(&path)?;
+
+            loop {
+                match file.try_lock() {
+                    Ok(()) => break,
+                    Err(TryLockError::WouldBlock) if started.elapsed() < timeout => thread::sleep(RETRY_INTERVAL),
+                    Err(TryLockError::WouldBlock) => {
+                        let holder = fs::read_to_string(&path).ok().map(|pid| pid.trim().to_string()).filter(|pid| !pid.is_empty());
+
+                        return Err(io_error(&match holder {
+                            Some(pid) => format!("The hop home is locked by pid {}. Try again once it has finished.", pid),
+                            None => "The hop home is locked by another hop. Try again once it has finished.".to_string(),
+                        }))
+                    },
+                    Err(TryLockError::Error(e)) => {
+                        return Err(io_error_ex(&format!("Could not lock the hop home: {}", path.to_string_lossy()), e))
+                    },
+                }
+            }
+
+            //The lock file is removed when `hop migrate-home` moves the hop home, so a lock taken on it is of no use
+            if is_same_file(&file, &path) {
+                break file
+            }
+        };
+
+        //The pid is only there to be shown to other hops. The lock is held by the open file.
+        file.set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| writeln!(file, "{}", process::id()))
+            .map_err(|e| io_error_ex(&format!("Could not write lock file: {}", path.to_string_lossy()), e))?;
+
+        Ok(file)
+    }
+}
+
+fn open_lock_file(path: &Path) -> HopEffect<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| io_error_ex(&format!("Could not open lock file: {}", path.to_string_lossy()), e))
+}
+
+fn is_same_file(file: &File, path: &Path) -> bool {
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(opened), Ok(current)) => opened.dev() == current.dev() && opened.ino() == current.ino(),
+        _ => false,
+    }
+}
@@ -0,0 +1,135 @@
+use super::prod_models::Prod;
+use crate::algebra::snapshots::Snapshots;
+use crate::config;
+use crate::models::{HopEffect, LinkPair, Snapshot, StoredOsString};
+use crate::program::{io_error, io_error_ex};
+
+use chrono::{Local, SecondsFormat};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Snapshots are gzipped JSON.
+const SNAPSHOT_EXTENSION: &str = ".json.gz";
+
+impl Snapshots for Prod {
+    fn write_snapshot(&self, hop_home: &Path, label: Option<&str>, links: &[LinkPair]) -> HopEffect<Snapshot> {
+        let dir = snapshots_dir()?;
+        let now = Local::now();
+
+        //Snapshots taken in the same second get a counter
+        let timestamp = now.format("%Y%m%d-%H%M%S").to_string();
+        let id =
+            (1..)
+            .map(|count| match count {
+                1 => timestamp.clone(),
+                _ => format!("{}-{}", timestamp, count),
+            })
+            .find(|id| !snapshot_path(&dir, id).exists())
+            .expect("expected a free snapshot id");
+
+        let record = SnapshotRecord {
+            id: id.clone(),
+            hop_home: hop_home.to_string_lossy().to_string(),
+            label: label.map(str::to_string),
+            created: now.to_rfc3339_opts(SecondsFormat::Secs, false),
+            links: links.iter().map(|lp| LinkPairRecord { name: StoredOsString::new(&lp.link.0), target: StoredOsString::new(&lp.target.0) }).collect(),
+        };
+
+        let path = snapshot_path(&dir, &id);
+
+        fs::create_dir_all(&dir)
+            .and_then(|_| write_record(&path, &record))
+            .map_err(|e| io_error_ex(&format!("Could not save snapshot: {}", path.to_string_lossy()), e))?;
+
+        Ok(record.into_snapshot())
+    }
+
+    fn list_snapshots(&self, hop_home: &Path) -> HopEffect<Vec<Snapshot>> {
+        let dir = snapshots_dir()?;
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(io_error_ex(&format!("Could not read snapshots: {}", dir.to_string_lossy()), e)),
+        };
+
+        let hop_home_key = hop_home.to_string_lossy().to_string();
+        let mut records: Vec<SnapshotRecord> = vec![];
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.to_string_lossy().ends_with(SNAPSHOT_EXTENSION) {
+                let record = read_record(&path)?;
+
+                if record.hop_home == hop_home_key {
+                    records.push(record)
+                }
+            }
+        }
+
+        records.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| b.id.cmp(&a.id)));
+
+        Ok(records.into_iter().map(SnapshotRecord::into_snapshot).collect())
+    }
+
+    fn remove_snapshot(&self, _hop_home: &Path, id: &str) -> HopEffect<()> {
+        let path = snapshot_path(&snapshots_dir()?, id);
+
+        fs::remove_file(&path)
+            .map_err(|e| io_error_ex(&format!("Could not remove snapshot: {}", path.to_string_lossy()), e))
+    }
+}
+
+/// A snapshot as it is saved. Snapshots of every hop home are kept together, so each holds the path of its hop home.
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotRecord {
+    id: String,
+    hop_home: String,
+    label: Option<String>,
+    created: String,
+    links: Vec<LinkPairRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LinkPairRecord {
+    name: StoredOsString,
+    target: StoredOsString,
+}
+
+impl SnapshotRecord {
+    fn into_snapshot(self) -> Snapshot {
+        Snapshot {
+            id: self.id,
+            label: self.label,
+            created: self.created,
+            links: self.links.into_iter().map(|record| LinkPair::new(record.name.into_os_string(), record.target.into_os_string())).collect(),
+        }
+    }
+}
+
+fn snapshots_dir() -> HopEffect<PathBuf> {
+    config::snapshots_dir(env::var_os(config::XDG_STATE_HOME_ENV))
+}
+
+fn snapshot_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}{}", id, SNAPSHOT_EXTENSION))
+}
+
+fn write_record(path: &Path, record: &SnapshotRecord) -> io::Result<()> {
+    let json = serde_json::to_vec(record).map_err(io::Error::other)?;
+    let mut encoder = GzEncoder::new(File::create(path)?, Compression::default());
+
+    encoder.write_all(&json)?;
+    encoder.finish().map(|_| ())
+}
+
+fn read_record(path: &Path) -> HopEffect<SnapshotRecord> {
+    File::open(path)
+        .and_then(|file| serde_json::from_reader(GzDecoder::new(file)).map_err(io::Error::other))
+        .map_err(|e| io_error(&format!("Could not read snapshot: {}\n{}", path.to_string_lossy(), e)))
+}
@@ -1,6 +1,6 @@
 use super::prod_models::Prod;
 use crate::algebra::std_io::StdIO;
-use std::io;
+use std::io::{self, IsTerminal};
 
 impl StdIO for Prod {
     fn println(&self, message: &str) {
@@ -9,11 +9,15 @@ impl StdIO for Prod {
 
     fn readln(&self) -> io::Result<String> {
         let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer)?;
-        let line = buffer
-            .lines()
-            .next()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Could not read stdin line"))?;
-        Ok(line.to_owned())
+
+        if io::stdin().read_line(&mut buffer)? > 0 {
+            Ok(buffer.lines().next().unwrap_or_default().to_owned())
+        } else if io::stdin().is_terminal() {
+            Err(io::Error::other("No answer was given"))
+        } else {
+            Err(io::Error::other(
+                "hop needs an answer but stdin is not a terminal. Pass --yes to answer yes or --no-input to take the default answer."
+            ))
+        }
     }
 }
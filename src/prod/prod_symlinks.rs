@@ -1,4 +1,5 @@
 use super::prod_models::Prod;
+use crate::config;
 use crate::models::{HopEffect, Link, LinkPair, LinkTarget};
 use crate::program::{io_error, io_error_ex};
 
@@ -34,7 +35,7 @@ fn get_links(path: &Path) -> HopEffect<Vec<LinkPair>> {
     match fs::read_dir(path) {
         Ok(dir_it) => {
             let symlinks = dir_it
-                .filter(|res| res.as_ref().map_or_else(|_| false, |d| is_symlink(d)))
+                .filter(|res| res.as_ref().map_or_else(|_| false, is_symlink))
                 .map(|res| res.and_then(|entry| create_link_pair(&entry)))
                 .collect::<Result<Vec<_>, io::Error>>()?; //sequence
             Ok(symlinks)
@@ -52,14 +53,15 @@ fn is_symlink(dir_entry: &DirEntry) -> bool {
 
 fn create_link_pair(dir_entry: &DirEntry) -> HopEffect<LinkPair> {
     let link_path = &dir_entry.path();
-    //Choose to display a lossy string
-    let link = &dir_entry.file_name().to_string_lossy().to_string();
+    //Keep the raw name and target. Lossy conversions are only done when displaying them.
+    let link = Link(dir_entry.file_name());
 
-    let target_res = fs::read_link(link_path);
+    //Targets written relative to the home directory are stored as `~/path`
+    let target_res = fs::read_link(link_path).and_then(config::expand_home);
     match target_res {
         Ok(target) => Ok(LinkPair {
-            link: Link(link.to_string()),
-            target: LinkTarget(target.to_string_lossy().to_string()),
+            link,
+            target: LinkTarget(target),
         }),
         Err(e) => Err(io_error(&format!(
             "Could not read link `{}` because: {}",
@@ -0,0 +1,151 @@
+use super::prod_models::Prod;
+use crate::algebra::trash::Trash;
+use crate::config;
+use crate::models::{HopEffect, Link, LinkPair, LinkTarget, StoredOsString, TrashedLink};
+use crate::program::{io_error, io_error_ex};
+
+use chrono::{DateTime, Duration, Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The directory in the hop home that deleted links are moved to. It is not a symlink, so it is never read as a link.
+pub const TRASH_DIR: &str = ".trash";
+
+/// Holds the name and deletion time of each link in the trash, which are kept under their id.
+const TRASH_INDEX: &str = "index.json";
+
+impl Trash for Prod {
+    fn trash_link(&self, hop_home: &Path, link_pair: &LinkPair) -> HopEffect<()> {
+        let trash_dir = hop_home.join(TRASH_DIR);
+        let mut records = read_index(&trash_dir)?;
+        let now = Local::now();
+
+        //The id is only made of the time and a counter, as names need not be valid UTF-8
+        let timestamp = now.format("%Y%m%d%H%M%S").to_string();
+        let id =
+            (1..)
+            .map(|count| format!("{}-{}", timestamp, count))
+            .find(|id| !records.iter().any(|record| &record.id == id) && fs::symlink_metadata(trash_dir.join(id)).is_err())
+            .expect("expected a free trash id");
+
+        fs::create_dir_all(&trash_dir)
+            .and_then(|_| fs::rename(hop_home.join(&link_pair.link), trash_dir.join(&id)))
+            .map_err(|e| io_error_ex(&format!("Could not move `{}` to the trash", link_pair.link), e))?;
+
+        records.push(TrashRecord {
+            id,
+            name: StoredOsString::new(&link_pair.link.0),
+            deleted: now.to_rfc3339_opts(SecondsFormat::Secs, false),
+        });
+
+        write_index(&trash_dir, &records)
+    }
+
+    fn trashed_links(&self, hop_home: &Path) -> HopEffect<Vec<TrashedLink>> {
+        let trash_dir = hop_home.join(TRASH_DIR);
+
+        let mut trashed_links =
+            read_index(&trash_dir)?
+            .into_iter()
+            .filter_map(|record| {
+                //Links removed from the trash by hand are skipped
+                let target = fs::read_link(trash_dir.join(&record.id)).and_then(config::expand_home).ok()?;
+
+                Some(TrashedLink {
+                    id: record.id,
+                    link_pair: LinkPair { link: Link::new(record.name.into_os_string()), target: LinkTarget(target) },
+                    deleted: record.deleted,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        trashed_links.reverse();
+        Ok(trashed_links)
+    }
+
+    fn restore_link(&self, hop_home: &Path, trashed_link: &TrashedLink) -> HopEffect<()> {
+        let trash_dir = hop_home.join(TRASH_DIR);
+        let mut records = read_index(&trash_dir)?;
+
+        //A rename would replace a link with the same name, so the link is hard linked back and then removed
+        let trashed_path = trash_dir.join(&trashed_link.id);
+        fs::hard_link(&trashed_path, hop_home.join(&trashed_link.link_pair.link))
+            .and_then(|_| fs::remove_file(&trashed_path))
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => e,
+                _ => io_error_ex(&format!("Could not restore `{}` from the trash", trashed_link.link_pair.link), e),
+            })?;
+
+        records.retain(|record| record.id != trashed_link.id);
+        write_index(&trash_dir, &records)
+    }
+
+    fn empty_trash(&self, hop_home: &Path, max_days: Option<u64>) -> HopEffect<Vec<TrashedLink>> {
+        let trash_dir = hop_home.join(TRASH_DIR);
+
+        if !trash_dir.exists() {
+            return Ok(vec![])
+        }
+
+        let expired = |trashed_link: &TrashedLink| match max_days {
+            Some(days) => {
+                DateTime::parse_from_rfc3339(&trashed_link.deleted)
+                    .map(|deleted| Local::now().signed_duration_since(deleted) > Duration::days(days as i64))
+                    .unwrap_or(false)
+            },
+            None => true,
+        };
+
+        let removed: Vec<TrashedLink> = self.trashed_links(hop_home)?.into_iter().filter(expired).collect();
+
+        for trashed_link in &removed {
+            fs::remove_file(trash_dir.join(&trashed_link.id))
+                .map_err(|e| io_error_ex(&format!("Could not remove `{}` from the trash", trashed_link.link_pair.link), e))?;
+        }
+
+        //Drop the records of removed links along with any whose link is gone
+        let records =
+            read_index(&trash_dir)?
+            .into_iter()
+            .filter(|record| fs::symlink_metadata(trash_dir.join(&record.id)).is_ok())
+            .collect::<Vec<_>>();
+
+        write_index(&trash_dir, &records)?;
+        Ok(removed)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TrashRecord {
+    id: String,
+    name: StoredOsString,
+    deleted: String,
+}
+
+fn index_path(trash_dir: &Path) -> PathBuf {
+    trash_dir.join(TRASH_INDEX)
+}
+
+fn read_index(trash_dir: &Path) -> HopEffect<Vec<TrashRecord>> {
+    let path = index_path(trash_dir);
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => {
+            serde_json::from_str(&contents)
+                .map_err(|e| io_error(&format!("Could not read trash: {}\n{}", path.to_string_lossy(), e)))
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(io_error_ex(&format!("Could not read trash: {}", path.to_string_lossy()), e)),
+    }
+}
+
+fn write_index(trash_dir: &Path, records: &[TrashRecord]) -> HopEffect<()> {
+    let path = index_path(trash_dir);
+
+    serde_json::to_string_pretty(records)
+        .map_err(io::Error::other)
+        .and_then(|json| fs::write(&path, format!("{}\n", json)))
+        .map_err(|e| io_error_ex(&format!("Could not save trash: {}", path.to_string_lossy()), e))
+}
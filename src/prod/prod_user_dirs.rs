@@ -1,37 +1,164 @@
+use super::prod_locks::LOCK_FILE;
 use super::prod_models::Prod;
 use crate::algebra::user_dirs::UserDirs;
+use crate::config;
 use crate::models::{HomeType, HopEffect};
-use crate::program::{io_error, io_error_ex_nested};
+use crate::program::{io_error, io_error_ex, io_error_ex_nested};
 use dirs::home_dir;
 
-use std::{fs, io};
-use std::path::PathBuf;
+use std::{env, fs, io};
+use std::ffi::OsString;
+use std::os::unix::fs as nixfs;
+use std::path::{Path, PathBuf};
+
+const LEGACY_HOP_HOME: &str = ".hop";
+const XDG_HOP_HOME: &str = "hop";
+const XDG_PROFILES_HOME: &str = "hop-profiles";
 
 impl UserDirs for Prod {
     fn get_hop_home(&self, home_type: &HomeType) -> HopEffect<PathBuf> {
         let hop_home = match home_type {
-            HomeType::Relative(path) => get_home()?.join(path),
-            HomeType::Absolute(absolute_path) => PathBuf::from(absolute_path),
+            HomeType::Xdg => match get_legacy_home()? {
+                Some(legacy_home) => legacy_home,
+                None => get_xdg_data_home()?.join(XDG_HOP_HOME),
+            },
+            HomeType::Profile(profile) => {
+                config::validate_profile_name(profile).map_err(|e| io_error(&e))?;
+                get_xdg_data_home()?.join(XDG_PROFILES_HOME).join(profile)
+            },
+            HomeType::Absolute(absolute_path) => absolute_path.clone(),
         };
 
-        match fs::metadata(&hop_home) {
-            Ok(dir) =>
-                if dir.is_dir() {
-                    Ok(hop_home)
-                } else {
-                    Err(io_error(&format!("{} is not a directory", &hop_home.to_string_lossy())))
-                },
-            Err(e1) => {
-                //hop_home is not a directory, try and create it
-                match fs::create_dir_all(&hop_home) {
-                    Ok(_) => Ok(hop_home),
-                    Err(e2) => Err(io_error_ex_nested(&format!("Could not create dir: {}", &hop_home.to_string_lossy()), e2, e1)),
-                }
+        create_hop_home(hop_home)
+    }
+
+    fn home_dir(&self) -> HopEffect<PathBuf> {
+        get_home()
+    }
+
+    fn data_home(&self) -> HopEffect<PathBuf> {
+        get_xdg_data_home()
+    }
+
+    fn list_profiles(&self) -> HopEffect<Vec<String>> {
+        let profiles_home = get_xdg_data_home()?.join(XDG_PROFILES_HOME);
+
+        match fs::read_dir(&profiles_home) {
+            Ok(dir_it) => {
+                let entries = dir_it.collect::<Result<Vec<_>, io::Error>>()?;
+                let profiles =
+                    entries
+                    .iter()
+                    .filter(|entry| entry.file_type().map_or_else(|_| false, |t| t.is_dir()))
+                    .filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+                    .collect();
+
+                Ok(profiles)
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(io_error_ex(&format!("Could not read directory: {}", profiles_home.to_string_lossy()), e)),
+        }
+    }
+
+    fn legacy_hop_home(&self) -> HopEffect<Option<PathBuf>> {
+        get_legacy_home()
+    }
+
+    fn xdg_hop_home(&self) -> HopEffect<PathBuf> {
+        create_hop_home(get_xdg_data_home()?.join(XDG_HOP_HOME))
+    }
+
+    fn move_hop_home(&self, from: &Path, to: &Path) -> HopEffect<()> {
+        move_entries(from, to)?;
+        remove_lock_file(from)?;
+        fs::remove_dir(from)
+            .map_err(|e| io_error_ex(&format!("Could not remove directory: {}", from.to_string_lossy()), e))?;
+        nixfs::symlink(to, from)
+    }
+}
+
+/// Moves every entry of `from` into `to`, whether it is a link or not, such as the `.trash` or a `.git` directory.
+/// Nothing is moved if any entry already exists in `to`, and entries already moved are moved back if one fails. The
+/// lock file stays behind, as `to` has its own.
+fn move_entries(from: &Path, to: &Path) -> HopEffect<()> {
+    let read_entries = |dir: &Path| {
+        fs::read_dir(dir)
+            .and_then(|dir_it| dir_it.map(|res| res.map(|entry| entry.file_name())).collect::<Result<Vec<_>, io::Error>>())
+            .map_err(|e| io_error_ex(&format!("Could not read directory: {}", dir.to_string_lossy()), e))
+    };
+
+    let entries: Vec<OsString> = read_entries(from)?.into_iter().filter(|name| name != LOCK_FILE).collect();
+
+    if let Some(existing) = entries.iter().find(|name| fs::symlink_metadata(to.join(name)).is_ok()) {
+        return Err(io_error(&format!(
+            "`{}` already exists in {}. Aborting migration.",
+            existing.to_string_lossy(),
+            to.to_string_lossy()
+        )))
+    }
+
+    let mut moved: Vec<&OsString> = vec![];
+    for name in &entries {
+        if let Err(e) = fs::rename(from.join(name), to.join(name)) {
+            let message = format!("Could not move `{}` to {}", name.to_string_lossy(), to.to_string_lossy());
+            return match moved.iter().try_for_each(|moved_name| fs::rename(to.join(moved_name), from.join(moved_name))) {
+                Ok(()) => Err(io_error_ex(&message, e)),
+                Err(e2) => Err(io_error_ex_nested(&format!("{} and the entries already moved could not be moved back", message), e, e2)),
             }
         }
+        moved.push(name);
+    }
+
+    Ok(())
+}
+
+fn remove_lock_file(hop_home: &Path) -> HopEffect<()> {
+    let lock_path = hop_home.join(LOCK_FILE);
+
+    match fs::remove_file(&lock_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error_ex(&format!("Could not remove {}", lock_path.to_string_lossy()), e)),
+        _ => Ok(()),
+    }
+}
+
+fn create_hop_home(hop_home: PathBuf) -> HopEffect<PathBuf> {
+    match fs::metadata(&hop_home) {
+        Ok(dir) =>
+            if dir.is_dir() {
+                Ok(hop_home)
+            } else {
+                Err(io_error(&format!("{} is not a directory", &hop_home.to_string_lossy())))
+            },
+        Err(e1) => {
+            //hop_home is not a directory, try and create it
+            match fs::create_dir_all(&hop_home) {
+                Ok(_) => Ok(hop_home),
+                Err(e2) => Err(io_error_ex_nested(&format!("Could not create dir: {}", &hop_home.to_string_lossy()), e2, e1)),
+            }
+        }
+    }
+}
+
+/// Returns `~/.hop` only if it is a real directory. Once migrated it is a symlink to the XDG hop home, which we
+/// then ignore.
+fn get_legacy_home() -> HopEffect<Option<PathBuf>> {
+    let legacy_home = get_home()?.join(LEGACY_HOP_HOME);
+    let is_real_dir =
+        fs::symlink_metadata(&legacy_home)
+        .map_or_else(|_| false, |meta| meta.file_type().is_dir());
+
+    Ok(Some(legacy_home).filter(|_| is_real_dir))
+}
+
+/// `$XDG_DATA_HOME` if it is set to an absolute path, otherwise `~/.local/share` as per the XDG base directory
+/// specification.
+fn get_xdg_data_home() -> HopEffect<PathBuf> {
+    match env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+        Some(data_home) if data_home.is_absolute() => Ok(data_home),
+        _ => Ok(get_home()?.join(".local").join("share")),
     }
 }
 
 fn get_home() -> HopEffect<PathBuf> {
-    home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Could not get home directory"))
+    home_dir().ok_or_else(|| io::Error::other("Could not get home directory"))
 }
@@ -1,99 +1,1004 @@
-use crate::algebra::hop::DeleteStatus;
+use crate::algebra::hop::{DeleteOutcome, DeleteStatus};
+use crate::config::{self, ColourMode, Config, ConfigFile, HopHome, OutputFormat};
+use crate::algebra::user_dirs::UserDirs;
+use crate::formats::{self, FrecencyTool, ImportSource, LinkFormat, LinkRecord};
+use crate::models::{BatchOutcome, GitSyncStatus, HomeType, ImportAction, ImportOptions, LinkChange, LinkTarget, ScanOptions, SyncChange, SyncPreference, LOCAL_LAYER};
 
 use super::*;
 
-use ansi_term::Color::{Red, Yellow};
-use std::io;
+use ansi_term::Colour::{self, Red, Yellow};
+use serde_json::json;
+use std::io::{self, IsTerminal, Write};
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process;
 
 pub fn handle_list(hop_program: &hop::HopProgram<Prod>) {
     let action = hop_program.list_links();
+    let colours = Colours::new(&hop_program.config);
 
-    fn handler(lp:&LinkPair) {
-        println!("{}", lp.link)
+    match hop_program.config.output {
+        OutputFormat::Text => handle_links(&colours, action, |lp| println!("{}", lp.link)),
+        OutputFormat::Json => handle_json_links(&colours, action, |lp| json!(lp.link.to_string())),
     }
-
-    handle_links(action, handler)
 }
 
-pub fn handle_table(hop_program: &hop::HopProgram<Prod>) {
+pub fn handle_table(hop_program: &hop::HopProgram<Prod>, profile: Option<&str>) {
     let action = hop_program.tabulate_links();
+    let colours = Colours::new(&hop_program.config);
 
-    fn handler(lp:&LinkPair) {
-        println!("{} {} {}", lp.link, Yellow.paint("->"), lp.target)
-    }
+    match hop_program.config.output {
+        OutputFormat::Text => {
+            if let Some(profile) = profile {
+                println!("{} {}", colours.yellow("Profile:"), profile)
+            }
+
+            let layered = hop_program.is_layered();
+
+            handle_links(&colours, action, |llp| {
+                let lp = &llp.link_pair;
 
-    handle_links(action, handler)
+                if layered {
+                    println!("{} {} {} [{}]", lp.link, colours.yellow("->"), lp.target, llp.layer)
+                } else {
+                    println!("{} {} {}", lp.link, colours.yellow("->"), lp.target)
+                }
+            })
+        },
+        OutputFormat::Json => handle_json_links(&colours, action, |llp| {
+            json!({ "name": llp.link_pair.link.to_string(), "target": llp.link_pair.target.to_string(), "layer": llp.layer })
+        }),
+    }
 }
 
-fn handle_links(action: io::Result<Vec<LinkPair>>, handler: fn(&LinkPair)) {
+fn handle_links<A, F>(colours: &Colours, action: io::Result<Vec<A>>, handler: F)
+where
+    F: Fn(&A),
+{
     match action {
         Ok(entries) => {
             if entries.is_empty() {
-                println!("No entries to list.\nPlease create some entries with {}\nPlease use {} for more information", Yellow.paint("hop -m <link> <path>"), Yellow.paint("hop -h"))
+                println!("No entries to list.\nPlease create some entries with {}\nPlease use {} for more information", colours.yellow("hop -m <link> <path>"), colours.yellow("hop -h"))
             } else {
                 entries.iter().for_each(handler)
             }
 
         },
-        Err(e) => handle_error(e, "Could not retrieve list of links"),
+        Err(e) => handle_error(colours, e, "Could not retrieve list of links"),
     }
 }
 
-pub fn handle_jump(hop_program: &hop::HopProgram<Prod>, jump_target: &str) {
-    let action = hop_program.jump_target(Link::new(jump_target));
+/// Prints the links as a JSON array. Names and targets that are not valid UTF-8 are converted lossily.
+fn handle_json_links<A, F>(colours: &Colours, action: io::Result<Vec<A>>, to_json: F)
+where
+    F: Fn(&A) -> serde_json::Value,
+{
     match action {
-        Ok(link) => println!("{}", link),
-        Err(e) => handle_error(
-            e,
-            &format!("Could not retrieve jump target: {}", jump_target),
-        ),
+        Ok(entries) => {
+            let values: Vec<serde_json::Value> = entries.iter().map(to_json).collect();
+            println!("{}", serde_json::Value::Array(values))
+        },
+        Err(e) => handle_error(colours, e, "Could not retrieve list of links"),
     }
 }
 
-pub fn handle_mark(hop_program: &hop::HopProgram<Prod>, link_pair: &LinkPair) {
-    let action = hop_program.mark_dir(link_pair);
+pub fn handle_jump(hop_program: &hop::HopProgram<Prod>, jump_target: &Link) {
+    let action = hop_program.jump_target(jump_target.clone());
+    let colours = Colours::new(&hop_program.config);
+
+    match action.and_then(|target| write_raw_line(target.as_os_str())) {
+        Ok(()) => (),
+        Err(e) => {
+            handle_error(
+                &colours,
+                e,
+                &format!("Could not retrieve jump target: {}", jump_target),
+            );
+            //Lets scripts check whether a link exists
+            process::exit(1)
+        },
+    }
+}
+
+/// Either the name or the target can be left out, in which case they are worked out by `infer_link_pair`.
+pub fn handle_mark(hop_program: &hop::HopProgram<Prod>, link: Option<Link>, target: Option<LinkTarget>, git_root: bool, if_missing: bool) {
+    let colours = Colours::new(&hop_program.config);
+
+    let link_pair = match hop_program.infer_link_pair(link, target, git_root) {
+        Ok(link_pair) => link_pair,
+        Err(e) => return handle_error(&colours, e, "Could not mark directory"),
+    };
+
+    let action =
+        if if_missing {
+            hop_program.mark_dir_if_missing(&link_pair)
+        } else {
+            hop_program.mark_dir(&link_pair).map(Some)
+        };
+
     match action {
-        Ok(target) => println!(
+        Ok(Some(target)) => println!(
             "Created link from {} {} {}",
             link_pair.link,
-            Yellow.paint("->"),
-            target
+            colours.yellow("->"),
+            target.to_string_lossy()
         ),
-        Err(e) => handle_error(e, &format!("Could not mark directory: {}", link_pair)),
+        Ok(None) => println!("A link named `{}` already exists. Leaving it as it is.", link_pair.link),
+        Err(e) => handle_error(&colours, e, &format!("Could not mark directory: {}", link_pair)),
     }
 }
 
-pub fn handle_delete(hop_program: &hop::HopProgram<Prod>, link: &Link) {
-    let action = hop_program.delete_link(link);
+pub fn handle_delete(hop_program: &hop::HopProgram<Prod>, links: &[Link]) {
+    let action = hop_program.delete_links(links);
+    let colours = Colours::new(&hop_program.config);
+    let names: Vec<String> = links.iter().map(|link| link.to_string()).collect();
+
+    match action {
+        Ok(DeleteStatus::DeleteAborted) => println!("Aborting delete of {}", names.join(", ")),
+        Ok(DeleteStatus::DeleteCompleted(outcomes)) => {
+            let mut failed = false;
+
+            for outcome in outcomes {
+                match outcome {
+                    DeleteOutcome::Deleted(pair) => {
+                        println!("Removed link {} {} {}", pair.link, colours.yellow("->"), pair.target)
+                    },
+                    DeleteOutcome::Failed(pair, error) => {
+                        handle_error(&colours, io_error(&error), &format!("Could not delete link: {}", pair.link));
+                        failed = true
+                    },
+                }
+            }
+
+            if failed {
+                process::exit(1)
+            }
+        },
+        Err(e) => {
+            handle_error(&colours, e, &format!("Could not delete link: {}", names.join(", ")));
+            process::exit(1)
+        },
+    }
+}
+
+/// Writes the raw bytes of `value` followed by a newline to stdout. Used for output that is consumed by other
+/// programs (such as `cd`), where a lossy conversion would produce a path that does not exist.
+fn write_raw_line(value: &OsStr) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    handle.write_all(value.as_bytes())?;
+    handle.write_all(b"\n")?;
+    handle.flush()
+}
+
+pub fn handle_config_show(hop_program: &hop::HopProgram<Prod>, hop_home: &HopHome, config_path: Option<&Path>) {
+    let action = hop_program.hop_home();
+    let colours = Colours::new(&hop_program.config);
+
     match action {
-        Ok(DeleteStatus::DeleteAborted) => println!("Aborting delete of {}", link),
-        Ok(DeleteStatus::DeleteSucceeded(pair)) => {
+        Ok(hop_home_dir) => {
+            println!("hop home: {}", hop_home_dir.to_string_lossy());
+            println!("source: {}", hop_home.source);
+
+            if let Some(profile) = &hop_home.profile {
+                println!("profile: {}", profile)
+            }
+
+            hop_program
+                .hop_homes
+                .iter()
+                .filter(|layer| layer.name == LOCAL_LAYER)
+                .for_each(|layer| {
+                    if let HomeType::Absolute(path) = &layer.home_type {
+                        println!("local hop home: {}", path.to_string_lossy())
+                    }
+                });
+
+            hop_program
+                .config
+                .shared
+                .iter()
+                .for_each(|shared| println!("shared home: {} ({})", shared.home.to_string_lossy(), shared.name));
+
+            match config_path {
+                Some(path) if path.exists() => println!("config file: {}", path.to_string_lossy()),
+                Some(path) => println!("config file: {} (not found)", path.to_string_lossy()),
+                None => println!("config file: (unknown)"),
+            }
+        },
+        Err(e) => handle_error(&colours, e, "Could not retrieve hop home"),
+    }
+}
+
+/// Validates the config file at `config_path`, exiting with a non-zero status if it is invalid.
+pub fn handle_config_check(hop_program: &hop::HopProgram<Prod>, config_path: Option<&Path>) {
+    let colours = Colours::new(&hop_program.config);
+
+    let config_path = match config_path {
+        Some(path) => path,
+        None => {
+            handle_error(&colours, io_error("Could not get home directory"), "Could not find config file");
+            process::exit(1)
+        },
+    };
+
+    match config::read_config_file(config_path) {
+        Ok(Some(config_file)) => {
+            config_file
+                .warnings
+                .iter()
+                .for_each(|warning| handle_warning(&colours, warning));
+
             println!(
-                "Removed link {} {} {}",
-                link,
-                Yellow.paint("->"),
-                pair.target
+                "{} is valid with {}",
+                config_path.to_string_lossy(),
+                pluralise(config_file.warnings.len(), "warning")
             )
+        },
+        Ok(None) => println!("No config file found at {}. Using defaults.", config_path.to_string_lossy()),
+        Err(e) => {
+            handle_error(&colours, e, "Config file is invalid");
+            process::exit(1)
+        },
+    }
+}
+
+pub fn handle_profile_use(hop_program: &hop::HopProgram<Prod>, active_profile_path: Option<&Path>, profile: &str) {
+    let colours = Colours::new(&hop_program.config);
+    let active_profile = Some(profile).filter(|&p| p != DEFAULT_PROFILE);
+
+    let action =
+        active_profile_path
+        .ok_or_else(|| io_error("Could not get home directory"))
+        .and_then(|path| config::write_active_profile(path, active_profile));
+
+    match action {
+        Ok(()) => match active_profile {
+            Some(profile) => println!("Using profile {}", colours.yellow(profile)),
+            None => println!("Using the default hop home"),
+        },
+        Err(e) => handle_error(&colours, e, &format!("Could not use profile: {}", profile)),
+    }
+}
+
+pub fn handle_profile_list(hop_program: &hop::HopProgram<Prod>, current_profile: Option<&str>) {
+    let action = hop_program.list_profiles();
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(profiles) => {
+            if profiles.is_empty() {
+                println!("No profiles to list.\nPlease create a profile with {}", colours.yellow("hop profile use <profile>"))
+            } else {
+                profiles.iter().for_each(|profile| {
+                    if Some(profile.as_str()) == current_profile {
+                        println!("* {}", colours.yellow(profile))
+                    } else {
+                        println!("  {}", profile)
+                    }
+                })
+            }
+        },
+        Err(e) => handle_error(&colours, e, "Could not retrieve list of profiles"),
+    }
+}
+
+/// Reads the profile chosen with `hop profile use` at startup. Problems are reported and the default hop home is
+/// used instead.
+pub fn load_active_profile(active_profile_path: &Path, hop_config: &Config) -> Option<String> {
+    let colours = Colours::new(hop_config);
+
+    match config::read_active_profile(active_profile_path) {
+        Ok(profile) => profile,
+        Err(e) => {
+            handle_warning(&colours, &format!("Ignoring active profile. {}", e));
+            None
+        },
+    }
+}
+
+/// Reads the config file at startup. Problems with the config file never stop hop from running. They are reported
+/// when `report` is set and the defaults are used instead.
+pub fn load_config_file(config_path: &Path, report: bool) -> Option<ConfigFile> {
+    match config::read_config_file(config_path) {
+        Ok(Some(config_file)) => {
+            if report {
+                let colours = Colours::new(&config_file.config);
+
+                config_file
+                    .warnings
+                    .iter()
+                    .for_each(|warning| handle_warning(&colours, &format!("{} ({})", warning, config_path.to_string_lossy())))
+            }
+
+            Some(config_file)
+        },
+        Ok(None) => None,
+        Err(e) => {
+            if report {
+                handle_warning(&Colours::new(&Config::default()), &format!("Ignoring config file. {}", e))
+            }
+
+            None
+        },
+    }
+}
+
+/// Exits with a non-zero status if the export fails, so that scripts don't keep an empty or partial export.
+pub fn handle_export(hop_program: &hop::HopProgram<Prod>, format: LinkFormat, output_path: Option<&Path>) {
+    let action =
+        hop_program
+        .tabulate_links()
+        .and_then(|links| formats::export_links(&links, format).map(|exported| (links.len(), exported)));
+    let colours = Colours::new(&hop_program.config);
+
+    match (action, output_path) {
+        (Ok((_, exported)), None) => print!("{}", exported),
+        (Ok((count, exported)), Some(path)) => match fs::write(path, exported) {
+            Ok(()) => println!("Exported {} to {}", pluralise(count, "link"), path.to_string_lossy()),
+            Err(e) => {
+                handle_error(&colours, e, &format!("Could not write export file: {}", path.to_string_lossy()));
+                process::exit(1)
+            },
+        },
+        (Err(e), _) => {
+            handle_error(&colours, e, "Could not export links");
+            process::exit(1)
+        },
+    }
+}
+
+/// Exits with a non-zero status if the import fails, so that scripts that set up a hop home can stop.
+pub fn handle_import(hop_program: &hop::HopProgram<Prod>, import_path: Option<&Path>, source: ImportSource, options: &ImportOptions) {
+    let colours = Colours::new(&hop_program.config);
+
+    let import_path = match (import_path, source) {
+        (Some(path), _) => Ok(path.to_path_buf()),
+        (None, ImportSource::Bookmarks(tool)) => hop_program.value.home_dir().map(|home| home.join(tool.default_file())),
+        (None, ImportSource::Export(_)) => Err(io_error("Please give a file to import")),
+    };
+
+    let action =
+        import_path
+        .and_then(|path| {
+            fs::read_to_string(&path)
+                .map_err(|e| io_error_ex(&format!("Could not read file: {}", path.to_string_lossy()), e))
+                .map(|contents| (path, contents))
+        })
+        .and_then(|(path, contents)| match source {
+            ImportSource::Export(format) => parse_export(&path, &contents, format),
+            ImportSource::Bookmarks(tool) => {
+                let records = formats::parse_bookmarks(&contents, tool, &hop_program.value.home_dir()?);
+                Ok(sanitise_names(&colours, records))
+            },
+        })
+        .and_then(|records| {
+            let pairs = records.into_iter().map(|record| LinkPair::new(record.name, record.target)).collect();
+            hop_program.import_links(pairs, options)
+        });
+
+    match action {
+        Ok(actions) => {
+            actions
+                .iter()
+                .for_each(|action| println!("{}", describe_import_action(&colours, action, options.dry_run)));
+
+            let created = actions.iter().filter(|action| matches!(action, ImportAction::Create(_))).count();
+            let updated = actions.iter().filter(|action| matches!(action, ImportAction::Update(_))).count();
+            let renamed = actions.iter().filter(|action| matches!(action, ImportAction::Rename(_, _))).count();
+            let skipped = actions.iter().filter(|action| matches!(action, ImportAction::Skip(_))).count();
+
+            if options.dry_run {
+                println!(
+                    "Dry run: {} to create, {} to update, {} to rename and {} to skip. No links were changed.",
+                    created, updated, renamed, skipped
+                )
+            } else {
+                println!(
+                    "Imported {}: {} created, {} updated, {} renamed and {} skipped",
+                    pluralise(actions.len(), "link"), created, updated, renamed, skipped
+                )
+            }
+        },
+        Err(e) => {
+            handle_error(&colours, e, "Could not import links");
+            process::exit(1)
+        },
+    }
+}
+
+/// Parses a file written by `hop export`. The format is worked out from the extension of `path` if not given.
+fn parse_export(path: &Path, contents: &str, format: Option<LinkFormat>) -> io::Result<Vec<LinkRecord>> {
+    format
+        .or_else(|| LinkFormat::from_path(path))
+        .ok_or_else(|| io_error(&format!("Could not work out the format of {}. Please use --format", path.to_string_lossy())))
+        .and_then(|format| formats::parse_links(contents, format))
+}
+
+/// Reads the links in another hop home, or in a file written by `hop export` if `other_path` is not a directory.
+fn read_other_links(hop_program: &hop::HopProgram<Prod>, other_path: &Path, format: Option<LinkFormat>) -> io::Result<Vec<LinkPair>> {
+    if other_path.is_dir() {
+        hop_program.hop_home_links(other_path)
+    } else {
+        let contents =
+            fs::read_to_string(other_path)
+            .map_err(|e| io_error_ex(&format!("Could not read file: {}", other_path.to_string_lossy()), e))?;
+
+        let records = parse_export(other_path, &contents, format)?;
+        Ok(records.into_iter().map(|record| LinkPair::new(record.name, record.target)).collect())
+    }
+}
+
+/// Shows how the links in the hop home differ from another hop home or export file. Like `diff`, exits with a
+/// non-zero status if there are any differences.
+pub fn handle_diff(hop_program: &hop::HopProgram<Prod>, other_path: &Path, format: Option<LinkFormat>) {
+    let action =
+        read_other_links(hop_program, other_path, format)
+        .and_then(|other| hop_program.diff_links(other));
+    let colours = Colours::new(&hop_program.config);
+    let other_name = other_path.to_string_lossy();
+
+    match action {
+        Ok(diff) if diff.is_empty() => println!("The hop home and {} have the same links", other_name),
+        Ok(diff) => {
+            let print_links = |heading: &str, links: &[LinkPair]| {
+                if !links.is_empty() {
+                    println!("{}", heading);
+                    links.iter().for_each(|lp| println!("  {} {} {}", lp.link, colours.yellow("->"), lp.target))
+                }
+            };
+
+            print_links("Only here:", &diff.only_here);
+            print_links(&format!("Only in {}:", other_name), &diff.only_there);
+
+            if !diff.different_targets.is_empty() {
+                println!("Different targets:");
+                diff.different_targets.iter().for_each(|(here_lp, there_lp)| {
+                    println!("  {} {} {} here, {} in {}", here_lp.link, colours.yellow("->"), here_lp.target, there_lp.target, other_name)
+                })
+            }
+
+            if !diff.different_names.is_empty() {
+                println!("Different names:");
+                diff.different_names.iter().for_each(|(here_lp, there_lp)| {
+                    println!("  {} is `{}` here, `{}` in {}", here_lp.target, here_lp.link, there_lp.link, other_name)
+                })
+            }
+
+            process::exit(1)
+        },
+        Err(e) => {
+            handle_error(&colours, e, &format!("Could not compare links with {}", other_name));
+            process::exit(2)
+        },
+    }
+}
+
+/// Adds the links from another hop home or export file, asking which side wins for each link that differs.
+pub fn handle_merge(hop_program: &hop::HopProgram<Prod>, other_path: &Path, format: Option<LinkFormat>) {
+    let other_name = other_path.to_string_lossy();
+    let action =
+        read_other_links(hop_program, other_path, format)
+        .and_then(|other| hop_program.merge_links(other, &other_name));
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(actions) => {
+            actions
+                .iter()
+                .for_each(|action| println!("{}", describe_import_action(&colours, action, false)));
+
+            let created = actions.iter().filter(|action| matches!(action, ImportAction::Create(_))).count();
+            let updated = actions.iter().filter(|action| matches!(action, ImportAction::Update(_))).count();
+            let renamed = actions.iter().filter(|action| matches!(action, ImportAction::Rename(_, _))).count();
+            let skipped = actions.iter().filter(|action| matches!(action, ImportAction::Skip(_))).count();
+
+            println!(
+                "Merged {}: {} created, {} updated, {} renamed and {} skipped",
+                other_name, created, updated, renamed, skipped
+            )
+        },
+        Err(e) => {
+            handle_error(&colours, e, &format!("Could not merge links from {}", other_name));
+            process::exit(1)
+        },
+    }
+}
+
+/// Names from other tools may not follow hop's naming rules. Each name that is changed or dropped is reported.
+fn sanitise_names(colours: &Colours, records: Vec<LinkRecord>) -> Vec<LinkRecord> {
+    records
+        .into_iter()
+        .filter_map(|record| match formats::sanitise_name(&record.name) {
+            Some(name) if name == record.name => Some(record),
+            Some(name) => {
+                println!("Renamed `{}` to `{}` to follow hop's naming rules", record.name, name);
+                Some(LinkRecord { name, ..record })
+            },
+            None => {
+                handle_warning(colours, &format!("Skipping `{}` as it has no characters that can be used in a link name", record.name));
+                None
+            },
+        })
+        .collect()
+}
+
+fn describe_import_action(colours: &Colours, action: &ImportAction, dry_run: bool) -> String {
+    let (verb, planned_verb, lp) = match action {
+        ImportAction::Create(lp) => ("Created", "Create", lp),
+        ImportAction::Update(lp) => ("Updated", "Update", lp),
+        ImportAction::Rename(_, lp) => ("Renamed", "Rename", lp),
+        ImportAction::Skip(lp) => ("Skipped", "Skip", lp),
+    };
+
+    let verb = if dry_run { planned_verb } else { verb };
+
+    match action {
+        ImportAction::Rename(from, _) => format!("{} {} to {} {} {}", verb, from, lp.link, colours.yellow("->"), lp.target),
+        _ => format!("{} {} {} {}", verb, lp.link, colours.yellow("->"), lp.target),
+    }
+}
+
+pub fn handle_suggest(hop_program: &hop::HopProgram<Prod>, tool: FrecencyTool, data_path: Option<&Path>, limit: usize) {
+    let colours = Colours::new(&hop_program.config);
+
+    let data_path = match data_path {
+        Some(path) => Ok(path.to_path_buf()),
+        None => hop_program.frecency_file(tool),
+    };
+
+    let action =
+        data_path
+        .and_then(|path| {
+            fs::read_to_string(&path)
+                .map_err(|e| io_error_ex(&format!("Could not read file: {}", path.to_string_lossy()), e))
+        })
+        .and_then(|contents| hop_program.suggest_links(formats::parse_visits(&contents, tool), limit));
+
+    match action {
+        Ok(marked) if marked.is_empty() => println!("No links were created"),
+        Ok(marked) => println!("Created {}", pluralise(marked.len(), "link")),
+        Err(e) => handle_error(&colours, e, "Could not suggest links"),
+    }
+}
+
+/// Shows the links a scan would create and creates them once confirmed. Exits with a non-zero status if any of them
+/// can't be created.
+pub fn handle_scan(hop_program: &hop::HopProgram<Prod>, dir: &LinkTarget, options: &ScanOptions, dry_run: bool) {
+    let colours = Colours::new(&hop_program.config);
+
+    let link_pairs = match hop_program.scan_dirs(dir, options) {
+        Ok(link_pairs) if link_pairs.is_empty() => return println!("No unmarked directories were found in {}", dir),
+        Ok(link_pairs) => link_pairs,
+        Err(e) => {
+            handle_error(&colours, e, &format!("Could not scan {}", dir));
+            process::exit(1)
+        },
+    };
+
+    link_pairs.iter().for_each(|lp| println!("{} {} {}", lp.link, colours.yellow("->"), lp.target));
+
+    if dry_run {
+        return
+    }
+
+    match hop_program.confirm(&format!("Create {}?", pluralise(link_pairs.len(), "link"))) {
+        Ok(true) => match hop_program.mark_links(&link_pairs) {
+            Ok(marked_pairs) => marked_pairs
+                .iter()
+                .for_each(|lp| println!("Created link from {} {} {}", lp.link, colours.yellow("->"), lp.target)),
+            Err(e) => {
+                handle_error(&colours, e, "Could not create links");
+                process::exit(1)
+            },
+        },
+        Ok(false) => println!("No links were created"),
+        Err(e) => {
+            handle_error(&colours, e, "Could not create links");
+            process::exit(1)
+        },
+    }
+}
+
+/// Syncs hop with the bookmarks of GTK file managers after showing the changes that will be made to each.
+pub fn handle_sync_gtk(hop_program: &hop::HopProgram<Prod>, bookmarks_path: Option<&Path>, state_path: Option<&Path>, dry_run: bool) {
+    let colours = Colours::new(&hop_program.config);
+
+    let action =
+        bookmarks_path
+        .zip(state_path)
+        .ok_or_else(|| io_error("Could not get home directory"))
+        .and_then(|(bookmarks_path, state_path)| sync_gtk(hop_program, &colours, bookmarks_path, state_path, dry_run));
+
+    if let Err(e) = action {
+        handle_error(&colours, e, "Could not sync with GTK bookmarks")
+    }
+}
+
+fn sync_gtk(
+    hop_program: &hop::HopProgram<Prod>,
+    colours: &Colours,
+    bookmarks_path: &Path,
+    state_path: &Path,
+    dry_run: bool
+) -> io::Result<()> {
+    let hop_home = hop_program.hop_home()?;
+
+    let contents = match fs::read_to_string(bookmarks_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(io_error_ex(&format!("Could not read GTK bookmarks: {}", bookmarks_path.to_string_lossy()), e)),
+    };
+
+    let base = config::read_sync_state(state_path, &hop_home)?;
+    let plan = hop_program.plan_sync(formats::parse_gtk_bookmarks(&contents), base)?;
+
+    print_sync_changes(colours, "hop", &plan.hop);
+    print_sync_changes(colours, "GTK bookmarks", &plan.other);
+
+    plan.conflicts.iter().for_each(|(hop_lp, other_lp)| {
+        handle_warning(colours, &format!(
+            "`{}` was changed in both. hop has {} and GTK bookmarks has {}. Leaving both as they are.",
+            hop_lp.link, hop_lp.target, other_lp.target
+        ))
+    });
+
+    if plan.hop.is_empty() && plan.other.is_empty() {
+        println!("hop and GTK bookmarks are in sync");
+    } else if dry_run {
+        println!("Dry run: No changes were made.");
+        return Ok(())
+    } else if !hop_program.confirm("Apply these changes?")? {
+        println!("Aborting sync");
+        return Ok(())
+    }
+
+    hop_program.apply_sync("GTK bookmarks", &plan.hop)?;
+
+    if !plan.other.is_empty() {
+        bookmarks_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(bookmarks_path, formats::update_gtk_bookmarks(&contents, &plan.other)))
+            .map_err(|e| io_error_ex(&format!("Could not write GTK bookmarks: {}", bookmarks_path.to_string_lossy()), e))?;
+    }
+
+    config::write_sync_state(state_path, &hop_home, &plan.synced)
+}
+
+/// Syncs a git-backed hop home with its remote, exiting with a non-zero status if it could not be synced.
+pub fn handle_sync_git(hop_program: &hop::HopProgram<Prod>, prefer: Option<SyncPreference>) {
+    let action = hop_program.sync_git(prefer);
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(GitSyncStatus::Synced) => println!("Synced hop home with its git remote"),
+        Ok(GitSyncStatus::Conflicts(conflicts)) => {
+            println!("{}", colours.yellow("These links were changed both here and in the remote:"));
+
+            conflicts.iter().for_each(|conflict| {
+                let describe = |target: &Option<LinkTarget>| target.as_ref().map_or("deleted".to_string(), |t| format!("{} {}", colours.yellow("->"), t));
+                println!("  {} here {}, remote {}", conflict.link, describe(&conflict.local), describe(&conflict.remote))
+            });
+
+            println!("Nothing was synced. Run {} or {} to choose which side wins.", colours.yellow("hop sync --prefer local"), colours.yellow("hop sync --prefer remote"));
+            process::exit(1)
+        },
+        Err(e) => {
+            handle_error(&colours, e, "Could not sync hop home with its git remote");
+            process::exit(1)
+        },
+    }
+}
+
+pub fn handle_snapshot_create(hop_program: &hop::HopProgram<Prod>, label: Option<&str>) {
+    let action = hop_program.create_snapshot(label);
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(snapshot) => println!("Created snapshot {} of {}", snapshot.id, pluralise(snapshot.links.len(), "link")),
+        Err(e) => {
+            handle_error(&colours, e, "Could not create snapshot");
+            process::exit(1)
+        },
+    }
+}
+
+pub fn handle_snapshot_list(hop_program: &hop::HopProgram<Prod>) {
+    let action = hop_program.snapshots();
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(snapshots) if snapshots.is_empty() => println!("There are no snapshots of the hop home"),
+        Ok(snapshots) => {
+            snapshots.iter().for_each(|snapshot| {
+                let label = snapshot.label.as_ref().map_or(String::new(), |label| format!(" {}", label));
+
+                println!(
+                    "{} {} {}{}",
+                    colours.yellow(&snapshot.id),
+                    describe_time(&snapshot.created),
+                    pluralise(snapshot.links.len(), "link"),
+                    label
+                )
+            })
+        },
+        Err(e) => handle_error(&colours, e, "Could not list snapshots"),
+    }
+}
+
+/// Shows the changes a restore would make and asks before making them.
+pub fn handle_snapshot_restore(hop_program: &hop::HopProgram<Prod>, id: &str, dry_run: bool) {
+    let colours = Colours::new(&hop_program.config);
+
+    let action = hop_program.plan_restore(id).and_then(|(snapshot, changes)| {
+        print_sync_changes(&colours, "hop", &changes);
+
+        if changes.is_empty() {
+            println!("The hop home already matches snapshot {}", snapshot.id);
+        } else if dry_run {
+            println!("Dry run: No changes were made.");
+        } else if hop_program.confirm(&format!("Restore snapshot {}?", snapshot.id))? {
+            hop_program.restore_snapshot(&snapshot, &changes)?;
+            println!("Restored snapshot {}", snapshot.id);
+        } else {
+            println!("Aborting restore");
         }
-        Err(e) => handle_error(e, &format!("Could not delete link: {}", link)),
+
+        Ok(())
+    });
+
+    if let Err(e) = action {
+        handle_error(&colours, e, &format!("Could not restore snapshot {}", id));
+        process::exit(1)
+    }
+}
+
+fn print_sync_changes(colours: &Colours, side: &str, changes: &[SyncChange]) {
+    if !changes.is_empty() {
+        println!("Changes to {}:", side);
+
+        changes.iter().for_each(|change| {
+            let (symbol, lp) = match change {
+                SyncChange::Add(lp) => ("+", lp),
+                SyncChange::Update(lp) => ("~", lp),
+                SyncChange::Remove(lp) => ("-", lp),
+            };
+
+            println!("  {} {} {} {}", symbol, lp.link, colours.yellow("->"), lp.target)
+        })
+    }
+}
+
+pub fn handle_undo(hop_program: &hop::HopProgram<Prod>, count: usize) {
+    let action = hop_program.undo(count);
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(entries) => entries.iter().for_each(|entry| println!("Undid {}", entry.description)),
+        Err(e) => {
+            handle_error(&colours, e, "Could not undo changes");
+            process::exit(1)
+        },
+    }
+}
+
+/// Prints the outcome of each operation as a table. Exits with a non-zero status unless every operation was applied.
+pub fn handle_batch(hop_program: &hop::HopProgram<Prod>, batch_path: Option<&Path>) {
+    let colours = Colours::new(&hop_program.config);
+
+    let contents = match batch_path {
+        Some(path) => {
+            fs::read_to_string(path)
+                .map_err(|e| io_error_ex(&format!("Could not read file: {}", path.to_string_lossy()), e))
+        },
+        None => io::read_to_string(io::stdin()).map_err(|e| io_error_ex("Could not read the batch from stdin", e)),
+    };
+
+    let action =
+        contents
+        .and_then(|contents| formats::parse_batch(&contents))
+        .and_then(|ops| hop_program.run_batch(&ops).map(|outcomes| (ops, outcomes)));
+
+    match action {
+        Ok((ops, outcomes)) => {
+            let descriptions: Vec<String> = ops.iter().map(|op| op.to_string()).collect();
+            let width = descriptions.iter().map(|description| description.chars().count()).max().unwrap_or(0);
+            let number_width = ops.len().to_string().len();
+
+            for (index, (description, outcome)) in descriptions.iter().zip(&outcomes).enumerate() {
+                let result = match outcome {
+                    BatchOutcome::Applied => "applied".to_string(),
+                    BatchOutcome::Invalid(e) => colours.red(&format!("invalid: {}", e)),
+                    BatchOutcome::NotApplied => "not applied".to_string(),
+                    BatchOutcome::RolledBack => colours.yellow("rolled back"),
+                    BatchOutcome::Failed(e) => colours.red(&format!("failed: {}", e)),
+                };
+
+                println!("{:>number_width$}  {:<width$}  {}", index + 1, description, result, number_width = number_width, width = width)
+            }
+
+            let invalid = outcomes.iter().filter(|outcome| matches!(outcome, BatchOutcome::Invalid(_))).count();
+            let failed = outcomes.iter().any(|outcome| matches!(outcome, BatchOutcome::Failed(_)));
+
+            if invalid > 0 {
+                eprintln!("{}", colours.red(&format!("Nothing was changed as {} can't be applied.", pluralise(invalid, "operation"))));
+                process::exit(1)
+            } else if failed {
+                eprintln!("{}", colours.red("Nothing was changed as an operation failed and the operations before it were rolled back."));
+                process::exit(1)
+            } else {
+                println!("Applied {}", pluralise(ops.len(), "operation"))
+            }
+        },
+        Err(e) => {
+            handle_error(&colours, e, "Could not apply the batch");
+            process::exit(1)
+        },
+    }
+}
+
+pub fn handle_log(hop_program: &hop::HopProgram<Prod>, limit: usize) {
+    let action = hop_program.journal();
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(entries) if entries.is_empty() => println!("No changes have been made"),
+        Ok(entries) => {
+            entries.iter().take(limit).enumerate().for_each(|(index, entry)| {
+                println!("{} {} {}", colours.yellow(&format!("{}.", index + 1)), describe_time(&entry.time), entry.description);
+                entry.changes.iter().for_each(|change| println!("  {}", describe_link_change(&colours, change)))
+            })
+        },
+        Err(e) => handle_error(&colours, e, "Could not read the journal"),
+    }
+}
+
+pub fn handle_trash_list(hop_program: &hop::HopProgram<Prod>) {
+    let action = hop_program.trashed_links();
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(trashed_links) if trashed_links.is_empty() => println!("The trash is empty"),
+        Ok(trashed_links) => {
+            trashed_links.iter().for_each(|trashed| {
+                let lp = &trashed.link_pair;
+                println!("{} {} {} (deleted {})", lp.link, colours.yellow("->"), lp.target, describe_time(&trashed.deleted))
+            })
+        },
+        Err(e) => handle_error(&colours, e, "Could not read the trash"),
+    }
+}
+
+pub fn handle_trash_restore(hop_program: &hop::HopProgram<Prod>, link: &Link) {
+    let action = hop_program.restore_link(link);
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(lp) => println!("Restored link {} {} {}", lp.link, colours.yellow("->"), lp.target),
+        Err(e) => {
+            handle_error(&colours, e, &format!("Could not restore link: {}", link));
+            process::exit(1)
+        },
     }
 }
 
+pub fn handle_trash_empty(hop_program: &hop::HopProgram<Prod>) {
+    let action = hop_program.empty_trash();
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(removed) => println!("Removed {} from the trash", pluralise(removed.len(), "link")),
+        Err(e) => handle_error(&colours, e, "Could not empty the trash"),
+    }
+}
+
+/// Shows a time from the journal or the trash in the local time zone, or as it was stored if it can't be read.
+fn describe_time(time: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|_| time.to_string())
+}
+
+fn describe_link_change(colours: &Colours, change: &LinkChange) -> String {
+    match (&change.before, &change.after) {
+        (None, Some(after)) => format!("+ {} {} {}", change.link, colours.yellow("->"), after),
+        (Some(before), None) => format!("- {} {} {}", change.link, colours.yellow("->"), before),
+        (Some(before), Some(after)) => format!("~ {} {} {} (was {})", change.link, colours.yellow("->"), after, before),
+        (None, None) => format!("  {}", change.link),
+    }
+}
+
+pub fn handle_migrate_home(hop_program: &hop::HopProgram<Prod>) {
+    let action = hop_program.migrate_home();
+    let colours = Colours::new(&hop_program.config);
+
+    match action {
+        Ok(migration) => {
+            migration
+                .links
+                .iter()
+                .for_each(|lp| println!("Moved link {} {} {}", lp.link, colours.yellow("->"), lp.target));
+
+            println!(
+                "Migrated {} from {} to {}",
+                pluralise(migration.links.len(), "link"),
+                migration.from.to_string_lossy(),
+                migration.to.to_string_lossy()
+            )
+        },
+        Err(e) => {
+            handle_error(&colours, e, "Could not migrate hop home");
+            process::exit(1)
+        },
+    }
+}
+
+pub fn pluralise(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+/// Choosing this profile goes back to the default hop home.
+const DEFAULT_PROFILE: &str = "default";
+
 pub fn io_error(message: &str) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, message)
+    io::Error::other(message)
 }
 
 pub fn io_error_ex(message: &str, e: io::Error) -> io::Error {
-    io_error(&format!("{}\n{}", message, e.to_string()))
+    io_error(&format!("{}\n{}", message, e))
 }
 
 /// Creates an error with both the `current_error` and the `original_error` that
 /// cause the current error.
 pub fn io_error_ex_nested(message: &str, current_error: io::Error, original_error: io::Error) -> io::Error {
-    io_error(&format!("{}\n{}\n{}", message, current_error.to_string(), original_error.to_string()))
+    io_error(&format!("{}\n{}\n{}", message, current_error, original_error))
 }
 
-fn handle_error(error: io::Error, message: &str) {
-    println!("{}", Yellow.paint(message));
-    eprintln!("{}", Red.paint(format!("Error: {}", error)))
+fn handle_error(colours: &Colours, error: io::Error, message: &str) {
+    println!("{}", colours.yellow(message));
+    eprintln!("{}", colours.red(&format!("Error: {}", error)))
+}
+
+fn handle_warning(colours: &Colours, message: &str) {
+    eprintln!("{}", colours.yellow(&format!("Warning: {}", message)))
+}
+
+/// Paints output only when colour is enabled in the config.
+struct Colours {
+    enabled: bool,
+}
+
+impl Colours {
+    fn new(config: &Config) -> Self {
+        let enabled = match config.colour {
+            ColourMode::Always => true,
+            ColourMode::Never => false,
+            ColourMode::Auto => io::stdout().is_terminal(),
+        };
+
+        Colours { enabled }
+    }
+
+    fn yellow(&self, text: &str) -> String {
+        self.paint(Yellow, text)
+    }
+
+    fn red(&self, text: &str) -> String {
+        self.paint(Red, text)
+    }
+
+    fn paint(&self, colour: Colour, text: &str) -> String {
+        if self.enabled {
+            colour.paint(text).to_string()
+        } else {
+            text.to_string()
+        }
+    }
 }
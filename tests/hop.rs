@@ -1,7 +1,12 @@
+#![allow(clippy::expect_fun_call)]
+
 use assert_cmd::Command;
 
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
 use tempfile::tempdir;
 use std::os::unix::fs as nixfs;
 use ansi_term::Color::Yellow;
@@ -69,8 +74,9 @@ fn create_links() -> Result<(), Box<dyn std::error::Error>> {
 
     fs::metadata(target_dir).expect(&format!("Could not find target dir: {}", target_dir.to_string_lossy()));
 
-    let entries = fs::read_dir(hop_home)?.map(|res| res.map(|d| d.file_name())).collect::<Result<Vec<_>, io::Error>>()?;
-    assert_eq!(entries, vec!["blee"]);
+    let mut entries = fs::read_dir(hop_home)?.map(|res| res.map(|d| d.file_name())).collect::<Result<Vec<_>, io::Error>>()?;
+    entries.sort();
+    assert_eq!(entries, vec![".lock", "blee"]);
 
     working_dir.close()?;
 
@@ -438,3 +444,1980 @@ fn delete_link() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn jump_to_non_utf8_target() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let hop_home_temp = working_dir.path().join("mine").join("hophome");
+    let hop_home = hop_home_temp.as_path();
+
+    //"caf\xe9" is latin-1 and not valid UTF-8
+    let target_dir_temp = working_dir.path().join(OsStr::from_bytes(b"caf\xe9"));
+    let target_dir = target_dir_temp.as_path();
+
+    fs::create_dir_all(target_dir)?;
+
+    Command::cargo_bin("hop")?
+        .arg("-c")
+        .arg(hop_home.as_os_str())
+        .arg("-m")
+        .arg("cafe")
+        .arg(target_dir.as_os_str())
+        .assert()
+        .success();
+
+    let output =
+        Command::cargo_bin("hop")?
+        .arg("-c")
+        .arg(hop_home.as_os_str())
+        .arg("-j")
+        .arg("cafe")
+        .output()?;
+
+    let mut expected = target_dir.as_os_str().as_bytes().to_vec();
+    expected.push(b'\n');
+
+    assert_eq!(output.stdout, expected);
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+fn config_show_output(hop_home: &Path, source: &str, config_home: &Path) -> String {
+    format!(
+        "hop home: {}\nsource: {}\nconfig file: {} (not found)\n",
+        hop_home.to_string_lossy(),
+        source,
+        config_home.join("hop").join("config.toml").to_string_lossy()
+    )
+}
+
+#[test]
+fn hop_home_from_env() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let hop_home_temp = working_dir.path().join("mine").join("hophome");
+    let hop_home = hop_home_temp.as_path();
+
+    Command::cargo_bin("hop")?
+        .env("HOP_HOME", hop_home.as_os_str())
+        .env_remove("HOP_OPTS")
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("show")
+        .assert()
+        .success()
+        .stdout(config_show_output(hop_home, "HOP_HOME environment variable", working_dir.path()));
+
+    fs::metadata(hop_home).expect(&format!("Could not find hop_home: {}", hop_home.to_string_lossy()));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn hop_home_flag_overrides_env() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let env_home_temp = working_dir.path().join("env-home");
+    let opts_home_temp = working_dir.path().join("opts-home");
+    let flag_home_temp = working_dir.path().join("flag-home");
+
+    let mut hop_opts = OsString::from("-c ");
+    hop_opts.push(opts_home_temp.as_os_str());
+
+    //HOP_HOME wins over -c in HOP_OPTS
+    Command::cargo_bin("hop")?
+        .env("HOP_HOME", env_home_temp.as_os_str())
+        .env("HOP_OPTS", &hop_opts)
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("show")
+        .assert()
+        .success()
+        .stdout(config_show_output(&env_home_temp, "HOP_HOME environment variable", working_dir.path()));
+
+    //-c in HOP_OPTS is used when HOP_HOME is not set
+    Command::cargo_bin("hop")?
+        .env_remove("HOP_HOME")
+        .env("HOP_OPTS", &hop_opts)
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("show")
+        .assert()
+        .success()
+        .stdout(config_show_output(&opts_home_temp, "-c/--c flag in the HOP_OPTS environment variable", working_dir.path()));
+
+    //-c on the command line wins over everything
+    Command::cargo_bin("hop")?
+        .env("HOP_HOME", env_home_temp.as_os_str())
+        .env("HOP_OPTS", &hop_opts)
+        .arg("-c")
+        .arg(flag_home_temp.as_os_str())
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("show")
+        .assert()
+        .success()
+        .stdout(config_show_output(&flag_home_temp, "-c/--c flag", working_dir.path()));
+
+    //-c on the command line wins even when it repeats the one in HOP_OPTS
+    Command::cargo_bin("hop")?
+        .env("HOP_HOME", env_home_temp.as_os_str())
+        .env("HOP_OPTS", &hop_opts)
+        .arg("-c")
+        .arg(opts_home_temp.as_os_str())
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("show")
+        .assert()
+        .success()
+        .stdout(config_show_output(&opts_home_temp, "-c/--c flag", working_dir.path()));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn actions_are_not_allowed_in_hop_opts() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+
+    Command::cargo_bin("hop")?
+        .env("HOP_HOME", working_dir.path().join("hophome").as_os_str())
+        .env("HOP_OPTS", "--no-local -l")
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("show")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("`-l` can't be used in HOP_OPTS."));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn default_hop_home_is_xdg() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let home = working_dir.path().join("home");
+    let data_home = working_dir.path().join("data");
+
+    fs::create_dir_all(&home)?;
+
+    Command::cargo_bin("hop")?
+        .env("HOME", home.as_os_str())
+        .env("XDG_DATA_HOME", data_home.as_os_str())
+        .env_remove("HOP_HOME")
+        .env_remove("HOP_OPTS")
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("show")
+        .assert()
+        .success()
+        .stdout(config_show_output(&data_home.join("hop"), "default", working_dir.path()));
+
+    //An existing ~/.hop is still used
+    fs::create_dir_all(home.join(".hop"))?;
+
+    Command::cargo_bin("hop")?
+        .env("HOME", home.as_os_str())
+        .env("XDG_DATA_HOME", data_home.as_os_str())
+        .env_remove("HOP_HOME")
+        .env_remove("HOP_OPTS")
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("show")
+        .assert()
+        .success()
+        .stdout(config_show_output(&home.join(".hop"), "default", working_dir.path()));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn migrate_legacy_hop_home() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let home = working_dir.path().join("home");
+    let data_home = working_dir.path().join("data");
+    let legacy_home = home.join(".hop");
+    let target_dir = working_dir.path().join("somedir");
+
+    fs::create_dir_all(legacy_home.join(".git"))?;
+    fs::create_dir_all(&target_dir)?;
+    nixfs::symlink(&target_dir, legacy_home.join("tag1"))?;
+    fs::write(legacy_home.join(".git").join("HEAD"), "ref: refs/heads/main\n")?;
+
+    Command::cargo_bin("hop")?
+        .env("HOME", home.as_os_str())
+        .env("XDG_DATA_HOME", data_home.as_os_str())
+        .env_remove("HOP_HOME")
+        .env_remove("HOP_OPTS")
+        .arg("migrate-home")
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_link(data_home.join("hop").join("tag1"))?, target_dir);
+    assert_eq!(fs::read_to_string(data_home.join("hop").join(".git").join("HEAD"))?, "ref: refs/heads/main\n");
+    assert_eq!(fs::read_link(&legacy_home)?, data_home.join("hop"));
+
+    let output =
+        Command::cargo_bin("hop")?
+        .env("HOME", home.as_os_str())
+        .env("XDG_DATA_HOME", data_home.as_os_str())
+        .env_remove("HOP_HOME")
+        .env_remove("HOP_OPTS")
+        .arg("-l")
+        .output()?;
+
+    assert_eq!(String::from_utf8(output.stdout)?, "tag1\n");
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn migrate_legacy_hop_home_conflict() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let home = working_dir.path().join("home");
+    let data_home = working_dir.path().join("data");
+    let legacy_home = home.join(".hop");
+    let target_dir = working_dir.path().join("somedir");
+
+    fs::create_dir_all(legacy_home.join(".git"))?;
+    fs::create_dir_all(data_home.join("hop").join(".git"))?;
+    fs::create_dir_all(&target_dir)?;
+    nixfs::symlink(&target_dir, legacy_home.join("tag1"))?;
+
+    Command::cargo_bin("hop")?
+        .env("HOME", home.as_os_str())
+        .env("XDG_DATA_HOME", data_home.as_os_str())
+        .env_remove("HOP_HOME")
+        .env_remove("HOP_OPTS")
+        .arg("migrate-home")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("`.git` already exists"));
+
+    assert_eq!(fs::read_link(legacy_home.join("tag1"))?, target_dir);
+    assert!(fs::symlink_metadata(data_home.join("hop").join("tag1")).is_err());
+    assert!(fs::symlink_metadata(&legacy_home)?.is_dir());
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn config_file_home_and_output() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("mine").join("hophome");
+    let target_dir1 = working_dir.path().join("somedir1");
+    let target_dir2 = working_dir.path().join("somedir2");
+
+    fs::create_dir_all(config_home.join("hop"))?;
+    fs::create_dir_all(&hop_home)?;
+    fs::create_dir_all(&target_dir1)?;
+    fs::create_dir_all(&target_dir2)?;
+
+    nixfs::symlink(&target_dir2, hop_home.join("tag1"))?;
+    nixfs::symlink(&target_dir1, hop_home.join("tag2"))?;
+
+    fs::write(
+        config_home.join("hop").join("config.toml"),
+        format!("home = \"{}\"\noutput = \"json\"\nsort = \"target\"\n", hop_home.to_string_lossy())
+    )?;
+
+    let output =
+        Command::cargo_bin("hop")?
+        .env("XDG_CONFIG_HOME", config_home.as_os_str())
+        .env("XDG_STATE_HOME", config_home.as_os_str())
+        .env_remove("HOP_HOME")
+        .env_remove("HOP_OPTS")
+        .arg("-t")
+        .output()?;
+
+    let expected = format!(
+        "[{{\"layer\":\"personal\",\"name\":\"tag2\",\"target\":\"{}\"}},{{\"layer\":\"personal\",\"name\":\"tag1\",\"target\":\"{}\"}}]\n",
+        target_dir1.to_string_lossy(),
+        target_dir2.to_string_lossy()
+    );
+
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+
+    Command::cargo_bin("hop")?
+        .env("XDG_CONFIG_HOME", config_home.as_os_str())
+        .env("XDG_STATE_HOME", config_home.as_os_str())
+        .env_remove("HOP_HOME")
+        .env_remove("HOP_OPTS")
+        .arg("config")
+        .arg("show")
+        .assert()
+        .success()
+        .stdout(format!(
+            "hop home: {}\nsource: home in the config file\nconfig file: {}\n",
+            hop_home.to_string_lossy(),
+            config_home.join("hop").join("config.toml").to_string_lossy()
+        ));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn config_check() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_file = working_dir.path().join("hop").join("config.toml");
+
+    fs::create_dir_all(working_dir.path().join("hop"))?;
+    fs::write(&config_file, "colour = \"never\"\nfavourite_colour = \"blue\"\n")?;
+
+    Command::cargo_bin("hop")?
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("check")
+        .assert()
+        .success()
+        .stdout(format!("{} is valid with 1 warning\n", config_file.to_string_lossy()))
+        .stderr("Warning: Unknown key `favourite_colour` in config file\n");
+
+    //Warnings when the config file is read at startup follow its colour setting
+    fs::create_dir_all(working_dir.path().join("hophome"))?;
+
+    Command::cargo_bin("hop")?
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .env("HOP_HOME", working_dir.path().join("hophome").as_os_str())
+        .env_remove("HOP_OPTS")
+        .arg("-l")
+        .assert()
+        .success()
+        .stderr(format!("Warning: Unknown key `favourite_colour` in config file ({})\n", config_file.to_string_lossy()));
+
+    fs::write(&config_file, "colour = \"never\"\n[[shared]]\nname = \"team\"\nhome = \"/srv/hop\"\nwritable = true\n")?;
+
+    Command::cargo_bin("hop")?
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("check")
+        .assert()
+        .success()
+        .stdout(format!("{} is valid with 1 warning\n", config_file.to_string_lossy()))
+        .stderr("Warning: Unknown key `shared.0.writable` in config file\n");
+
+    fs::write(&config_file, "sort = \"sideways\"\n")?;
+
+    Command::cargo_bin("hop")?
+        .env("XDG_CONFIG_HOME", working_dir.path().as_os_str())
+        .env("XDG_STATE_HOME", working_dir.path().as_os_str())
+        .arg("config")
+        .arg("check")
+        .assert()
+        .failure();
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn profiles() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let data_home = working_dir.path().join("data");
+    let config_home = working_dir.path().join("config");
+    let target_dir = working_dir.path().join("somedir");
+
+    fs::create_dir_all(&target_dir)?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_DATA_HOME", data_home.as_os_str())
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env_remove("HOP_HOME")
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    hop()?
+        .arg("--profile")
+        .arg("work")
+        .arg("-m")
+        .arg("client")
+        .arg(target_dir.as_os_str())
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_link(data_home.join("hop-profiles").join("work").join("client"))?, target_dir);
+
+    //Marks in the profile are not in the default hop home
+    hop()?
+        .arg("-l")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("No entries to list."));
+
+    hop()?
+        .arg("profile")
+        .arg("use")
+        .arg("work")
+        .assert()
+        .success();
+
+    hop()?
+        .arg("-t")
+        .assert()
+        .success()
+        .stdout(format!("{} work\nclient {} {}\n", Yellow.paint("Profile:"), Yellow.paint("->"), target_dir.to_string_lossy()));
+
+    hop()?
+        .arg("profile")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(format!("* {}\n", Yellow.paint("work")));
+
+    hop()?
+        .arg("profile")
+        .arg("use")
+        .arg("default")
+        .assert()
+        .success();
+
+    hop()?
+        .arg("-l")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("No entries to list."));
+
+    hop()?
+        .arg("--profile")
+        .arg("../escape")
+        .arg("-l")
+        .assert()
+        .failure();
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn shared_hop_home() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let team_home = working_dir.path().join("team");
+    let my_docs = working_dir.path().join("mydocs");
+    let team_docs = working_dir.path().join("teamdocs");
+    let team_wiki = working_dir.path().join("teamwiki");
+
+    fs::create_dir_all(config_home.join("hop"))?;
+    fs::create_dir_all(&hop_home)?;
+    fs::create_dir_all(&team_home)?;
+    fs::create_dir_all(&my_docs)?;
+    fs::create_dir_all(&team_docs)?;
+    fs::create_dir_all(&team_wiki)?;
+
+    nixfs::symlink(&my_docs, hop_home.join("docs"))?;
+    nixfs::symlink(&team_docs, team_home.join("docs"))?;
+    nixfs::symlink(&team_wiki, team_home.join("wiki"))?;
+
+    fs::write(
+        config_home.join("hop").join("config.toml"),
+        format!("colour = \"never\"\n\n[[shared]]\nname = \"team\"\nhome = \"{}\"\n", team_home.to_string_lossy())
+    )?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    //The personal link hides the shared link with the same name
+    hop()?
+        .arg("-t")
+        .assert()
+        .success()
+        .stdout(format!(
+            "docs -> {} [personal]\nwiki -> {} [team]\n",
+            my_docs.to_string_lossy(),
+            team_wiki.to_string_lossy()
+        ));
+
+    hop()?
+        .arg("-j")
+        .arg("wiki")
+        .assert()
+        .success()
+        .stdout(format!("{}\n", team_wiki.to_string_lossy()));
+
+    hop()?
+        .arg("-d")
+        .arg("wiki")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("The link `wiki` is in the read-only hop home `team` and can't be deleted."));
+
+    assert!(team_home.join("wiki").exists());
+
+    //A shared hop home that isn't available is skipped
+    fs::remove_dir_all(&team_home)?;
+
+    hop()?
+        .arg("-l")
+        .assert()
+        .success()
+        .stdout("docs\n");
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn local_hop_home() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let project = working_dir.path().join("project");
+    let api_dir = project.join("services").join("api");
+    let web_dir = project.join("web");
+    let global_web = working_dir.path().join("globalweb");
+
+    fs::create_dir_all(project.join(".hop"))?;
+    fs::create_dir_all(&hop_home)?;
+    fs::create_dir_all(&api_dir)?;
+    fs::create_dir_all(&web_dir)?;
+    fs::create_dir_all(&global_web)?;
+
+    //Relative targets are resolved against the project, not the .hop directory
+    nixfs::symlink("services/api", project.join(".hop").join("api"))?;
+    nixfs::symlink("web", project.join(".hop").join("web"))?;
+    nixfs::symlink(&global_web, hop_home.join("web"))?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .current_dir(&api_dir)
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    hop()?
+        .arg("-j")
+        .arg("api")
+        .assert()
+        .success()
+        .stdout(format!("{}\n", project.join("services/api").to_string_lossy()));
+
+    //Local links hide global links with the same name
+    hop()?
+        .arg("-j")
+        .arg("web")
+        .assert()
+        .success()
+        .stdout(format!("{}\n", web_dir.to_string_lossy()));
+
+    hop()?
+        .arg("--no-local")
+        .arg("-j")
+        .arg("web")
+        .assert()
+        .success()
+        .stdout(format!("{}\n", global_web.to_string_lossy()));
+
+    hop()?
+        .arg("--no-local")
+        .arg("-l")
+        .assert()
+        .success()
+        .stdout("web\n");
+
+    //A .hop directory that is really the personal hop home is not read again as a local one
+    let linked_home = working_dir.path().join("linked");
+    nixfs::symlink(project.join(".hop"), &linked_home)?;
+
+    hop()?
+        .env("HOP_HOME", linked_home.as_os_str())
+        .current_dir(&web_dir)
+        .arg("-t")
+        .assert()
+        .success()
+        .stdout(format!("api {} {}\nweb {} {}\n",
+            Yellow.paint("->"), linked_home.join("services/api").to_string_lossy(),
+            Yellow.paint("->"), linked_home.join("web").to_string_lossy()));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn home_relative_targets() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let alice_home = working_dir.path().join("alice");
+    let bob_home = working_dir.path().join("bob");
+
+    fs::create_dir_all(config_home.join("hop"))?;
+    fs::create_dir_all(alice_home.join("code"))?;
+    fs::create_dir_all(bob_home.join("code"))?;
+
+    fs::write(config_home.join("hop").join("config.toml"), "targets = \"home\"\n")?;
+
+    let hop = |home: &Path| -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("HOME", home.as_os_str())
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    hop(&alice_home)?
+        .arg("-m")
+        .arg("code")
+        .arg(alice_home.join("code").as_os_str())
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_link(hop_home.join("code"))?, Path::new("~/code"));
+
+    //The same hop home works for a different home directory
+    hop(&bob_home)?
+        .arg("-j")
+        .arg("code")
+        .assert()
+        .success()
+        .stdout(format!("{}\n", bob_home.join("code").to_string_lossy()));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn export_links() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let new_hop_home = working_dir.path().join("newhophome");
+    let target_dir = working_dir.path().join("some dir, with 'quotes'");
+
+    fs::create_dir_all(&hop_home)?;
+    fs::create_dir_all(&target_dir)?;
+
+    nixfs::symlink(&target_dir, hop_home.join("code"))?;
+
+    let hop = |hop_home: &Path| -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    let target = target_dir.to_string_lossy();
+
+    hop(&hop_home)?
+        .arg("export")
+        .assert()
+        .success()
+        .stdout(format!("[\n  {{\n    \"name\": \"code\",\n    \"target\": \"{}\",\n    \"layer\": \"personal\"\n  }}\n]\n", target));
+
+    hop(&hop_home)?
+        .arg("export")
+        .arg("--format")
+        .arg("toml")
+        .assert()
+        .success()
+        .stdout(format!("[[links]]\nname = \"code\"\ntarget = \"{}\"\nlayer = \"personal\"\n", target));
+
+    hop(&hop_home)?
+        .arg("export")
+        .arg("--format")
+        .arg("csv")
+        .assert()
+        .success()
+        .stdout(format!("name,target,layer\ncode,\"{}\",personal\n", target));
+
+    let script = working_dir.path().join("marks.sh");
+
+    hop(&hop_home)?
+        .arg("export")
+        .arg("--format")
+        .arg("sh")
+        .arg("--output")
+        .arg(script.as_os_str())
+        .assert()
+        .success()
+        .stdout(format!("Exported 1 link to {}\n", script.to_string_lossy()));
+
+    //The script marks the links in another hop home and does nothing when run again
+    let hop_dir = assert_cmd::cargo::cargo_bin("hop").parent().expect("expected hop directory").to_path_buf();
+    let path = std::env::join_paths(std::iter::once(hop_dir).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default())))?;
+
+    for _ in 0..2 {
+        Command::new("sh")
+            .arg(script.as_os_str())
+            .env("PATH", &path)
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", new_hop_home.as_os_str())
+            .env_remove("HOP_OPTS")
+            .assert()
+            .success();
+    }
+
+    assert_eq!(fs::read_link(new_hop_home.join("code"))?, target_dir);
+
+    //A target that isn't valid UTF-8 can't be written to text without pointing somewhere else
+    let bad_dir = working_dir.path().join(OsStr::from_bytes(b"b\xffad"));
+    fs::create_dir_all(&bad_dir)?;
+    nixfs::symlink(&bad_dir, hop_home.join("bad"))?;
+
+    hop(&hop_home)?
+        .arg("export")
+        .assert()
+        .failure()
+        .stdout(format!("{}\n", Yellow.paint("Could not export links")))
+        .stderr(predicates::str::contains("`bad` can't be exported as its name or target is not valid UTF-8."));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn import_links() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let new_hop_home = working_dir.path().join("newhophome");
+    let code_dir = working_dir.path().join("code");
+    let docs_dir = working_dir.path().join("docs");
+    let export_file = working_dir.path().join("marks.json");
+
+    fs::create_dir_all(&hop_home)?;
+    fs::create_dir_all(&new_hop_home)?;
+    fs::create_dir_all(&code_dir)?;
+    fs::create_dir_all(&docs_dir)?;
+
+    nixfs::symlink(&code_dir, hop_home.join("code"))?;
+    nixfs::symlink(&docs_dir, hop_home.join("docs"))?;
+    nixfs::symlink(&code_dir, new_hop_home.join("docs"))?;
+
+    let hop = |hop_home: &Path| -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    hop(&hop_home)?
+        .arg("export")
+        .arg("-o")
+        .arg(export_file.as_os_str())
+        .assert()
+        .success();
+
+    hop(&new_hop_home)?
+        .arg("import")
+        .arg(export_file.as_os_str())
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(format!(
+            "Create code {} {}\nSkip docs {} {}\nDry run: 1 to create, 0 to update, 0 to rename and 1 to skip. No links were changed.\n",
+            Yellow.paint("->"),
+            code_dir.to_string_lossy(),
+            Yellow.paint("->"),
+            docs_dir.to_string_lossy()
+        ));
+
+    assert!(!new_hop_home.join("code").exists());
+
+    hop(&new_hop_home)?
+        .arg("import")
+        .arg(export_file.as_os_str())
+        .arg("--on-conflict")
+        .arg("overwrite")
+        .assert()
+        .success()
+        .stdout(format!(
+            "Created code {} {}\nUpdated docs {} {}\nImported 2 links: 1 created, 1 updated, 0 renamed and 0 skipped\n",
+            Yellow.paint("->"),
+            code_dir.to_string_lossy(),
+            Yellow.paint("->"),
+            docs_dir.to_string_lossy()
+        ));
+
+    assert_eq!(fs::read_link(new_hop_home.join("code"))?, code_dir);
+    assert_eq!(fs::read_link(new_hop_home.join("docs"))?, docs_dir);
+
+    hop(&new_hop_home)?
+        .arg("import")
+        .arg(working_dir.path().join("missing.json").as_os_str())
+        .assert()
+        .failure();
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn import_from_bookmark_tools() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let home = working_dir.path().join("home");
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let code_dir = home.join("code");
+    let docs_dir = home.join("my docs");
+    let web_dir = home.join("web");
+
+    fs::create_dir_all(&code_dir)?;
+    fs::create_dir_all(&docs_dir)?;
+    fs::create_dir_all(&web_dir)?;
+
+    fs::write(home.join(".sdirs"), "export DIR_code=\"$HOME/code\"\n")?;
+    fs::write(home.join(".warprc"), "my docs:~/my docs\n")?;
+    fs::write(working_dir.path().join("apparixrc"), format!("j,web,{}\ne,portal,/tmp\nj,???,/tmp\n", web_dir.to_string_lossy()))?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("HOME", home.as_os_str())
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    hop()?
+        .arg("import")
+        .arg("--from")
+        .arg("bashmarks")
+        .assert()
+        .success();
+
+    hop()?
+        .arg("import")
+        .arg("--from")
+        .arg("wd")
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with("Renamed `my docs` to `my-docs` to follow hop's naming rules\n"));
+
+    hop()?
+        .arg("import")
+        .arg("--from")
+        .arg("apparix")
+        .arg(working_dir.path().join("apparixrc").as_os_str())
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("Skipping `???` as it has no characters that can be used in a link name"));
+
+    assert_eq!(fs::read_link(hop_home.join("code"))?, code_dir);
+    assert_eq!(fs::read_link(hop_home.join("my-docs"))?, docs_dir);
+    assert_eq!(fs::read_link(hop_home.join("web"))?, web_dir);
+    assert_eq!(fs::read_dir(&hop_home)?.filter(|entry| entry.as_ref().map_or(true, |e| e.file_name() != ".lock")).count(), 3);
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn suggest_links() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let data_home = working_dir.path().join("data");
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let code_dir = working_dir.path().join("code");
+    let docs_dir = working_dir.path().join("docs");
+
+    fs::create_dir_all(data_home.join("autojump"))?;
+    fs::create_dir_all(&code_dir)?;
+    fs::create_dir_all(&docs_dir)?;
+
+    fs::write(
+        data_home.join("autojump").join("autojump.txt"),
+        format!("12.5\t{}\n40.0\t{}\n", docs_dir.to_string_lossy(), code_dir.to_string_lossy())
+    )?;
+
+    Command::cargo_bin("hop")?
+        .env("XDG_DATA_HOME", data_home.as_os_str())
+        .env("XDG_CONFIG_HOME", config_home.as_os_str())
+        .env("XDG_STATE_HOME", config_home.as_os_str())
+        .env("HOP_HOME", hop_home.as_os_str())
+        .env_remove("HOP_OPTS")
+        .arg("suggest")
+        .arg("--from")
+        .arg("autojump")
+        .write_stdin("y\nn\n")
+        .assert()
+        .success()
+        .stdout(format!(
+            "Mark code -> {} ? [y]es, [n]o or [q]uit\nCreated link from code -> {}\nMark docs -> {} ? [y]es, [n]o or [q]uit\nCreated 1 link\n",
+            code_dir.to_string_lossy(),
+            code_dir.to_string_lossy(),
+            docs_dir.to_string_lossy()
+        ));
+
+    assert_eq!(fs::read_link(hop_home.join("code"))?, code_dir);
+    assert!(!hop_home.join("docs").exists());
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn sync_gtk_bookmarks() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let state_home = working_dir.path().join("state");
+    let hop_home = working_dir.path().join("hophome");
+    let music_dir = working_dir.path().join("My Music");
+    let code_dir = working_dir.path().join("code");
+    let bookmarks = config_home.join("gtk-3.0").join("bookmarks");
+
+    fs::create_dir_all(config_home.join("gtk-3.0"))?;
+    fs::create_dir_all(&hop_home)?;
+    fs::create_dir_all(&music_dir)?;
+    fs::create_dir_all(&code_dir)?;
+
+    let music_uri = format!("file://{}/My%20Music", working_dir.path().to_string_lossy());
+    fs::write(&bookmarks, format!("{} Music\nsftp://server/share Server\n", music_uri))?;
+    nixfs::symlink(&code_dir, hop_home.join("code"))?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", state_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS")
+            .arg("sync")
+            .arg("gtk");
+        Ok(cmd)
+    };
+
+    hop()?
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(format!(
+            "Changes to hop:\n  + Music {} {}\nChanges to GTK bookmarks:\n  + code {} {}\nApply these changes? [y/N]\n",
+            Yellow.paint("->"),
+            music_dir.to_string_lossy(),
+            Yellow.paint("->"),
+            code_dir.to_string_lossy()
+        ));
+
+    assert_eq!(fs::read_link(hop_home.join("Music"))?, music_dir);
+    assert_eq!(
+        fs::read_to_string(&bookmarks)?,
+        format!("{} Music\nsftp://server/share Server\nfile://{} code\n", music_uri, code_dir.to_string_lossy())
+    );
+    assert!(state_home.join("hop").join("sync-gtk.json").is_file());
+    assert!(!config_home.join("hop").join("sync-gtk.json").exists());
+
+    //A mark removed from hop is removed from the GTK bookmarks on the next sync
+    fs::remove_file(hop_home.join("Music"))?;
+
+    hop()?
+        .write_stdin("y\n")
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&bookmarks)?,
+        format!("sftp://server/share Server\nfile://{} code\n", code_dir.to_string_lossy())
+    );
+
+    hop()?
+        .assert()
+        .success()
+        .stdout("hop and GTK bookmarks are in sync\n");
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn sync_git_hop_homes() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let remote = working_dir.path().join("remote.git");
+    let work_dir = working_dir.path().join("work");
+    let docs_dir = working_dir.path().join("docs");
+
+    fs::create_dir_all(&work_dir)?;
+    fs::create_dir_all(&docs_dir)?;
+
+    std::process::Command::new("git")
+        .args(["init", "-q", "--bare"])
+        .arg(&remote)
+        .status()?;
+
+    //Two machines, each with their own hop home and config
+    let machine = |name: &str| -> Result<_, Box<dyn std::error::Error>> {
+        let config_home = working_dir.path().join(name).join("config");
+        let hop_home = working_dir.path().join(name).join("hophome");
+
+        fs::create_dir_all(config_home.join("hop"))?;
+        fs::write(
+            config_home.join("hop").join("config.toml"),
+            format!("git = true\ngit_remote = \"{}\"\nconfirm = false\n", remote.to_string_lossy())
+        )?;
+
+        Ok((config_home, hop_home))
+    };
+
+    let (laptop_config, laptop_home) = machine("laptop")?;
+    let (desktop_config, desktop_home) = machine("desktop")?;
+
+    let hop = |config_home: &Path, hop_home: &Path| -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    let laptop = || hop(&laptop_config, &laptop_home);
+    let desktop = || hop(&desktop_config, &desktop_home);
+
+    laptop()?.arg("-m").arg("work").arg(&work_dir).assert().success();
+    laptop()?.args(["-d", "work"]).assert().success();
+    laptop()?.arg("-m").arg("office").arg(&work_dir).assert().success();
+
+    let log = std::process::Command::new("git")
+        .arg("-C")
+        .arg(&laptop_home)
+        .args(["log", "--format=%s"])
+        .output()?;
+
+    assert_eq!(
+        String::from_utf8(log.stdout)?,
+        format!(
+            "Mark office -> {target}\nDelete work -> {target}\nMark work -> {target}\n",
+            target = work_dir.to_string_lossy()
+        )
+    );
+
+    //The lock file is only for the hops on this machine
+    let files = std::process::Command::new("git")
+        .arg("-C")
+        .arg(&laptop_home)
+        .args(["ls-files"])
+        .output()?;
+
+    assert_eq!(String::from_utf8(files.stdout)?, "office\n");
+
+    laptop()?.arg("sync").assert().success().stdout("Synced hop home with its git remote\n");
+    desktop()?.arg("sync").assert().success().stdout("Synced hop home with its git remote\n");
+
+    assert_eq!(fs::read_link(desktop_home.join("office"))?, work_dir);
+
+    //The same link is changed on both machines
+    laptop()?.args(["-d", "office"]).assert().success();
+    laptop()?.arg("-m").arg("office").arg(&docs_dir).assert().success();
+    laptop()?.arg("sync").assert().success();
+
+    desktop()?.arg("-m").arg("work").arg(&work_dir).assert().success();
+    desktop()?.args(["-d", "office"]).assert().success();
+
+    desktop()?
+        .arg("sync")
+        .assert()
+        .failure()
+        .stdout(format!(
+            "{}\n  office here deleted, remote {} {}\nNothing was synced. Run {} or {} to choose which side wins.\n",
+            Yellow.paint("These links were changed both here and in the remote:"),
+            Yellow.paint("->"),
+            docs_dir.to_string_lossy(),
+            Yellow.paint("hop sync --prefer local"),
+            Yellow.paint("hop sync --prefer remote")
+        ));
+
+    assert!(fs::symlink_metadata(desktop_home.join("office")).is_err());
+
+    desktop()?.args(["sync", "--prefer", "remote"]).assert().success();
+
+    assert_eq!(fs::read_link(desktop_home.join("office"))?, docs_dir);
+    assert_eq!(fs::read_link(desktop_home.join("work"))?, work_dir);
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn diff_and_merge_hop_homes() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let other_home = working_dir.path().join("otherhome");
+    let code_dir = working_dir.path().join("code");
+    let docs_dir = working_dir.path().join("docs");
+    let wiki_dir = working_dir.path().join("wiki");
+
+    for dir in [&config_home, &hop_home, &other_home, &code_dir, &docs_dir, &wiki_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    nixfs::symlink(&code_dir, hop_home.join("code"))?;
+    nixfs::symlink(&docs_dir, hop_home.join("docs"))?;
+    nixfs::symlink(&code_dir, other_home.join("src"))?;
+    nixfs::symlink(&wiki_dir, other_home.join("docs"))?;
+    nixfs::symlink(&wiki_dir, other_home.join("wiki"))?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    let other_name = other_home.to_string_lossy();
+
+    hop()?
+        .arg("diff")
+        .arg(&other_home)
+        .assert()
+        .code(1)
+        .stdout(format!(
+            "Only in {}:\n  wiki {} {}\nDifferent targets:\n  docs {} {} here, {} in {}\nDifferent names:\n  {} is `code` here, `src` in {}\n",
+            other_name,
+            Yellow.paint("->"),
+            wiki_dir.to_string_lossy(),
+            Yellow.paint("->"),
+            docs_dir.to_string_lossy(),
+            wiki_dir.to_string_lossy(),
+            other_name,
+            code_dir.to_string_lossy(),
+            other_name
+        ));
+
+    //Keep our target for docs and take their name for code
+    hop()?
+        .arg("merge")
+        .arg(&other_home)
+        .write_stdin("n\ny\n")
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_link(hop_home.join("wiki"))?, wiki_dir);
+    assert_eq!(fs::read_link(hop_home.join("docs"))?, docs_dir);
+    assert_eq!(fs::read_link(hop_home.join("src"))?, code_dir);
+    assert!(fs::symlink_metadata(hop_home.join("code")).is_err());
+
+    //An export file can be compared in the same way
+    let export_file = working_dir.path().join("other.json");
+
+    Command::cargo_bin("hop")?
+        .env("XDG_CONFIG_HOME", config_home.as_os_str())
+        .env("XDG_STATE_HOME", config_home.as_os_str())
+        .env("HOP_HOME", other_home.as_os_str())
+        .env_remove("HOP_OPTS")
+        .arg("export")
+        .arg("-o")
+        .arg(&export_file)
+        .assert()
+        .success();
+
+    hop()?
+        .arg("diff")
+        .arg(&export_file)
+        .assert()
+        .code(1)
+        .stdout(format!(
+            "Different targets:\n  docs {} {} here, {} in {}\n",
+            Yellow.paint("->"),
+            docs_dir.to_string_lossy(),
+            wiki_dir.to_string_lossy(),
+            export_file.to_string_lossy()
+        ));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn undo_delete_and_log() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let code_dir = working_dir.path().join("code");
+
+    for dir in [&config_home, &hop_home, &code_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    let code_target = code_dir.to_string_lossy();
+
+    hop()?.arg("-m").arg("code").arg(&code_dir).assert().success();
+    hop()?.arg("-d").arg("code").write_stdin("Y".as_bytes()).assert().success();
+
+    fs::symlink_metadata(hop_home.join("code")).expect_err("Found link code. Expected it to be deleted");
+
+    let log = hop()?.arg("log").assert().success();
+    let log_output = String::from_utf8(log.get_output().stdout.clone())?;
+    let log_lines: Vec<&str> = log_output.lines().collect();
+
+    assert_eq!(4, log_lines.len());
+    assert!(log_lines[0].ends_with(&format!("Delete code -> {}", code_target)));
+    assert_eq!(format!("  - code {} {}", Yellow.paint("->"), code_target), log_lines[1]);
+    assert!(log_lines[2].ends_with(&format!("Mark code -> {}", code_target)));
+    assert_eq!(format!("  + code {} {}", Yellow.paint("->"), code_target), log_lines[3]);
+
+    hop()?
+        .arg("undo")
+        .assert()
+        .success()
+        .stdout(format!("Undid Delete code -> {}\n", code_target));
+
+    assert_eq!(code_dir, fs::read_link(hop_home.join("code"))?);
+    assert_eq!("[]\n", fs::read_to_string(hop_home.join(".trash").join("index.json"))?);
+
+    hop()?
+        .arg("undo")
+        .arg("2")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Only 1 change can be undone."));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn journal_keeps_changes_to_every_hop_home() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_homes = [working_dir.path().join("hophome"), working_dir.path().join("otherhophome")];
+    let code_dir = working_dir.path().join("code");
+
+    for dir in [&config_home, &hop_homes[0], &hop_homes[1], &code_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    //Hops changing different hop homes at once share the journal
+    let marks =
+        (0..8)
+        .map(|index| {
+            std::process::Command::new(assert_cmd::cargo::cargo_bin("hop"))
+                .env("XDG_CONFIG_HOME", config_home.as_os_str())
+                .env("XDG_STATE_HOME", config_home.as_os_str())
+                .env("HOP_HOME", hop_homes[index % 2].as_os_str())
+                .env_remove("HOP_OPTS")
+                .arg("-m")
+                .arg(format!("code-{}", index))
+                .arg(&code_dir)
+                .stdout(std::process::Stdio::null())
+                .spawn()
+        })
+        .collect::<Result<Vec<_>, io::Error>>()?
+        .into_iter()
+        .map(|mut child| child.wait())
+        .collect::<Result<Vec<_>, io::Error>>()?;
+
+    assert!(marks.iter().all(|status| status.success()));
+
+    for hop_home in &hop_homes {
+        let log =
+            Command::cargo_bin("hop")?
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS")
+            .arg("log")
+            .assert()
+            .success();
+
+        let log_output = String::from_utf8(log.get_output().stdout.clone())?;
+        assert_eq!(4, log_output.lines().filter(|line| line.contains("Mark code-")).count());
+    }
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn delete_link_without_a_terminal() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let code_dir = working_dir.path().join("code");
+
+    for dir in [&config_home, &hop_home, &code_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    hop()?.arg("-m").arg("code").arg(&code_dir).assert().success();
+
+    //Without an answer on stdin the delete fails rather than guessing
+    hop()?
+        .arg("-d")
+        .arg("code")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("stdin is not a terminal. Pass --yes"));
+
+    hop()?
+        .arg("-d")
+        .arg("code")
+        .arg("--no-input")
+        .assert()
+        .success()
+        .stdout("Aborting delete of code\n");
+
+    assert_eq!(code_dir, fs::read_link(hop_home.join("code"))?);
+
+    hop()?
+        .arg("-d")
+        .arg("code")
+        .arg("--yes")
+        .assert()
+        .success();
+
+    fs::symlink_metadata(hop_home.join("code")).expect_err("Found link code. Expected it to be deleted");
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn delete_links_by_pattern() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let code_dir = working_dir.path().join("code");
+
+    for dir in [&config_home, &hop_home, &code_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    for name in ["tmp-1", "tmp-2", "old", "code"] {
+        hop()?.arg("-m").arg(name).arg(&code_dir).assert().success();
+    }
+
+    let code_target = code_dir.to_string_lossy();
+
+    hop()?
+        .arg("-d")
+        .arg("tmp-*")
+        .arg("old")
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(format!(
+            "  tmp-1 -> {target}\n  tmp-2 -> {target}\n  old -> {target}\n\
+             Are you sure you want to delete these 3 links? [y/N]\n\
+             Removed link tmp-1 {arrow} {target}\n\
+             Removed link tmp-2 {arrow} {target}\n\
+             Removed link old {arrow} {target}\n",
+            target = code_target,
+            arrow = Yellow.paint("->")
+        ));
+
+    let remaining: Vec<_> =
+        fs::read_dir(&hop_home)?
+        .map(|entry| entry.map(|e| e.file_name()))
+        .collect::<Result<_, _>>()?;
+
+    assert!(remaining.iter().all(|name| name == "code" || name == ".trash" || name == ".lock"));
+
+    //One undo brings back every link deleted together
+    hop()?.arg("undo").assert().success().stdout("Undid Delete 3 links\n");
+    assert_eq!(code_dir, fs::read_link(hop_home.join("tmp-2"))?);
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn batch_is_applied_in_full_or_rolled_back() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let code_dir = working_dir.path().join("code");
+    let docs_dir = working_dir.path().join("docs");
+
+    for dir in [&config_home, &hop_home, &code_dir, &docs_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    hop()?.arg("-m").arg("old").arg(&code_dir).assert().success();
+
+    let code_target = code_dir.to_string_lossy();
+    let docs_target = docs_dir.to_string_lossy();
+
+    let batch = format!(
+        "# Set up the project marks\n\
+         mark code {code}\n\
+         {{\"op\": \"rename\", \"name\": \"old\", \"new_name\": \"older\"}}\n\
+         retarget code {docs}\n",
+        code = code_target,
+        docs = docs_target
+    );
+
+    hop()?
+        .arg("batch")
+        .write_stdin(batch)
+        .assert()
+        .success()
+        .stdout(format!(
+            "1  mark code -> {code}      applied\n\
+             2  rename old to older{padding}applied\n\
+             3  retarget code -> {docs}  applied\n\
+             Applied 3 operations\n",
+            code = code_target,
+            docs = docs_target,
+            padding = " ".repeat(docs_target.len())
+        ));
+
+    assert_eq!(docs_dir, fs::read_link(hop_home.join("code"))?);
+    assert_eq!(code_dir, fs::read_link(hop_home.join("older"))?);
+
+    //A file in the hop home isn't a link, so marking over it passes the checks and then fails
+    fs::write(hop_home.join("notes"), "")?;
+
+    hop()?
+        .arg("batch")
+        .write_stdin(format!("delete older\nmark notes {}\nmark docs {}\n", code_target, docs_target))
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("1  delete older"))
+        .stdout(predicates::str::contains("rolled back"))
+        .stdout(predicates::str::contains("failed: A link named `notes` already exists. Aborting batch."))
+        .stdout(predicates::str::contains("not applied"));
+
+    assert_eq!(code_dir, fs::read_link(hop_home.join("older"))?);
+    fs::symlink_metadata(hop_home.join("docs")).expect_err("Found link docs. Expected the batch to be rolled back");
+
+    hop()?
+        .arg("batch")
+        .write_stdin("mark\n")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Could not read line 1 of the batch: expected `mark NAME TARGET`"));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn mark_working_directory_with_inferred_name() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let project_dir = working_dir.path().join("My Project");
+    let other_project_dir = working_dir.path().join("other").join("My Project");
+
+    for dir in [&config_home, &hop_home, &project_dir, &other_project_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    hop()?
+        .arg("-m")
+        .current_dir(&project_dir)
+        .assert()
+        .success()
+        .stdout(format!("Created link from My-Project {} {}\n", Yellow.paint("->"), project_dir.to_string_lossy()));
+
+    //The name is taken, so the next directory with the same name gets a suffix
+    hop()?
+        .arg("-m")
+        .current_dir(&other_project_dir)
+        .assert()
+        .success()
+        .stdout(format!("Created link from My-Project-2 {} {}\n", Yellow.paint("->"), other_project_dir.to_string_lossy()));
+
+    hop()?.arg("-m").arg("proj").current_dir(&project_dir).assert().success();
+
+    assert_eq!(project_dir, fs::read_link(hop_home.join("My-Project"))?);
+    assert_eq!(other_project_dir, fs::read_link(hop_home.join("My-Project-2"))?);
+    assert_eq!(project_dir, fs::read_link(hop_home.join("proj"))?);
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn mark_git_root() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let repo_dir = working_dir.path().join("repo");
+    let repo_sub_dir = repo_dir.join("src").join("algebra");
+    let submodule_dir = repo_dir.join("vendor").join("lib");
+    let submodule_sub_dir = submodule_dir.join("docs");
+    let plain_dir = working_dir.path().join("plain");
+
+    for dir in [&config_home, &hop_home, &repo_dir.join(".git"), &repo_sub_dir, &submodule_sub_dir, &plain_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(submodule_dir.join(".git"), "gitdir: ../../.git/modules/lib\n")?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    hop()?.arg("-m").arg("--git-root").current_dir(&repo_sub_dir).assert().success();
+    hop()?.arg("-m").arg("lib").arg(&submodule_sub_dir).arg("--git-root").assert().success();
+
+    assert_eq!(repo_dir, fs::read_link(hop_home.join("repo"))?);
+    assert_eq!(submodule_dir, fs::read_link(hop_home.join("lib"))?);
+
+    //The temporary directory could be inside a repository, so the plain directory is only checked when it isn't
+    if !working_dir.path().ancestors().any(|dir| dir.join(".git").exists()) {
+        hop()?
+            .arg("-m")
+            .arg("plain")
+            .arg(&plain_dir)
+            .arg("--git-root")
+            .assert()
+            .success()
+            .stderr(predicates::str::contains(format!("`{}` is not in a git repository.", plain_dir.to_string_lossy())));
+    }
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn scan_marks_repositories() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let code_dir = working_dir.path().join("code");
+    let repo_dir = code_dir.join("hop");
+    let nested_repo_dir = code_dir.join("work").join("api");
+    let project_dir = code_dir.join("rusty");
+    let deep_repo_dir = code_dir.join("a").join("b").join("c").join("deep");
+
+    for dir in [&config_home, &hop_home, &nested_repo_dir, &project_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    for repo in [&repo_dir, &repo_dir.join("vendor"), &deep_repo_dir] {
+        fs::create_dir_all(repo.join(".git"))?;
+    }
+
+    fs::write(nested_repo_dir.join(".git"), "gitdir: ../../.git/worktrees/api\n")?;
+    fs::write(project_dir.join("Cargo.toml"), "")?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    hop()?.arg("-m").arg("hop").arg(&nested_repo_dir).assert().success();
+
+    hop()?
+        .arg("scan")
+        .arg(&code_dir)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(format!("hop-2 {} {}\n", Yellow.paint("->"), repo_dir.to_string_lossy()));
+
+    hop()?
+        .arg("scan")
+        .arg(&code_dir)
+        .arg("--projects")
+        .arg("--tag")
+        .arg("w")
+        .arg("--yes")
+        .assert()
+        .success();
+
+    assert_eq!(repo_dir, fs::read_link(hop_home.join("w-hop"))?);
+    assert_eq!(project_dir, fs::read_link(hop_home.join("w-rusty"))?);
+    fs::symlink_metadata(hop_home.join("w-vendor")).expect_err("Found link w-vendor. Expected nested repositories to be skipped");
+    fs::symlink_metadata(hop_home.join("w-deep")).expect_err("Found link w-deep. Expected it to be deeper than the scan");
+
+    hop()?
+        .arg("scan")
+        .arg(&code_dir)
+        .arg("--depth")
+        .arg("4")
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stdout(format!(
+            "deep {} {}\nCreate 1 link? [y/N]\nNo links were created\n",
+            Yellow.paint("->"),
+            deep_repo_dir.to_string_lossy()
+        ));
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn restore_deleted_link_from_trash() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let code_dir = working_dir.path().join("code");
+
+    for dir in [&config_home, &hop_home, &code_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    nixfs::symlink(&code_dir, hop_home.join("code"))?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    let code_target = code_dir.to_string_lossy();
+
+    hop()?.arg("-d").arg("code").write_stdin("Y".as_bytes()).assert().success();
+
+    fs::symlink_metadata(hop_home.join("code")).expect_err("Found link code. Expected it to be deleted");
+
+    hop()?
+        .arg("trash")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with(format!("code {} {} (deleted ", Yellow.paint("->"), code_target)));
+
+    //A link with the same name is never replaced, even one whose target is gone
+    let missing_dir = working_dir.path().join("missing");
+    nixfs::symlink(&missing_dir, hop_home.join("code"))?;
+
+    hop()?
+        .arg("trash")
+        .arg("restore")
+        .arg("code")
+        .assert()
+        .stderr(predicates::str::contains("A link named `code` already exists. Aborting restore."));
+
+    assert_eq!(missing_dir, fs::read_link(hop_home.join("code"))?);
+    fs::remove_file(hop_home.join("code"))?;
+
+    hop()?
+        .arg("trash")
+        .arg("restore")
+        .arg("code")
+        .assert()
+        .success()
+        .stdout(format!("Restored link code {} {}\n", Yellow.paint("->"), code_target));
+
+    assert_eq!(code_dir, fs::read_link(hop_home.join("code"))?);
+
+    hop()?.arg("-d").arg("code").write_stdin("Y".as_bytes()).assert().success();
+
+    hop()?
+        .arg("trash")
+        .arg("empty")
+        .assert()
+        .success()
+        .stdout("Removed 1 link from the trash\n");
+
+    hop()?
+        .arg("trash")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout("The trash is empty\n");
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_and_restore_hop_home() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let code_dir = working_dir.path().join("code");
+    let docs_dir = working_dir.path().join("docs");
+
+    for dir in [&config_home, &hop_home, &code_dir, &docs_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    //A name that isn't valid UTF-8 is restored as it was
+    let code_link = OsStr::from_bytes(b"caf\xe9");
+    nixfs::symlink(&code_dir, hop_home.join(code_link))?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    let created = hop()?.arg("snapshot").arg("create").arg("tidy").assert().success();
+    let created_output = String::from_utf8(created.get_output().stdout.clone())?;
+    let id = created_output
+        .strip_prefix("Created snapshot ")
+        .and_then(|rest| rest.strip_suffix(" of 1 link\n"))
+        .expect(&format!("Unexpected output: {}", created_output))
+        .to_string();
+
+    hop()?.arg("-m").arg("docs").arg(&docs_dir).assert().success();
+
+    hop()?
+        .arg("snapshot")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with(Yellow.paint(id.as_str()).to_string()))
+        .stdout(predicates::str::ends_with(" 1 link tidy\n"));
+
+    hop()?
+        .arg("snapshot")
+        .arg("restore")
+        .arg(&id)
+        .write_stdin("y".as_bytes())
+        .assert()
+        .success()
+        .stdout(format!(
+            "Changes to hop:\n  - docs {} {}\nRestore snapshot {}? [y/N]\nRestored snapshot {}\n",
+            Yellow.paint("->"),
+            docs_dir.to_string_lossy(),
+            id,
+            id
+        ));
+
+    fs::symlink_metadata(hop_home.join("docs")).expect_err("Found link docs. Expected it to be removed");
+    assert_eq!(code_dir, fs::read_link(hop_home.join(code_link))?);
+
+    working_dir.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn locked_hop_home() -> Result<(), Box<dyn std::error::Error>> {
+
+    let working_dir = tempdir()?;
+    let config_home = working_dir.path().join("config");
+    let hop_home = working_dir.path().join("hophome");
+    let code_dir = working_dir.path().join("code");
+
+    for dir in [&config_home.join("hop"), &hop_home, &code_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(config_home.join("hop").join("config.toml"), "lock_timeout = 0\n")?;
+
+    let hop = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("hop")?;
+        cmd
+            .env("XDG_CONFIG_HOME", config_home.as_os_str())
+            .env("XDG_STATE_HOME", config_home.as_os_str())
+            .env("HOP_HOME", hop_home.as_os_str())
+            .env_remove("HOP_OPTS");
+        Ok(cmd)
+    };
+
+    //Hold the lock as another hop would
+    let lock_path = hop_home.join(".lock");
+    fs::write(&lock_path, "4321\n")?;
+    let lock = fs::File::open(&lock_path)?;
+    lock.lock()?;
+
+    hop()?
+        .arg("-m")
+        .arg("code")
+        .arg(&code_dir)
+        .assert()
+        .stderr(predicates::str::contains("The hop home is locked by pid 4321. Try again once it has finished."));
+
+    fs::symlink_metadata(hop_home.join("code")).expect_err("Found link code. Expected it not to be created");
+
+    drop(lock);
+
+    //Only one of the hops marking the same name at once creates the link
+    let marks =
+        (0..8)
+        .map(|_| {
+            std::process::Command::new(assert_cmd::cargo::cargo_bin("hop"))
+                .env("XDG_CONFIG_HOME", config_home.as_os_str())
+                .env("XDG_STATE_HOME", config_home.as_os_str())
+                .env("HOP_HOME", hop_home.as_os_str())
+                .env_remove("HOP_OPTS")
+                .arg("-m")
+                .arg("code")
+                .arg(&code_dir)
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+        })
+        .collect::<Result<Vec<_>, io::Error>>()?
+        .into_iter()
+        .map(|child| child.wait_with_output())
+        .collect::<Result<Vec<_>, io::Error>>()?;
+
+    let created =
+        marks
+        .iter()
+        .filter(|output| String::from_utf8_lossy(&output.stdout).starts_with("Created link from code"))
+        .count();
+
+    assert_eq!(1, created);
+    assert_eq!(code_dir, fs::read_link(hop_home.join("code"))?);
+
+    working_dir.close()?;
+
+    Ok(())
+}